@@ -1,38 +1,43 @@
+use crabiec61850::network::{
+    clock::Clock,
+    publisher::scheduler::{BusyWait, ClockNanosleep, Hybrid, LatenessStats, Nanosleep, Scheduler},
+};
 use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
-use libc::nanosleep;
 
-fn nanosleep_benchmark(c: &mut Criterion) {
-    let mut group = c.benchmark_group("nanosleep");
+/// One SV frame at 4800 frames/s.
+const FRAME_PERIOD: u64 = 208_333;
 
-    let mut rmtp = libc::timespec {
-        tv_sec: 0,
-        tv_nsec: 0,
-    };
+fn scheduler_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("scheduler");
+    let clock = Clock::Monotonic;
 
-    let time_between_packets = 208_333;
-    let time_next_perf = 16;
-    let time_to_bytes_perf = 909;
-    let time_send_perf = 1_500;
-    let precision_diff_time = 55_750;
-    let sv_time_sleep_estimation: i64 = time_between_packets
-        - time_next_perf
-        - time_to_bytes_perf
-        - time_send_perf
-        - precision_diff_time;
+    let schedulers: Vec<(&str, Box<dyn Scheduler>)> = vec![
+        ("busy_wait", Box::new(BusyWait { clock })),
+        ("nanosleep", Box::new(Nanosleep { clock })),
+        ("clock_nanosleep", Box::new(ClockNanosleep { clock })),
+        (
+            "hybrid",
+            Box::new(Hybrid {
+                clock,
+                wake_ahead: 60_000,
+            }),
+        ),
+    ];
 
-    for i in [100_000, 200_000, 208_333, sv_time_sleep_estimation] {
-        rmtp.tv_nsec = i;
-        group.bench_function(BenchmarkId::new("nanosleep", i), |b| {
+    for (name, mut scheduler) in schedulers {
+        let mut lateness = LatenessStats::default();
+        group.bench_function(BenchmarkId::new(name, FRAME_PERIOD), |b| {
             b.iter(|| {
-                unsafe {
-                    std::hint::black_box(nanosleep(&rmtp, core::ptr::null_mut()));
-                };
+                let deadline = clock.now() + FRAME_PERIOD;
+                let late = scheduler.wait_until(std::hint::black_box(deadline));
+                lateness.record(late.unwrap());
             });
         });
+        println!("{}: {}", name, lateness);
     }
 
     group.finish();
 }
 
-criterion_group!(benches, nanosleep_benchmark);
+criterion_group!(benches, scheduler_benchmark);
 criterion_main!(benches);
@@ -21,6 +21,7 @@ fn default_packet() -> Packet {
         ethernet: Ethernet {
             dst_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
             src_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            vlan: None,
             ether_type: [0x88, 0xba],
         },
         sampled_value: SampledValue::from_bytes(sv_bytes),
@@ -22,6 +22,7 @@ fn default_packet() -> Packet {
         ethernet: Ethernet {
             dst_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
             src_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+            vlan: None,
             ether_type: [0x88, 0xba],
         },
         sampled_value: SampledValue::from_bytes(sv_bytes),
@@ -35,7 +36,7 @@ fn socket_benchmark(c: &mut Criterion) {
     let socket = RawSocket::new("lo".to_string(), 0x88ba_u16);
     group.bench_function("socket.send", |b| {
         b.iter(|| {
-            std::hint::black_box(socket.send(&packet_bytes));
+            socket.send(std::hint::black_box(&packet_bytes));
         });
     });
 
@@ -1,3 +1,4 @@
 pub mod network;
 pub mod protocols;
+pub mod random;
 pub mod standards;
@@ -0,0 +1,131 @@
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::task::{Context, Poll, ready};
+
+use tokio::io::unix::AsyncFd;
+
+use crate::network::socket::RawSocket;
+
+/// `RawSocket` registered with the tokio reactor.
+///
+/// The underlying socket is switched to non-blocking mode, so every call
+/// waits on readiness instead of blocking the runtime thread.
+pub struct AsyncRawSocket {
+    inner: AsyncFd<RawSocket>,
+}
+
+impl AsyncRawSocket {
+    pub fn new(iface: String, protocol: u16) -> io::Result<Self> {
+        Self::from_socket(RawSocket::try_new(iface, protocol)?)
+    }
+
+    pub fn from_socket(socket: RawSocket) -> io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(AsyncRawSocket {
+            inner: AsyncFd::new(socket)?,
+        })
+    }
+
+    pub fn get_ref(&self) -> &RawSocket {
+        self.inner.get_ref()
+    }
+
+    pub fn into_inner(self) -> RawSocket {
+        self.inner.into_inner()
+    }
+
+    pub async fn recv(&self) -> io::Result<Vec<u8>> {
+        loop {
+            let mut guard = self.inner.readable().await?;
+            match guard.try_io(|inner| inner.get_ref().try_recv()) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    pub fn poll_recv(&self, cx: &mut Context<'_>) -> Poll<io::Result<Vec<u8>>> {
+        loop {
+            let mut guard = ready!(self.inner.poll_read_ready(cx))?;
+            match guard.try_io(|inner| inner.get_ref().try_recv()) {
+                Ok(result) => return Poll::Ready(result),
+                Err(_would_block) => continue,
+            }
+        }
+    }
+
+    pub async fn send(&self, data: &[u8]) -> io::Result<()> {
+        loop {
+            let mut guard = self.inner.writable().await?;
+            match guard.try_io(|inner| inner.get_ref().try_send(data)) {
+                Ok(result) => return result,
+                Err(_would_block) => continue,
+            }
+        }
+    }
+}
+
+impl AsRawFd for AsyncRawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.inner.as_raw_fd()
+    }
+}
+
+impl AsFd for AsyncRawSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.inner.get_ref().as_fd()
+    }
+}
+
+/// Sampled Values socket on the loopback interface for tests, `None` without
+/// CAP_NET_RAW since there is nothing to test then.
+#[cfg(test)]
+pub(crate) fn loopback() -> Option<AsyncRawSocket> {
+    match AsyncRawSocket::new(
+        "lo".to_string(),
+        crate::network::eth_types::EthTypes::SampledValues as u16,
+    ) {
+        Ok(socket) => Some(socket),
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => None,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use crate::network::packet::Packet;
+    use crate::network::publisher::config::PublisherConfig;
+
+    #[tokio::test]
+    async fn send_and_receive_on_loopback() {
+        let (Some(sender), Some(receiver)) = (loopback(), loopback()) else {
+            return;
+        };
+        let frame = PublisherConfig::builder()
+            .app_id(0x4e01)
+            .build()
+            .unwrap()
+            .packet()
+            .to_bytes();
+        sender.send(&frame).await.unwrap();
+        // Other tests publish on lo as well.
+        let received = timeout(Duration::from_secs(2), async {
+            loop {
+                let bytes = receiver.recv().await.unwrap();
+                if Packet::try_from_bytes(&bytes)
+                    .is_ok_and(|packet| packet.sampled_value.app_id == 0x4e01)
+                {
+                    return bytes;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(received, frame);
+    }
+}
@@ -0,0 +1,151 @@
+pub mod pcap;
+pub mod pcapng;
+
+use std::fs::File;
+use std::io::{self, BufReader, Chain, Cursor, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::network::capture::pcap::{MAGIC_MICROS, MAGIC_NANOS, PcapReader};
+use crate::network::capture::pcapng::{PcapNgReader, SECTION_HEADER_BLOCK};
+use crate::network::transport::{Frame, FrameTransport};
+
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const SNAPLEN: u32 = 65535;
+/// Largest pcap record accepted, libpcap's maximum snapshot length.
+pub(crate) const MAX_RECORD_LEN: usize = 262_144;
+/// Largest pcapng block accepted, as in libpcap.
+pub(crate) const MAX_BLOCK_LEN: usize = 16 * 1024 * 1024;
+
+type Peeked<R> = Chain<Cursor<[u8; 4]>, R>;
+
+/// Capture file reader that detects pcap or pcapng from the magic number.
+pub enum CaptureReader<R: Read> {
+    Pcap(PcapReader<Peeked<R>>),
+    PcapNg(PcapNgReader<Peeked<R>>),
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        let peeked = Cursor::new(magic).chain(reader);
+
+        let little = LittleEndian::read_u32(&magic);
+        let big = BigEndian::read_u32(&magic);
+        if little == SECTION_HEADER_BLOCK {
+            Ok(CaptureReader::PcapNg(PcapNgReader::new(peeked)?))
+        } else if [little, big]
+            .iter()
+            .any(|magic| *magic == MAGIC_MICROS || *magic == MAGIC_NANOS)
+        {
+            Ok(CaptureReader::Pcap(PcapReader::new(peeked)?))
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Unknown capture file format",
+            ))
+        }
+    }
+
+    /// Returns the next frame, or `None` at the end of the file.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        match self {
+            CaptureReader::Pcap(reader) => reader.next_frame(),
+            CaptureReader::PcapNg(reader) => reader.next_frame(),
+        }
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+impl<R: Read> FrameTransport for CaptureReader<R> {
+    fn send_frame(&mut self, _data: &[u8], _timestamp: Option<SystemTime>) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot send frames to a capture reader",
+        ))
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        self.next_frame()?
+            .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::capture::{pcap::PcapWriter, pcapng::PcapNgWriter};
+    use crate::network::packet::Packet;
+
+    const SV_FRAME: &[u8] = &[
+        0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // MACs
+        0x81, 0x00, 0x80, 0x05, // VLAN
+        0x88, 0xba, // Ethertype
+        0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
+        0x60, 0x5c, // PDU
+        0x80, 0x01, 0x01, // number of ASDU
+        0xa2, 0x57, // sequence of ASDU
+        0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04, 0x00,
+        0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00,
+        0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfc,
+        0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00, 0x00, 0x20, 0x00, // ASDU
+    ];
+
+    #[test]
+    fn detect_pcap() {
+        let packet = Packet::from_bytes(SV_FRAME);
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::new(10, 1);
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        writer.write_packet(&packet, timestamp).unwrap();
+        let bytes = writer.into_inner();
+
+        let mut reader = CaptureReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader, CaptureReader::Pcap(_)));
+        let frame = reader.recv_frame().unwrap();
+        assert_eq!(frame.timestamp, Some(timestamp));
+        assert_eq!(Packet::from_bytes(&frame.data), packet);
+    }
+
+    #[test]
+    fn detect_pcapng() {
+        let packet = Packet::from_bytes(SV_FRAME);
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::new(10, 1);
+        let mut writer = PcapNgWriter::new(vec![]).unwrap();
+        writer.write_packet(&packet, timestamp).unwrap();
+        let bytes = writer.into_inner();
+
+        let mut reader = CaptureReader::new(bytes.as_slice()).unwrap();
+        assert!(matches!(reader, CaptureReader::PcapNg(_)));
+        let frame = reader.next().unwrap().unwrap();
+        assert_eq!(frame.data, SV_FRAME);
+        assert_eq!(Packet::from_bytes(&frame.data), packet);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn reject_unknown_format() {
+        assert!(CaptureReader::new([0u8; 32].as_slice()).is_err());
+    }
+}
@@ -0,0 +1,270 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::network::capture::{LINKTYPE_ETHERNET, MAX_RECORD_LEN, SNAPLEN};
+use crate::network::packet::Packet;
+use crate::network::transport::{Frame, FrameTransport};
+
+pub(crate) const MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+pub(crate) const MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+
+/// Reader for classic libpcap capture files, in microsecond or nanosecond
+/// resolution and either byte order.
+pub struct PcapReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    nanos: bool,
+    pub link_type: u32,
+    pub snaplen: u32,
+}
+
+impl PcapReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        reader.read_exact(&mut header)?;
+
+        let (big_endian, nanos) = match (
+            LittleEndian::read_u32(&header[0..4]),
+            BigEndian::read_u32(&header[0..4]),
+        ) {
+            (MAGIC_MICROS, _) => (false, false),
+            (MAGIC_NANOS, _) => (false, true),
+            (_, MAGIC_MICROS) => (true, false),
+            (_, MAGIC_NANOS) => (true, true),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Not a pcap file: unknown magic number",
+                ));
+            }
+        };
+
+        let mut pcap = PcapReader {
+            reader,
+            big_endian,
+            nanos,
+            link_type: 0,
+            snaplen: 0,
+        };
+        pcap.snaplen = pcap.read_u32(&header[16..20]);
+        pcap.link_type = pcap.read_u32(&header[20..24]);
+        if pcap.link_type != LINKTYPE_ETHERNET {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unsupported pcap link type {}", pcap.link_type),
+            ));
+        }
+        Ok(pcap)
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        if self.big_endian {
+            BigEndian::read_u32(bytes)
+        } else {
+            LittleEndian::read_u32(bytes)
+        }
+    }
+
+    /// Returns the next record, or `None` at the end of the file.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; 16];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let seconds = self.read_u32(&header[0..4]) as u64;
+        let fraction = self.read_u32(&header[4..8]) as u64;
+        let fraction = if self.nanos {
+            Duration::from_nanos(fraction)
+        } else {
+            Duration::from_micros(fraction)
+        };
+        let captured = self.read_u32(&header[8..12]) as usize;
+        if captured > MAX_RECORD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid pcap record length {}", captured),
+            ));
+        }
+
+        let mut data = vec![0u8; captured];
+        self.reader.read_exact(&mut data)?;
+
+        Ok(Some(Frame {
+            data,
+            timestamp: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds) + fraction),
+        }))
+    }
+}
+
+impl<R: Read> Iterator for PcapReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+impl<R: Read> FrameTransport for PcapReader<R> {
+    fn send_frame(&mut self, _data: &[u8], _timestamp: Option<SystemTime>) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot send frames to a pcap reader",
+        ))
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        self.next_frame()?
+            .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer for classic libpcap capture files with Ethernet link type and
+/// nanosecond timestamps.
+pub struct PcapWriter<W: Write> {
+    writer: W,
+}
+
+impl PcapWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        let mut header = [0u8; 24];
+        LittleEndian::write_u32(&mut header[0..4], MAGIC_NANOS);
+        LittleEndian::write_u16(&mut header[4..6], 2);
+        LittleEndian::write_u16(&mut header[6..8], 4);
+        LittleEndian::write_u32(&mut header[16..20], SNAPLEN);
+        LittleEndian::write_u32(&mut header[20..24], LINKTYPE_ETHERNET);
+        writer.write_all(&header)?;
+        Ok(PcapWriter { writer })
+    }
+
+    pub fn write_frame(&mut self, data: &[u8], timestamp: SystemTime) -> io::Result<()> {
+        let since_epoch = timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut header = [0u8; 16];
+        LittleEndian::write_u32(&mut header[0..4], since_epoch.as_secs() as u32);
+        LittleEndian::write_u32(&mut header[4..8], since_epoch.subsec_nanos());
+        LittleEndian::write_u32(&mut header[8..12], data.len() as u32);
+        LittleEndian::write_u32(&mut header[12..16], data.len() as u32);
+        self.writer.write_all(&header)?;
+        self.writer.write_all(data)
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet, timestamp: SystemTime) -> io::Result<()> {
+        self.write_frame(&packet.to_bytes(), timestamp)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> FrameTransport for PcapWriter<W> {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        self.write_frame(data, timestamp.unwrap_or_else(SystemTime::now))
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot receive frames from a pcap writer",
+        ))
+    }
+
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read() {
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        writer.write_frame(&[0xaa; 60], timestamp).unwrap();
+        writer.send_frame(&[0xbb; 64], Some(timestamp)).unwrap();
+        let bytes = writer.into_inner();
+        assert_eq!(bytes.len(), 24 + 16 + 60 + 16 + 64);
+
+        let mut reader = PcapReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.link_type, LINKTYPE_ETHERNET);
+        let first = reader.recv_frame().unwrap();
+        assert_eq!(first.data, vec![0xaa; 60]);
+        assert_eq!(first.timestamp, Some(timestamp));
+        assert_eq!(reader.next().unwrap().unwrap().data, vec![0xbb; 64]);
+        assert!(reader.next().is_none());
+        assert_eq!(
+            reader.recv_frame().unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn read_big_endian() {
+        let mut bytes = vec![0u8; 24 + 16 + 2];
+        BigEndian::write_u32(&mut bytes[0..4], MAGIC_MICROS);
+        BigEndian::write_u32(&mut bytes[20..24], LINKTYPE_ETHERNET);
+        BigEndian::write_u32(&mut bytes[24..28], 5);
+        BigEndian::write_u32(&mut bytes[28..32], 7);
+        BigEndian::write_u32(&mut bytes[32..36], 2);
+        bytes[40] = 0x88;
+        bytes[41] = 0xba;
+
+        let frame = PcapReader::new(bytes.as_slice())
+            .unwrap()
+            .recv_frame()
+            .unwrap();
+        assert_eq!(frame.data, vec![0x88, 0xba]);
+        assert_eq!(
+            frame.timestamp,
+            Some(SystemTime::UNIX_EPOCH + Duration::new(5, 7_000))
+        );
+    }
+
+    #[test]
+    fn reject_oversized_record() {
+        let mut bytes = PcapWriter::new(vec![]).unwrap().into_inner();
+        let mut header = [0u8; 16];
+        LittleEndian::write_u32(&mut header[8..12], u32::MAX);
+        bytes.extend_from_slice(&header);
+        let mut reader = PcapReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(
+            reader.next_frame().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn reject_unknown_magic() {
+        let bytes = [0u8; 24];
+        assert!(PcapReader::new(bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::network::capture::{LINKTYPE_ETHERNET, MAX_BLOCK_LEN, SNAPLEN};
+use crate::network::packet::Packet;
+use crate::network::transport::{Frame, FrameTransport};
+
+pub(crate) const SECTION_HEADER_BLOCK: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const SIMPLE_PACKET_BLOCK: u32 = 3;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+
+const OPT_END: u16 = 0;
+const OPT_IF_NAME: u16 = 2;
+const OPT_IF_TSRESOL: u16 = 9;
+
+fn truncated_block() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Truncated pcapng block")
+}
+
+/// Interface described by an Interface Description Block.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Interface {
+    pub link_type: u16,
+    pub snaplen: u32,
+    pub name: Option<String>,
+    /// Raw `if_tsresol` value: `10^-n` seconds per unit, or `2^-n` when the
+    /// most significant bit is set. Defaults to microseconds.
+    pub ts_resolution: u8,
+}
+
+impl Interface {
+    fn timestamp(&self, units: u64) -> io::Result<SystemTime> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Unsupported pcapng timestamp resolution {:#x}",
+                    self.ts_resolution
+                ),
+            )
+        };
+        let exponent = (self.ts_resolution & 0x7f) as u32;
+        let nanos: u128 = if self.ts_resolution & 0x80 != 0 {
+            ((units as u128) * 1_000_000_000) >> exponent
+        } else if exponent <= 9 {
+            units as u128 * 10u128.pow(9 - exponent)
+        } else {
+            units as u128 / 10u128.checked_pow(exponent - 9).ok_or_else(invalid)?
+        };
+        let since_epoch = Duration::new(
+            (nanos / 1_000_000_000) as u64,
+            (nanos % 1_000_000_000) as u32,
+        );
+        SystemTime::UNIX_EPOCH
+            .checked_add(since_epoch)
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "pcapng timestamp out of range")
+            })
+    }
+}
+
+/// Reader for pcapng capture files. Packets captured on interfaces whose link
+/// type is not Ethernet are skipped.
+pub struct PcapNgReader<R: Read> {
+    reader: R,
+    big_endian: bool,
+    interfaces: Vec<Interface>,
+}
+
+impl PcapNgReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> PcapNgReader<R> {
+    pub fn new(reader: R) -> io::Result<Self> {
+        let mut pcapng = PcapNgReader {
+            reader,
+            big_endian: false,
+            interfaces: vec![],
+        };
+        match pcapng.read_block()? {
+            Some((SECTION_HEADER_BLOCK, _)) => Ok(pcapng),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not a pcapng file: missing section header block",
+            )),
+        }
+    }
+
+    /// Interfaces declared so far in the current section.
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+
+    fn read_u16(&self, bytes: &[u8]) -> u16 {
+        if self.big_endian {
+            BigEndian::read_u16(bytes)
+        } else {
+            LittleEndian::read_u16(bytes)
+        }
+    }
+
+    fn read_u32(&self, bytes: &[u8]) -> u32 {
+        if self.big_endian {
+            BigEndian::read_u32(bytes)
+        } else {
+            LittleEndian::read_u32(bytes)
+        }
+    }
+
+    /// Reads one block and returns its type and body, or `None` at the end
+    /// of the file. A section header switches the byte order and forgets the
+    /// interfaces of the previous section.
+    fn read_block(&mut self) -> io::Result<Option<(u32, Vec<u8>)>> {
+        let mut header = [0u8; 8];
+        match self.reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+
+        let mut body: Vec<u8> = vec![];
+        // The section header type is a palindrome, so it reads the same in
+        // either byte order; its body starts with the byte order magic.
+        if LittleEndian::read_u32(&header[0..4]) == SECTION_HEADER_BLOCK {
+            let mut magic = [0u8; 4];
+            self.reader.read_exact(&mut magic)?;
+            self.big_endian = if LittleEndian::read_u32(&magic) == BYTE_ORDER_MAGIC {
+                false
+            } else if BigEndian::read_u32(&magic) == BYTE_ORDER_MAGIC {
+                true
+            } else {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid pcapng byte order magic",
+                ));
+            };
+            self.interfaces.clear();
+            body.extend_from_slice(&magic);
+        }
+
+        let block_type = self.read_u32(&header[0..4]);
+        let total_length = self.read_u32(&header[4..8]) as usize;
+        if total_length < 12 || !total_length.is_multiple_of(4) || total_length > MAX_BLOCK_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid pcapng block length {}", total_length),
+            ));
+        }
+        let already_read = body.len();
+        body.resize(total_length - 12, 0);
+        self.reader.read_exact(&mut body[already_read..])?;
+        let mut trailer = [0u8; 4];
+        self.reader.read_exact(&mut trailer)?;
+
+        Ok(Some((block_type, body)))
+    }
+
+    fn parse_interface(&self, body: &[u8]) -> Interface {
+        let mut interface = Interface {
+            link_type: self.read_u16(&body[0..2]),
+            snaplen: self.read_u32(&body[4..8]),
+            name: None,
+            ts_resolution: 6,
+        };
+        let mut start = 8;
+        while start + 4 <= body.len() {
+            let code = self.read_u16(&body[start..start + 2]);
+            let length = self.read_u16(&body[start + 2..start + 4]) as usize;
+            let value = &body[start + 4..(start + 4 + length).min(body.len())];
+            match code {
+                OPT_END => break,
+                OPT_IF_NAME => interface.name = Some(String::from_utf8_lossy(value).into_owned()),
+                OPT_IF_TSRESOL if !value.is_empty() => interface.ts_resolution = value[0],
+                _ => {}
+            }
+            start += 4 + length.div_ceil(4) * 4;
+        }
+        interface
+    }
+
+    /// Returns the next Ethernet frame with the index of the interface it was
+    /// captured on, or `None` at the end of the file.
+    pub fn next_frame_with_interface(&mut self) -> io::Result<Option<(u32, Frame)>> {
+        while let Some((block_type, body)) = self.read_block()? {
+            match block_type {
+                INTERFACE_DESCRIPTION_BLOCK => {
+                    if body.len() < 8 {
+                        return Err(truncated_block());
+                    }
+                    let interface = self.parse_interface(&body);
+                    self.interfaces.push(interface);
+                }
+                ENHANCED_PACKET_BLOCK => {
+                    if body.len() < 20 {
+                        return Err(truncated_block());
+                    }
+                    let interface_id = self.read_u32(&body[0..4]);
+                    let interface =
+                        self.interfaces.get(interface_id as usize).ok_or_else(|| {
+                            io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                format!("Packet references unknown interface {}", interface_id),
+                            )
+                        })?;
+                    if interface.link_type as u32 != LINKTYPE_ETHERNET {
+                        continue;
+                    }
+                    let units = ((self.read_u32(&body[4..8]) as u64) << 32)
+                        | self.read_u32(&body[8..12]) as u64;
+                    let captured = self.read_u32(&body[12..16]) as usize;
+                    if body.len() < 20 + captured {
+                        return Err(truncated_block());
+                    }
+                    let frame = Frame {
+                        data: body[20..20 + captured].to_vec(),
+                        timestamp: Some(interface.timestamp(units)?),
+                    };
+                    return Ok(Some((interface_id, frame)));
+                }
+                SIMPLE_PACKET_BLOCK => {
+                    let Some(interface) = self.interfaces.first() else {
+                        continue;
+                    };
+                    if interface.link_type as u32 != LINKTYPE_ETHERNET {
+                        continue;
+                    }
+                    if body.len() < 4 {
+                        return Err(truncated_block());
+                    }
+                    let original = self.read_u32(&body[0..4]) as usize;
+                    let mut captured = original.min(body.len() - 4);
+                    if interface.snaplen > 0 {
+                        captured = captured.min(interface.snaplen as usize);
+                    }
+                    let frame = Frame {
+                        data: body[4..4 + captured].to_vec(),
+                        timestamp: None,
+                    };
+                    return Ok(Some((0, frame)));
+                }
+                _ => {}
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next Ethernet frame, or `None` at the end of the file.
+    pub fn next_frame(&mut self) -> io::Result<Option<Frame>> {
+        Ok(self.next_frame_with_interface()?.map(|(_, frame)| frame))
+    }
+}
+
+impl<R: Read> Iterator for PcapNgReader<R> {
+    type Item = io::Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_frame().transpose()
+    }
+}
+
+impl<R: Read> FrameTransport for PcapNgReader<R> {
+    fn send_frame(&mut self, _data: &[u8], _timestamp: Option<SystemTime>) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot send frames to a pcapng reader",
+        ))
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        self.next_frame()?
+            .ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writer for little-endian pcapng files. Every interface is Ethernet with
+/// nanosecond timestamp resolution.
+pub struct PcapNgWriter<W: Write> {
+    writer: W,
+    interfaces: u32,
+}
+
+impl PcapNgWriter<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    pub fn new(writer: W) -> io::Result<Self> {
+        let mut pcapng = PcapNgWriter {
+            writer,
+            interfaces: 0,
+        };
+        let mut body = vec![0u8; 16];
+        LittleEndian::write_u32(&mut body[0..4], BYTE_ORDER_MAGIC);
+        LittleEndian::write_u16(&mut body[4..6], 1);
+        LittleEndian::write_u16(&mut body[6..8], 0);
+        LittleEndian::write_i64(&mut body[8..16], -1);
+        pcapng.write_block(SECTION_HEADER_BLOCK, &body)?;
+        Ok(pcapng)
+    }
+
+    fn write_block(&mut self, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let padded = body.len().div_ceil(4) * 4;
+        let total_length = (padded + 12) as u32;
+        let mut bytes = vec![0u8; 8];
+        LittleEndian::write_u32(&mut bytes[0..4], block_type);
+        LittleEndian::write_u32(&mut bytes[4..8], total_length);
+        bytes.extend_from_slice(body);
+        bytes.resize(8 + padded, 0);
+        bytes.extend_from_slice(&total_length.to_le_bytes());
+        self.writer.write_all(&bytes)
+    }
+
+    fn option_to_bytes(code: u16, value: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0u8; 4];
+        LittleEndian::write_u16(&mut bytes[0..2], code);
+        LittleEndian::write_u16(&mut bytes[2..4], value.len() as u16);
+        bytes.extend_from_slice(value);
+        bytes.resize(4 + value.len().div_ceil(4) * 4, 0);
+        bytes
+    }
+
+    /// Writes an Interface Description Block and returns the interface index
+    /// to pass to `write_frame_on`.
+    pub fn add_interface(&mut self, name: Option<&str>) -> io::Result<u32> {
+        let mut body = vec![0u8; 8];
+        LittleEndian::write_u16(&mut body[0..2], LINKTYPE_ETHERNET as u16);
+        LittleEndian::write_u32(&mut body[4..8], SNAPLEN);
+        if let Some(name) = name {
+            body.append(&mut Self::option_to_bytes(OPT_IF_NAME, name.as_bytes()));
+        }
+        body.append(&mut Self::option_to_bytes(OPT_IF_TSRESOL, &[9]));
+        body.append(&mut Self::option_to_bytes(OPT_END, &[]));
+        self.write_block(INTERFACE_DESCRIPTION_BLOCK, &body)?;
+        self.interfaces += 1;
+        Ok(self.interfaces - 1)
+    }
+
+    pub fn write_frame_on(
+        &mut self,
+        interface: u32,
+        data: &[u8],
+        timestamp: SystemTime,
+    ) -> io::Result<()> {
+        if interface >= self.interfaces {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown pcapng interface {}", interface),
+            ));
+        }
+        let nanos = timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64;
+        let mut body = vec![0u8; 20];
+        LittleEndian::write_u32(&mut body[0..4], interface);
+        LittleEndian::write_u32(&mut body[4..8], (nanos >> 32) as u32);
+        LittleEndian::write_u32(&mut body[8..12], nanos as u32);
+        LittleEndian::write_u32(&mut body[12..16], data.len() as u32);
+        LittleEndian::write_u32(&mut body[16..20], data.len() as u32);
+        body.extend_from_slice(data);
+        self.write_block(ENHANCED_PACKET_BLOCK, &body)
+    }
+
+    /// Writes a frame on the first interface, declaring it if needed.
+    pub fn write_frame(&mut self, data: &[u8], timestamp: SystemTime) -> io::Result<()> {
+        if self.interfaces == 0 {
+            self.add_interface(None)?;
+        }
+        self.write_frame_on(0, data, timestamp)
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet, timestamp: SystemTime) -> io::Result<()> {
+        self.write_frame(&packet.to_bytes(), timestamp)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> FrameTransport for PcapNgWriter<W> {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        self.write_frame(data, timestamp.unwrap_or_else(SystemTime::now))
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Cannot receive frames from a pcapng writer",
+        ))
+    }
+
+    fn set_read_timeout(&mut self, _timeout: Option<Duration>) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_then_read() {
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let mut writer = PcapNgWriter::new(vec![]).unwrap();
+        let eth0 = writer.add_interface(Some("eth0")).unwrap();
+        let eth1 = writer.add_interface(Some("eth1")).unwrap();
+        writer.write_frame_on(eth1, &[0xaa; 61], timestamp).unwrap();
+        writer.write_frame_on(eth0, &[0xbb; 64], timestamp).unwrap();
+        let bytes = writer.into_inner();
+
+        let mut reader = PcapNgReader::new(bytes.as_slice()).unwrap();
+        let (interface, frame) = reader.next_frame_with_interface().unwrap().unwrap();
+        assert_eq!(interface, 1);
+        assert_eq!(frame.data, vec![0xaa; 61]);
+        assert_eq!(frame.timestamp, Some(timestamp));
+        assert_eq!(reader.interfaces().len(), 2);
+        assert_eq!(reader.interfaces()[0].name.as_deref(), Some("eth0"));
+        assert_eq!(reader.interfaces()[1].ts_resolution, 9);
+        assert_eq!(reader.next().unwrap().unwrap().data, vec![0xbb; 64]);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn write_frame_declares_default_interface() {
+        let mut writer = PcapNgWriter::new(vec![]).unwrap();
+        writer.send_frame(&[0x01; 60], None).unwrap();
+        let bytes = writer.into_inner();
+
+        let mut reader = PcapNgReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(reader.recv_frame().unwrap().data, vec![0x01; 60]);
+        assert_eq!(reader.interfaces()[0].name, None);
+    }
+
+    #[test]
+    fn microsecond_resolution_by_default() {
+        let interface = Interface {
+            link_type: LINKTYPE_ETHERNET as u16,
+            snaplen: SNAPLEN,
+            name: None,
+            ts_resolution: 6,
+        };
+        assert_eq!(
+            interface.timestamp(1_500_000).unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_millis(1_500)
+        );
+        let binary = Interface {
+            ts_resolution: 0x80 | 10,
+            ..interface
+        };
+        assert_eq!(
+            binary.timestamp(512).unwrap(),
+            SystemTime::UNIX_EPOCH + Duration::from_millis(500)
+        );
+    }
+
+    #[test]
+    fn reject_corrupt_resolution_and_lengths() {
+        let interface = Interface {
+            link_type: LINKTYPE_ETHERNET as u16,
+            snaplen: SNAPLEN,
+            name: None,
+            ts_resolution: 48,
+        };
+        let kind = |result: io::Result<SystemTime>| result.unwrap_err().kind();
+        assert_eq!(kind(interface.timestamp(1)), io::ErrorKind::InvalidData);
+        let binary = Interface {
+            ts_resolution: 0x80,
+            ..interface
+        };
+        assert_eq!(kind(binary.timestamp(u64::MAX)), io::ErrorKind::InvalidData);
+
+        let mut bytes = PcapNgWriter::new(vec![]).unwrap().into_inner();
+        let mut block = [0u8; 8];
+        LittleEndian::write_u32(&mut block[0..4], ENHANCED_PACKET_BLOCK);
+        LittleEndian::write_u32(&mut block[4..8], 0xffff_fff0);
+        bytes.extend_from_slice(&block);
+        let mut reader = PcapNgReader::new(bytes.as_slice()).unwrap();
+        assert_eq!(
+            reader.next_frame().unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn reject_pcap_file() {
+        let mut bytes = vec![0u8; 24];
+        LittleEndian::write_u32(&mut bytes[0..4], 0xa1b2_c3d4);
+        assert!(PcapNgReader::new(bytes.as_slice()).is_err());
+    }
+}
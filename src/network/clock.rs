@@ -0,0 +1,117 @@
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use libc::{CLOCK_MONOTONIC, CLOCK_REALTIME, CLOCK_TAI, TIMER_ABSTIME, clockid_t, timespec};
+
+pub const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// POSIX clocks usable for deadlines and launch times. `Tai` is the clock the
+/// ETF qdisc expects for SO_TXTIME.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Clock {
+    Monotonic,
+    Realtime,
+    Tai,
+}
+
+impl Clock {
+    pub fn id(self) -> clockid_t {
+        match self {
+            Clock::Monotonic => CLOCK_MONOTONIC,
+            Clock::Realtime => CLOCK_REALTIME,
+            Clock::Tai => CLOCK_TAI,
+        }
+    }
+
+    /// Current time in nanoseconds.
+    pub fn now(self) -> u64 {
+        let mut time = timespec {
+            tv_sec: 0,
+            tv_nsec: 0,
+        };
+        unsafe { libc::clock_gettime(self.id(), &mut time) };
+        time.tv_sec as u64 * NANOS_PER_SECOND + time.tv_nsec as u64
+    }
+
+    /// Sleeps until the absolute time `deadline`, in nanoseconds, using
+    /// `clock_nanosleep(TIMER_ABSTIME)`. Returns at once if it already passed.
+    pub fn sleep_until(self, deadline: u64) -> io::Result<()> {
+        let time = timespec {
+            tv_sec: (deadline / NANOS_PER_SECOND) as libc::time_t,
+            tv_nsec: (deadline % NANOS_PER_SECOND) as libc::c_long,
+        };
+        loop {
+            let result = unsafe {
+                libc::clock_nanosleep(self.id(), TIMER_ABSTIME, &time, std::ptr::null_mut())
+            };
+            match result {
+                0 => return Ok(()),
+                libc::EINTR => continue,
+                errno => return Err(io::Error::from_raw_os_error(errno)),
+            }
+        }
+    }
+
+    /// Whether the kernel reports the system clock as synchronized by an NTP
+    /// or PTP daemon (`adjtimex` state and `STA_UNSYNC`). `Monotonic` is never
+    /// synchronized to an external time reference.
+    pub fn is_synchronized(self) -> bool {
+        if self == Clock::Monotonic {
+            return false;
+        }
+        let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+        let state = unsafe { libc::adjtimex(&mut timex) };
+        state >= 0 && state != libc::TIME_ERROR && timex.status & libc::STA_UNSYNC == 0
+    }
+
+    /// Nanoseconds to add to a `Realtime` reading to get this clock. The TAI
+    /// offset is a whole number of seconds, so it is rounded to stay exact.
+    fn offset_from_realtime(self) -> i64 {
+        match self {
+            Clock::Realtime => 0,
+            Clock::Tai => {
+                let offset = Clock::Tai.now() as i64 - Clock::Realtime.now() as i64;
+                let second = NANOS_PER_SECOND as i64;
+                (offset + second / 2).div_euclid(second) * second
+            }
+            Clock::Monotonic => Clock::Monotonic.now() as i64 - Clock::Realtime.now() as i64,
+        }
+    }
+
+    pub fn to_system_time(self, time: u64) -> SystemTime {
+        let realtime = time as i64 - self.offset_from_realtime();
+        SystemTime::UNIX_EPOCH + Duration::from_nanos(realtime.max(0) as u64)
+    }
+
+    pub fn from_system_time(self, time: SystemTime) -> u64 {
+        let realtime = time
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as i64;
+        (realtime + self.offset_from_realtime()).max(0) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sleep_until_deadline() {
+        let deadline = Clock::Monotonic.now() + 1_000_000;
+        Clock::Monotonic.sleep_until(deadline).unwrap();
+        assert!(Clock::Monotonic.now() >= deadline);
+    }
+
+    #[test]
+    fn tai_conversion_is_exact() {
+        let time = SystemTime::UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789);
+        let tai = Clock::Tai.from_system_time(time);
+        assert_eq!(tai % NANOS_PER_SECOND, 123_456_789);
+        assert_eq!(Clock::Tai.to_system_time(tai), time);
+        assert_eq!(
+            Clock::Realtime.from_system_time(time),
+            1_700_000_000_123_456_789
+        );
+    }
+}
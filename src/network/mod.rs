@@ -1,5 +1,11 @@
+#[cfg(feature = "tokio")]
+pub mod async_socket;
+pub mod capture;
+pub mod clock;
 pub mod eth_types;
 pub mod packet;
 pub mod publisher;
+pub mod realtime;
 pub mod socket;
 pub mod subscriber;
+pub mod transport;
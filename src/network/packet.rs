@@ -1,3 +1,6 @@
+use std::io;
+
+use crate::network::eth_types::EthTypes;
 use crate::protocols::{ethernet::model::Ethernet, sampled_values::model::SampledValue};
 
 #[derive(PartialEq, Debug)]
@@ -9,8 +12,8 @@ pub struct Packet {
 
 impl Packet {
     pub fn from_bytes(bytes: &[u8]) -> Packet {
-        let eth_model = Ethernet::from_bytes(&bytes[0..14]);
-        let sv_model = SampledValue::from_bytes(&bytes[14..]);
+        let eth_model = Ethernet::from_bytes(bytes);
+        let sv_model = SampledValue::from_bytes(&bytes[eth_model.header_len()..]);
         let last_eth_type = eth_model.ether_type;
         Packet {
             ether_type: last_eth_type,
@@ -19,6 +22,25 @@ impl Packet {
         }
     }
 
+    /// Decodes a Sampled Values frame received from the network, failing on
+    /// other EtherTypes and on truncated or malformed frames.
+    pub fn try_from_bytes(bytes: &[u8]) -> io::Result<Packet> {
+        let eth_model = Ethernet::try_from_bytes(bytes)?;
+        let ether_type = u16::from_be_bytes(eth_model.ether_type);
+        if ether_type != EthTypes::SampledValues as u16 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("EtherType {:#06x} is not Sampled Values", ether_type),
+            ));
+        }
+        let sv_model = SampledValue::try_from_bytes(&bytes[eth_model.header_len()..])?;
+        Ok(Packet {
+            ether_type: eth_model.ether_type,
+            ethernet: eth_model,
+            sampled_value: sv_model,
+        })
+    }
+
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
         bytes.append(&mut self.ethernet.to_bytes());
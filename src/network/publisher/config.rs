@@ -0,0 +1,486 @@
+use std::fmt;
+use std::io;
+
+use crate::{
+    network::{
+        clock::Clock,
+        eth_types::EthTypes,
+        packet::Packet,
+        publisher::scheduler::{BusyWait, Scheduler},
+        realtime::RealtimeConfig,
+    },
+    protocols::{
+        ethernet::model::{Ethernet, Vlan},
+        sampled_values::{
+            model::{Asdu, Phases, SampleSync, SampledValue},
+            rate,
+            waveform::{SineGenerator, WaveformSource},
+        },
+    },
+};
+
+/// Interface names are limited to `IFNAMSIZ` bytes including the NUL.
+const MAX_INTERFACE_LEN: usize = libc::IFNAMSIZ - 1;
+/// svID and datSet are encoded with a single byte BER length.
+const MAX_STRING_LEN: usize = 127;
+/// Largest Ethernet payload without jumbo frames.
+const MAX_PAYLOAD_LEN: usize = 1500;
+
+/// Everything needed to publish one SV stream. Build it with
+/// [`PublisherConfig::builder`], which validates the result.
+pub struct PublisherConfig {
+    pub interface: String,
+    pub dst_mac: [u8; 6],
+    pub src_mac: [u8; 6],
+    pub vlan: Option<Vlan>,
+    pub app_id: u16,
+    pub sv_id: String,
+    pub dataset: Option<String>,
+    pub conf_rev: u32,
+    /// Samples per second. smpCnt wraps at this value.
+    pub sample_rate: u32,
+    /// Accepts sample rates outside [`rate::STANDARD_RATES`], e.g. for
+    /// slow test streams.
+    pub nonstandard_rate: bool,
+    pub asdus_per_frame: u8,
+    /// smpSynch to publish. With `time_aligned`, published only while the
+    /// system clock is synchronized, `Internal` otherwise.
+    pub smp_sync: SampleSync,
+    /// Derives smpCnt and the frame deadlines from the scheduler clock, which
+    /// must be `Realtime` or `Tai`, so smpCnt 0 leaves at the top of each
+    /// second like a merging unit locked to a 1PPS signal.
+    pub time_aligned: bool,
+    /// Waits for each frame deadline.
+    pub scheduler: Box<dyn Scheduler>,
+    /// When set, the scheduler wakes this many nanoseconds before each
+    /// deadline and the deadline is passed to the transport as launch time.
+    /// A `RawSocket` needs `enable_txtime` on the clock of the scheduler.
+    pub launch_lead: Option<u64>,
+    pub waveform: Box<dyn WaveformSource>,
+    /// Initial simulation bit, switchable at runtime with
+    /// [`super::control::PublisherControl`].
+    pub simulation: bool,
+    /// Real-time setup applied to the publishing thread before the first
+    /// frame. Steps that fail are reported, not fatal.
+    pub realtime: Option<RealtimeConfig>,
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ConfigError {
+    Interface(String),
+    SourceMac([u8; 6]),
+    Vlan(Vlan),
+    AppId(u16),
+    SvId(String),
+    Dataset(String),
+    SampleRate(u32),
+    /// Not an IEC 61869-9 rate and `nonstandard_rate` is not set.
+    NonstandardRate(u32),
+    AsdusPerFrame(u8),
+    FrameTooLong(usize),
+    AlignmentClock(Clock),
+    /// Another stream of the same emulator already publishes this svID.
+    DuplicateSvId(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Interface(iface) => write!(f, "Invalid interface name {:?}", iface),
+            ConfigError::SourceMac(mac) => {
+                write!(
+                    f,
+                    "Source MAC {:02x?} is not a unicast station address",
+                    mac
+                )
+            }
+            ConfigError::Vlan(vlan) => write!(
+                f,
+                "Invalid VLAN priority {} or id {}",
+                vlan.priority, vlan.id
+            ),
+            ConfigError::AppId(app_id) => write!(
+                f,
+                "APPID {:#06x} is outside the SV range 0x4000-0x7fff",
+                app_id
+            ),
+            ConfigError::SvId(sv_id) => write!(
+                f,
+                "svID {:?} must be 1 to {} visible characters",
+                sv_id, MAX_STRING_LEN
+            ),
+            ConfigError::Dataset(dataset) => write!(
+                f,
+                "datSet {:?} must be 1 to {} visible characters",
+                dataset, MAX_STRING_LEN
+            ),
+            ConfigError::SampleRate(rate) => write!(f, "Invalid sample rate {}", rate),
+            ConfigError::NonstandardRate(rate) => write!(
+                f,
+                "Sample rate {} is not an IEC 61869-9 rate, allow it with nonstandard_rate",
+                rate
+            ),
+            ConfigError::AsdusPerFrame(asdus) => write!(f, "Invalid ASDUs per frame {}", asdus),
+            ConfigError::AlignmentClock(clock) => write!(
+                f,
+                "Time aligned publishing needs a Realtime or Tai clock, not {:?}",
+                clock
+            ),
+            ConfigError::DuplicateSvId(sv_id) => {
+                write!(f, "svID {:?} is already published", sv_id)
+            }
+            ConfigError::FrameTooLong(len) => write!(
+                f,
+                "SV payload of {} bytes exceeds the {} bytes MTU",
+                len, MAX_PAYLOAD_LEN
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<ConfigError> for io::Error {
+    fn from(err: ConfigError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+fn is_visible_string(value: &str) -> bool {
+    (1..=MAX_STRING_LEN).contains(&value.len()) && value.bytes().all(|b| (0x20..0x7f).contains(&b))
+}
+
+impl PublisherConfig {
+    pub fn builder() -> PublisherConfigBuilder {
+        PublisherConfigBuilder::default()
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.interface.is_empty()
+            || self.interface.len() > MAX_INTERFACE_LEN
+            || self.interface.contains(['\0', '/'])
+        {
+            return Err(ConfigError::Interface(self.interface.clone()));
+        }
+        if self.src_mac[0] & 0x01 != 0 || self.src_mac == [0; 6] {
+            return Err(ConfigError::SourceMac(self.src_mac));
+        }
+        if let Some(vlan) = self.vlan
+            && (vlan.priority > 7 || vlan.id > 0x0fff)
+        {
+            return Err(ConfigError::Vlan(vlan));
+        }
+        if !(0x4000..=0x7fff).contains(&self.app_id) {
+            return Err(ConfigError::AppId(self.app_id));
+        }
+        if !is_visible_string(&self.sv_id) {
+            return Err(ConfigError::SvId(self.sv_id.clone()));
+        }
+        if let Some(dataset) = &self.dataset
+            && !is_visible_string(dataset)
+        {
+            return Err(ConfigError::Dataset(dataset.clone()));
+        }
+        if !(1..=u16::MAX as u32 + 1).contains(&self.sample_rate) {
+            return Err(ConfigError::SampleRate(self.sample_rate));
+        }
+        if !self.nonstandard_rate && !rate::is_standard_rate(self.sample_rate) {
+            return Err(ConfigError::NonstandardRate(self.sample_rate));
+        }
+        if self.asdus_per_frame == 0
+            || self.asdus_per_frame as u32 > self.sample_rate
+            || (self.time_aligned && !self.sample_rate.is_multiple_of(self.asdus_per_frame as u32))
+        {
+            return Err(ConfigError::AsdusPerFrame(self.asdus_per_frame));
+        }
+        if self.time_aligned && self.scheduler.clock() == Clock::Monotonic {
+            return Err(ConfigError::AlignmentClock(Clock::Monotonic));
+        }
+        let packet = self.packet();
+        let payload_len = packet.to_bytes().len() - packet.ethernet.header_len();
+        if payload_len > MAX_PAYLOAD_LEN {
+            return Err(ConfigError::FrameTooLong(payload_len));
+        }
+        Ok(())
+    }
+
+    /// Frames per second, the sample rate divided by the ASDUs per frame.
+    pub fn frame_rate(&self) -> f64 {
+        self.sample_rate as f64 / self.asdus_per_frame as f64
+    }
+
+    /// Frame template with `asdus_per_frame` zeroed ASDUs and smpCnt 0.
+    pub fn packet(&self) -> Packet {
+        let ether_type = (EthTypes::SampledValues as u16).to_be_bytes();
+        let asdu = Asdu {
+            sv_id: self.sv_id.clone(),
+            dataset: self.dataset.clone(),
+            smp_count: 0,
+            conf_rev: self.conf_rev,
+            refr_tm: None,
+            smp_sync: self.smp_sync,
+            smp_rate: None,
+            measures: Phases::default(),
+            smp_mode: None,
+        };
+        let mut sampled_value = SampledValue {
+            app_id: self.app_id,
+            length: 0,
+            simulation: self.simulation,
+            reserved1: [0, 0],
+            reserved2: [0, 0],
+            number_of_asdu: self.asdus_per_frame as u32,
+            asdu: vec![asdu; self.asdus_per_frame as usize],
+        };
+        sampled_value.length = sampled_value.to_bytes().len() as u16;
+        Packet {
+            ether_type,
+            ethernet: Ethernet {
+                dst_mac: self.dst_mac,
+                src_mac: self.src_mac,
+                vlan: self.vlan,
+                ether_type,
+            },
+            sampled_value,
+        }
+    }
+}
+
+/// Builder for [`PublisherConfig`]. Defaults to a 4800 Hz stream of one ASDU
+/// per frame on `lo`, APPID 0x4000 and a balanced 50 Hz set of 1 A and 63.5 V.
+pub struct PublisherConfigBuilder {
+    config: PublisherConfig,
+}
+
+impl Default for PublisherConfigBuilder {
+    fn default() -> Self {
+        PublisherConfigBuilder {
+            config: PublisherConfig {
+                interface: "lo".to_string(),
+                dst_mac: [0x01, 0x0c, 0xcd, 0x04, 0x00, 0x00],
+                // Locally administered unicast, set the interface's own
+                // address with `src_mac` on a real network.
+                src_mac: [0x02, 0x00, 0x00, 0x00, 0x00, 0x01],
+                vlan: None,
+                app_id: 0x4000,
+                sv_id: "SV01".to_string(),
+                dataset: None,
+                conf_rev: 1,
+                sample_rate: 4800,
+                nonstandard_rate: false,
+                asdus_per_frame: 1,
+                smp_sync: SampleSync::Internal,
+                time_aligned: false,
+                scheduler: Box::new(BusyWait::default()),
+                launch_lead: None,
+                waveform: Box::new(SineGenerator::default()),
+                simulation: false,
+                realtime: None,
+            },
+        }
+    }
+}
+
+impl PublisherConfigBuilder {
+    pub fn interface(mut self, interface: impl Into<String>) -> Self {
+        self.config.interface = interface.into();
+        self
+    }
+
+    pub fn dst_mac(mut self, dst_mac: [u8; 6]) -> Self {
+        self.config.dst_mac = dst_mac;
+        self
+    }
+
+    pub fn src_mac(mut self, src_mac: [u8; 6]) -> Self {
+        self.config.src_mac = src_mac;
+        self
+    }
+
+    pub fn vlan(mut self, vlan: Option<Vlan>) -> Self {
+        self.config.vlan = vlan;
+        self
+    }
+
+    pub fn app_id(mut self, app_id: u16) -> Self {
+        self.config.app_id = app_id;
+        self
+    }
+
+    pub fn sv_id(mut self, sv_id: impl Into<String>) -> Self {
+        self.config.sv_id = sv_id.into();
+        self
+    }
+
+    pub fn dataset(mut self, dataset: Option<String>) -> Self {
+        self.config.dataset = dataset;
+        self
+    }
+
+    pub fn conf_rev(mut self, conf_rev: u32) -> Self {
+        self.config.conf_rev = conf_rev;
+        self
+    }
+
+    pub fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.config.sample_rate = sample_rate;
+        self
+    }
+
+    pub fn nonstandard_rate(mut self, nonstandard_rate: bool) -> Self {
+        self.config.nonstandard_rate = nonstandard_rate;
+        self
+    }
+
+    /// Sets the sample rate from samples per cycle at a nominal frequency,
+    /// e.g. 80 at 50 Hz for 4000 Hz or 256 at 60 Hz for 15360 Hz.
+    pub fn samples_per_cycle(mut self, samples_per_cycle: u32, frequency: u32) -> Self {
+        self.config.sample_rate = samples_per_cycle * frequency;
+        self
+    }
+
+    pub fn asdus_per_frame(mut self, asdus_per_frame: u8) -> Self {
+        self.config.asdus_per_frame = asdus_per_frame;
+        self
+    }
+
+    pub fn smp_sync(mut self, smp_sync: SampleSync) -> Self {
+        self.config.smp_sync = smp_sync;
+        self
+    }
+
+    pub fn time_aligned(mut self, time_aligned: bool) -> Self {
+        self.config.time_aligned = time_aligned;
+        self
+    }
+
+    pub fn scheduler<S: Scheduler + 'static>(mut self, scheduler: S) -> Self {
+        self.config.scheduler = Box::new(scheduler);
+        self
+    }
+
+    pub fn launch_lead(mut self, launch_lead: Option<u64>) -> Self {
+        self.config.launch_lead = launch_lead;
+        self
+    }
+
+    pub fn waveform<W: WaveformSource + 'static>(mut self, waveform: W) -> Self {
+        self.config.waveform = Box::new(waveform);
+        self
+    }
+
+    pub fn simulation(mut self, simulation: bool) -> Self {
+        self.config.simulation = simulation;
+        self
+    }
+
+    pub fn realtime(mut self, realtime: Option<RealtimeConfig>) -> Self {
+        self.config.realtime = realtime;
+        self
+    }
+
+    pub fn build(self) -> Result<PublisherConfig, ConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::sampled_values::rate::STANDARD_RATES;
+
+    #[test]
+    fn build_defaults() {
+        let config = PublisherConfig::builder().build().unwrap();
+        let packet = config.packet();
+        let bytes = packet.to_bytes();
+        let decoded = Packet::from_bytes(&bytes);
+        assert_eq!(decoded, packet);
+        assert_eq!(decoded.sampled_value.length as usize, bytes.len() - 14);
+        // Unicast and locally administered.
+        assert_eq!(packet.ethernet.src_mac[0] & 0x03, 0x02);
+    }
+
+    #[test]
+    fn standard_rates_fit_in_a_frame() {
+        for sample_rate in STANDARD_RATES {
+            for asdus_per_frame in [1, 2, 4, 8] {
+                let config = PublisherConfig::builder()
+                    .sample_rate(sample_rate)
+                    .asdus_per_frame(asdus_per_frame)
+                    .build()
+                    .unwrap();
+                assert_eq!(
+                    config.frame_rate(),
+                    sample_rate as f64 / asdus_per_frame as f64
+                );
+            }
+        }
+        let config = PublisherConfig::builder()
+            .samples_per_cycle(256, 50)
+            .build()
+            .unwrap();
+        assert_eq!(config.sample_rate, 12800);
+    }
+
+    #[test]
+    fn reject_invalid_fields() {
+        let build = |builder: PublisherConfigBuilder| builder.build().err();
+        assert_eq!(
+            build(PublisherConfig::builder().app_id(0x1000)),
+            Some(ConfigError::AppId(0x1000))
+        );
+        assert_eq!(
+            build(PublisherConfig::builder().interface("")),
+            Some(ConfigError::Interface("".to_string()))
+        );
+        assert_eq!(
+            build(PublisherConfig::builder().sv_id("")),
+            Some(ConfigError::SvId("".to_string()))
+        );
+        assert_eq!(
+            build(PublisherConfig::builder().sample_rate(0)),
+            Some(ConfigError::SampleRate(0))
+        );
+        for mac in [[0; 6], [0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01]] {
+            assert_eq!(
+                build(PublisherConfig::builder().src_mac(mac)),
+                Some(ConfigError::SourceMac(mac))
+            );
+        }
+        assert_eq!(
+            build(PublisherConfig::builder().sample_rate(1000)),
+            Some(ConfigError::NonstandardRate(1000))
+        );
+        assert!(
+            build(
+                PublisherConfig::builder()
+                    .sample_rate(1000)
+                    .nonstandard_rate(true)
+            )
+            .is_none()
+        );
+        assert_eq!(
+            build(PublisherConfig::builder().asdus_per_frame(0)),
+            Some(ConfigError::AsdusPerFrame(0))
+        );
+        assert!(matches!(
+            build(PublisherConfig::builder().asdus_per_frame(20)),
+            Some(ConfigError::FrameTooLong(_))
+        ));
+        assert_eq!(
+            build(PublisherConfig::builder().time_aligned(true)),
+            Some(ConfigError::AlignmentClock(Clock::Monotonic))
+        );
+        let vlan = Vlan {
+            priority: 9,
+            dei: false,
+            id: 1,
+        };
+        assert_eq!(
+            build(PublisherConfig::builder().vlan(Some(vlan))),
+            Some(ConfigError::Vlan(vlan))
+        );
+    }
+}
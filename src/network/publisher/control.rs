@@ -0,0 +1,219 @@
+use std::io;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU8, AtomicU64, Ordering},
+    mpsc::{RecvTimeoutError, Sender, channel},
+};
+use std::time::Duration;
+
+use crate::network::packet::Packet;
+use crate::protocols::sampled_values::{
+    quality::{QualityOverride, Validity},
+    waveform::{Channel, WaveformChange, WaveformSource},
+};
+
+/// Runtime control of a publisher: the simulation bit, per-channel quality
+/// overrides, waveform changes and pausing. Clones share the same state, so
+/// it can be kept and changed while the publisher runs; changes apply from
+/// the next frame, on its first sample.
+///
+/// The simulation bit is set when either this control or the waveform
+/// source (such as a scenario state) asks for it. Quality overrides apply on
+/// top of the quality produced by the source.
+#[derive(Clone, Debug, Default)]
+pub struct PublisherControl {
+    shared: Arc<ControlState>,
+}
+
+#[derive(Debug, Default)]
+struct ControlState {
+    simulation: AtomicBool,
+    /// Per-channel overrides, encoded so every frame reads them without
+    /// locking.
+    quality: [AtomicU8; 8],
+    paused: AtomicBool,
+    next_change: AtomicU64,
+    changes: Mutex<Vec<QueuedChange>>,
+}
+
+#[derive(Debug)]
+struct QueuedChange {
+    id: u64,
+    change: WaveformChange,
+    outcome: Sender<io::Result<()>>,
+}
+
+impl PublisherControl {
+    pub fn new(simulation: bool) -> Self {
+        let control = PublisherControl::default();
+        control.set_simulation(simulation);
+        control
+    }
+
+    pub fn simulation(&self) -> bool {
+        self.shared.simulation.load(Ordering::Acquire)
+    }
+
+    pub fn set_simulation(&self, simulation: bool) {
+        self.shared.simulation.store(simulation, Ordering::Release);
+    }
+
+    pub fn quality(&self, channel: Channel) -> QualityOverride {
+        decode(self.shared.quality[channel.index()].load(Ordering::Acquire))
+    }
+
+    pub fn set_quality(&self, channel: Channel, quality: QualityOverride) {
+        self.shared.quality[channel.index()].store(encode(quality), Ordering::Release);
+    }
+
+    /// Removes every quality override.
+    pub fn clear_quality(&self) {
+        for quality in &self.shared.quality {
+            quality.store(encode(QualityOverride::NONE), Ordering::Release);
+        }
+    }
+
+    /// Stops sending frames. Samples keep advancing with time, so publishing
+    /// resumes where an uninterrupted stream would be.
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::Release);
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::Release);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Acquire)
+    }
+
+    /// Queues `change` for the waveform source and waits up to `timeout` for
+    /// the publisher to apply it before its next frame. A change still queued
+    /// then is withdrawn, so it never applies later, and this fails with
+    /// `io::ErrorKind::TimedOut`.
+    pub fn submit(&self, change: WaveformChange, timeout: Duration) -> io::Result<()> {
+        let (outcome, receiver) = channel();
+        let id = self.shared.next_change.fetch_add(1, Ordering::Relaxed);
+        self.shared.changes.lock().unwrap().push(QueuedChange {
+            id,
+            change,
+            outcome,
+        });
+        let dropped = || io::Error::other("Publisher dropped the change");
+        match receiver.recv_timeout(timeout) {
+            Ok(result) => result,
+            Err(RecvTimeoutError::Timeout) => {
+                let mut changes = self.shared.changes.lock().unwrap();
+                match changes.iter().position(|queued| queued.id == id) {
+                    Some(position) => {
+                        changes.remove(position);
+                        Err(io::Error::new(
+                            io::ErrorKind::TimedOut,
+                            "Publisher is not running, the change was withdrawn",
+                        ))
+                    }
+                    // The publisher took it meanwhile, its outcome is coming.
+                    None => {
+                        drop(changes);
+                        receiver.recv().unwrap_or_else(|_| Err(dropped()))
+                    }
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => Err(dropped()),
+        }
+    }
+
+    /// Applies the queued waveform changes before sample `index`.
+    pub(crate) fn adjust(&self, waveform: &mut dyn WaveformSource, index: u64, sample_rate: u32) {
+        let changes = std::mem::take(&mut *self.shared.changes.lock().unwrap());
+        for queued in changes {
+            // The submitter may have stopped waiting.
+            let _ = queued
+                .outcome
+                .send(waveform.adjust(&queued.change, index, sample_rate));
+        }
+    }
+
+    /// Sets the simulation bit and the overridden qualities of a frame whose
+    /// samples `waveform` just produced.
+    pub(crate) fn apply(&self, waveform: &dyn WaveformSource, packet: &mut Packet) {
+        let sampled_value = &mut packet.sampled_value;
+        sampled_value.simulation = self.simulation() || waveform.simulation() == Some(true);
+        let overrides = self
+            .shared
+            .quality
+            .each_ref()
+            .map(|quality| decode(quality.load(Ordering::Acquire)));
+        if overrides.iter().all(QualityOverride::is_none) {
+            return;
+        }
+        for asdu in &mut sampled_value.asdu {
+            for channel in Channel::ALL {
+                let measurement = channel.measurement_mut(&mut asdu.measures);
+                measurement.quality = overrides[channel.index()].apply(measurement.quality);
+            }
+        }
+    }
+}
+
+// Override encoding: validity in bits 0-1 and test in bits 2-3, zero meaning
+// "keep the source's value" for both.
+fn encode(quality: QualityOverride) -> u8 {
+    let validity = match quality.validity {
+        None => 0,
+        Some(Validity::Good) => 1,
+        Some(Validity::Invalid) => 2,
+        Some(Validity::Questionable) => 3,
+    };
+    let test = match quality.test {
+        None => 0,
+        Some(false) => 1,
+        Some(true) => 2,
+    };
+    validity | test << 2
+}
+
+fn decode(bits: u8) -> QualityOverride {
+    QualityOverride {
+        validity: match bits & 0b11 {
+            1 => Some(Validity::Good),
+            2 => Some(Validity::Invalid),
+            3 => Some(Validity::Questionable),
+            _ => None,
+        },
+        test: match bits >> 2 & 0b11 {
+            1 => Some(false),
+            2 => Some(true),
+            _ => None,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quality_overrides_round_trip() {
+        let control = PublisherControl::default();
+        let validities = [
+            None,
+            Some(Validity::Good),
+            Some(Validity::Invalid),
+            Some(Validity::Questionable),
+        ];
+        for validity in validities {
+            for test in [None, Some(false), Some(true)] {
+                let quality = QualityOverride { validity, test };
+                control.set_quality(Channel::Vn, quality);
+                assert_eq!(control.quality(Channel::Vn), quality);
+            }
+        }
+        control.clear_quality();
+        assert!(
+            Channel::ALL
+                .iter()
+                .all(|&channel| control.quality(channel).is_none())
+        );
+    }
+}
@@ -0,0 +1,474 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::network::publisher::{control::PublisherControl, sampled_value_pub::PublisherStats};
+use crate::protocols::sampled_values::{
+    quality::QualityOverride,
+    waveform::{Channel, WaveformChange},
+};
+
+/// How often idle server threads check whether they should stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+/// How long a waveform change may wait for the publisher to apply it.
+const APPLY_TIMEOUT: Duration = Duration::from_secs(1);
+/// Longest command line; longer ones are answered with an error and the
+/// connection is closed.
+const MAX_LINE_LEN: usize = 64 * 1024;
+/// Clients served at once; further ones are answered with an error.
+const MAX_CONNECTIONS: usize = 16;
+
+/// One command of the control protocol, a JSON object per line:
+///
+/// ```json
+/// {"command": "set_phasor", "channel": "Ia", "magnitude": 5.0, "angle": -30.0}
+/// {"command": "set_frequency", "frequency": 49.8}
+/// {"command": "set_state", "state": "fault"}
+/// {"command": "set_simulation", "simulation": true}
+/// {"command": "set_quality", "channel": "Va", "quality": {"validity": "invalid"}}
+/// {"command": "clear_quality"}
+/// {"command": "pause"}
+/// {"command": "resume"}
+/// {"command": "stats"}
+/// ```
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    SetPhasor {
+        channel: Channel,
+        magnitude: Option<f64>,
+        angle: Option<f64>,
+    },
+    SetFrequency {
+        frequency: f64,
+    },
+    SetState {
+        state: String,
+    },
+    SetSimulation {
+        simulation: bool,
+    },
+    SetQuality {
+        channel: Channel,
+        quality: QualityOverride,
+    },
+    ClearQuality,
+    Pause,
+    Resume,
+    Stats,
+}
+
+/// Answer to a [`Command`], one JSON object per line: `{"ok": true}`, with
+/// `stats` for a stats command, or `{"ok": false, "error": "..."}`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<PublisherStats>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Response {
+            ok: true,
+            error: None,
+            stats: None,
+        }
+    }
+
+    fn error(error: impl ToString) -> Self {
+        Response {
+            ok: false,
+            error: Some(error.to_string()),
+            stats: None,
+        }
+    }
+}
+
+/// Runs `command` against a publisher. Waveform changes wait up to `timeout`
+/// until the publisher applied them on a sample boundary, so their errors
+/// are reported; a change that timed out is withdrawn, see
+/// [`PublisherControl::submit`].
+pub fn execute(
+    command: Command,
+    control: &PublisherControl,
+    timeout: Duration,
+    stats: impl FnOnce() -> PublisherStats,
+) -> Response {
+    let change = match command {
+        Command::SetPhasor {
+            channel,
+            magnitude,
+            angle,
+        } => WaveformChange::Phasor {
+            channel,
+            magnitude,
+            angle,
+        },
+        Command::SetFrequency { frequency } => WaveformChange::Frequency(frequency),
+        Command::SetState { state } => WaveformChange::State(state),
+        Command::SetSimulation { simulation } => {
+            control.set_simulation(simulation);
+            return Response::ok();
+        }
+        Command::SetQuality { channel, quality } => {
+            control.set_quality(channel, quality);
+            return Response::ok();
+        }
+        Command::ClearQuality => {
+            control.clear_quality();
+            return Response::ok();
+        }
+        Command::Pause => {
+            control.pause();
+            return Response::ok();
+        }
+        Command::Resume => {
+            control.resume();
+            return Response::ok();
+        }
+        Command::Stats => {
+            return Response {
+                stats: Some(stats()),
+                ..Response::ok()
+            };
+        }
+    };
+    match control.submit(change, timeout) {
+        Ok(()) => Response::ok(),
+        Err(err) => Response::error(err),
+    }
+}
+
+/// Local control channel of a running publisher: a Unix domain socket
+/// accepting one [`Command`] per line from any number of clients, answered
+/// with one [`Response`] per line. Try it with
+/// `echo '{"command": "stats"}' | socat - UNIX-CONNECT:/tmp/sv.sock`.
+///
+/// The socket file is removed when the server is stopped or dropped.
+pub struct ControlServer {
+    path: PathBuf,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ControlServer {
+    /// Listens on `path`, replacing a stale socket left there. Fails with
+    /// `AddrInUse` while another server still listens on it. `stats`
+    /// produces the answer to stats commands.
+    pub fn bind<P, F>(path: P, control: PublisherControl, stats: F) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+        F: Fn() -> PublisherStats + Send + Sync + 'static,
+    {
+        let path = path.as_ref().to_path_buf();
+        if fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+            match UnixStream::connect(&path) {
+                Ok(_) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AddrInUse,
+                        format!("A control server already listens on {}", path.display()),
+                    ));
+                }
+                Err(err) if err.kind() == io::ErrorKind::ConnectionRefused => {
+                    fs::remove_file(&path)?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        let listener = UnixListener::bind(&path)?;
+        listener.set_nonblocking(true)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let running = Arc::clone(&running);
+            let stats = Arc::new(stats);
+            thread::Builder::new()
+                .name("sv-pub-control".to_string())
+                .spawn(move || accept(listener, &running, &control, &stats))?
+        };
+        Ok(ControlServer {
+            path,
+            running,
+            thread: Some(thread),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops accepting commands, closes the connections and removes the
+    /// socket file.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.running.store(false, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+fn accept<F>(
+    listener: UnixListener,
+    running: &Arc<AtomicBool>,
+    control: &PublisherControl,
+    stats: &Arc<F>,
+) where
+    F: Fn() -> PublisherStats + Send + Sync + 'static,
+{
+    let mut connections: Vec<JoinHandle<()>> = vec![];
+    while running.load(Ordering::Acquire) {
+        connections.retain(|connection| !connection.is_finished());
+        match listener.accept() {
+            Ok((mut stream, _)) if connections.len() >= MAX_CONNECTIONS => {
+                let response = Response::error("Too many control connections");
+                let _ = reply(&mut stream, &response);
+            }
+            Ok((stream, _)) => {
+                let running = Arc::clone(running);
+                let control = control.clone();
+                let stats = Arc::clone(stats);
+                connections.push(thread::spawn(move || {
+                    // A client that goes away only ends its own connection.
+                    let _ = serve(stream, &running, &control, stats.as_ref());
+                }));
+            }
+            // Nothing to accept yet, or a connection that failed to set up.
+            Err(_) => thread::sleep(POLL_INTERVAL),
+        }
+    }
+    for connection in connections {
+        let _ = connection.join();
+    }
+}
+
+fn serve(
+    stream: UnixStream,
+    running: &AtomicBool,
+    control: &PublisherControl,
+    stats: &dyn Fn() -> PublisherStats,
+) -> io::Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(POLL_INTERVAL))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut line = vec![];
+    while running.load(Ordering::Acquire) {
+        let limit = (MAX_LINE_LEN + 1 - line.len()) as u64;
+        match reader.by_ref().take(limit).read_until(b'\n', &mut line) {
+            Ok(0) => return Ok(()),
+            Ok(_) if line.len() > MAX_LINE_LEN && !line.ends_with(b"\n") => {
+                let message = format!("Command longer than {} bytes", MAX_LINE_LEN);
+                return reply(&mut writer, &Response::error(message));
+            }
+            Ok(_) => {}
+            // Partial lines stay in `line` until the rest arrives.
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(err) => return Err(err),
+        }
+        let text = String::from_utf8_lossy(&line);
+        if !text.trim().is_empty() {
+            let response = match serde_json::from_str::<Command>(text.trim()) {
+                Ok(command) => execute(command, control, APPLY_TIMEOUT, stats),
+                Err(err) => Response::error(format!("Invalid command: {}", err)),
+            };
+            reply(&mut writer, &response)?;
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+fn reply(stream: &mut UnixStream, response: &Response) -> io::Result<()> {
+    let mut reply = serde_json::to_vec(response).map_err(io::Error::other)?;
+    reply.push(b'\n');
+    stream.write_all(&reply)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::publisher::{config::PublisherConfig, sampled_value_pub::SvPublisher};
+    use crate::network::transport::FrameTransport;
+    use crate::network::transport::memory::VirtualSwitch;
+    use crate::network::{clock::Clock, packet::Packet, publisher::scheduler::ClockNanosleep};
+    use crate::protocols::sampled_values::{
+        model::Phases,
+        waveform::{SineGenerator, WaveformSource},
+    };
+
+    fn request(stream: &mut BufReader<UnixStream>, command: &str) -> serde_json::Value {
+        stream.get_mut().write_all(command.as_bytes()).unwrap();
+        stream.get_mut().write_all(b"\n").unwrap();
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn commands_over_unix_socket() {
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let config = PublisherConfig::builder()
+            .sample_rate(4000)
+            .scheduler(ClockNanosleep {
+                clock: Clock::Monotonic,
+            })
+            .waveform(SineGenerator::balanced(50.0, 1.0, 100.0))
+            .build()
+            .unwrap();
+        let publisher = SvPublisher::with_transport(config, switch.port()).unwrap();
+        let path = std::env::temp_dir().join(format!("sv-control-{}.sock", std::process::id()));
+        let server = publisher.serve_control(&path).unwrap();
+        let mut client = BufReader::new(UnixStream::connect(&path).unwrap());
+
+        let handle = publisher.start().unwrap();
+        let response = request(
+            &mut client,
+            r#"{"command": "set_phasor", "channel": "Ia", "magnitude": 5.0, "angle": 90}"#,
+        );
+        assert_eq!(response["ok"], true);
+        let response = request(&mut client, r#"{"command": "set_state", "state": "fault"}"#);
+        assert_eq!(response["ok"], false);
+        assert!(
+            response["error"]
+                .as_str()
+                .unwrap()
+                .contains("does not support")
+        );
+        let response = request(
+            &mut client,
+            r#"{"command": "set_quality", "channel": "Va", "quality": {"validity": "invalid"}}"#,
+        );
+        assert_eq!(response["ok"], true);
+        assert_eq!(
+            request(
+                &mut client,
+                r#"{"command": "set_simulation", "simulation": true}"#
+            )["ok"],
+            true
+        );
+        assert_eq!(request(&mut client, r#"{"command": "nope"}"#)["ok"], false);
+
+        // Frames published after the answers carry the changes.
+        input
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        let latest = loop {
+            let packet = Packet::from_bytes(&input.recv_frame().unwrap().data);
+            if packet.sampled_value.simulation {
+                break packet;
+            }
+        };
+        let asdu = &latest.sampled_value.asdu[0];
+        assert_eq!(asdu.measures.voltage.a.quality & 0x3, 0x1);
+        assert_eq!(asdu.measures.current.a.quality, 0);
+
+        assert_eq!(request(&mut client, r#"{"command": "pause"}"#)["ok"], true);
+        let stats = request(&mut client, r#"{"command": "stats"}"#);
+        assert_eq!(stats["stats"]["paused"], true);
+        assert_eq!(stats["stats"]["running"], true);
+        assert!(stats["stats"]["frames_sent"].as_u64().unwrap() > 0);
+        assert_eq!(request(&mut client, r#"{"command": "resume"}"#)["ok"], true);
+
+        handle.stop().unwrap();
+        server.stop();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn live_sockets_are_not_replaced() {
+        let path = std::env::temp_dir().join(format!("sv-bind-{}.sock", std::process::id()));
+        let _ = fs::remove_file(&path);
+        drop(UnixListener::bind(&path).unwrap());
+        let server =
+            ControlServer::bind(&path, PublisherControl::default(), || unreachable!()).unwrap();
+        let err = ControlServer::bind(&path, PublisherControl::default(), || unreachable!())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        assert!(path.exists());
+        server.stop();
+    }
+
+    #[test]
+    fn long_lines_and_extra_clients_are_refused() {
+        let path = std::env::temp_dir().join(format!("sv-limits-{}.sock", std::process::id()));
+        let server =
+            ControlServer::bind(&path, PublisherControl::default(), || unreachable!()).unwrap();
+        let mut clients: Vec<_> = (0..MAX_CONNECTIONS)
+            .map(|_| BufReader::new(UnixStream::connect(&path).unwrap()))
+            .collect();
+        let response = request(&mut clients[0], r#"{"command": "nope"}"#);
+        assert_eq!(response["ok"], false);
+
+        let mut extra = BufReader::new(UnixStream::connect(&path).unwrap());
+        let mut line = String::new();
+        extra.read_line(&mut line).unwrap();
+        assert!(line.contains("Too many control connections"));
+
+        let mut long = clients.pop().unwrap();
+        let command = "x".repeat(MAX_LINE_LEN + 1);
+        long.get_mut().write_all(command.as_bytes()).unwrap();
+        line.clear();
+        long.read_line(&mut line).unwrap();
+        assert!(line.contains("Command longer"));
+        line.clear();
+        assert_eq!(long.read_line(&mut line).unwrap(), 0);
+        server.stop();
+    }
+
+    #[test]
+    fn timed_out_changes_are_withdrawn() {
+        #[derive(Default)]
+        struct Recorder(Vec<WaveformChange>);
+
+        impl WaveformSource for Recorder {
+            fn sample(&mut self, _index: u64, _sample_rate: u32, _measures: &mut Phases) {}
+
+            fn adjust(&mut self, change: &WaveformChange, _: u64, _: u32) -> io::Result<()> {
+                self.0.push(change.clone());
+                Ok(())
+            }
+        }
+
+        let control = PublisherControl::default();
+        let command = Command::SetFrequency { frequency: 60.0 };
+        let response = execute(command, &control, Duration::ZERO, || unreachable!());
+        assert!(!response.ok);
+        assert!(response.error.unwrap().contains("withdrawn"));
+
+        let mut recorder = Recorder::default();
+        control.adjust(&mut recorder, 0, 4000);
+        assert!(recorder.0.is_empty());
+    }
+}
@@ -0,0 +1,561 @@
+use std::io;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use std::thread::{self, JoinHandle};
+
+use crate::{
+    network::{
+        clock::{Clock, NANOS_PER_SECOND},
+        eth_types::EthTypes,
+        packet::Packet,
+        publisher::{
+            config::{ConfigError, PublisherConfig},
+            control::PublisherControl,
+            sampled_value_pub::{FramePeriod, Timeline, fill_frame},
+            scheduler::{LatenessStats, Scheduler, SharedLateness},
+        },
+        realtime::{RealtimeConfig, RealtimeReport},
+        socket::RawSocket,
+        transport::FrameTransport,
+    },
+    protocols::sampled_values::model::SampleSync,
+};
+
+/// Counters of one stream, shared with the handle.
+#[derive(Default)]
+struct StreamCounters {
+    sent: AtomicU64,
+    skipped: AtomicU64,
+}
+
+struct Stream {
+    config: PublisherConfig,
+    packet: Packet,
+    sample: u64,
+    timeline: Timeline,
+    /// Frames of the timeline elapsed.
+    frame: u64,
+    next: Option<Vec<u8>>,
+    control: PublisherControl,
+    counters: Arc<StreamCounters>,
+}
+
+impl Stream {
+    fn deadline(&self) -> u64 {
+        self.timeline.deadline(self.frame)
+    }
+
+    /// Encodes the next frame, `None` while paused.
+    fn prepare(&mut self) {
+        let bytes = fill_frame(
+            &mut self.config.waveform,
+            &self.control,
+            self.config.sample_rate,
+            &mut self.packet,
+            &mut self.sample,
+        );
+        self.next = (!self.control.is_paused()).then_some(bytes);
+    }
+}
+
+/// Identity and counters of one stream of an emulator.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct StreamStats {
+    pub sv_id: String,
+    pub app_id: u16,
+    pub frames_sent: u64,
+    /// Frame deadlines left empty while the stream was paused.
+    pub frames_skipped: u64,
+}
+
+/// Snapshot of a [`MergingUnitEmulator`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct EmulatorStats {
+    pub streams: Vec<StreamStats>,
+    /// Wake-ups of the timing loop, each sending the frames due together.
+    pub batches: u64,
+    /// Lateness of the wake-ups.
+    pub lateness: LatenessStats,
+}
+
+/// State shared between an emulator thread and its handle.
+#[derive(Default)]
+struct Shared {
+    running: AtomicBool,
+    batches: AtomicU64,
+    lateness: SharedLateness,
+    realtime: Mutex<Option<RealtimeReport>>,
+}
+
+/// Identity of a stream for its statistics.
+#[derive(Clone)]
+struct StreamInfo {
+    sv_id: String,
+    app_id: u16,
+    control: PublisherControl,
+    counters: Arc<StreamCounters>,
+}
+
+fn stats(shared: &Shared, streams: &[StreamInfo]) -> EmulatorStats {
+    EmulatorStats {
+        streams: streams
+            .iter()
+            .map(|stream| StreamStats {
+                sv_id: stream.sv_id.clone(),
+                app_id: stream.app_id,
+                frames_sent: stream.counters.sent.load(Ordering::Relaxed),
+                frames_skipped: stream.counters.skipped.load(Ordering::Relaxed),
+            })
+            .collect(),
+        batches: shared.batches.load(Ordering::Relaxed),
+        lateness: shared.lateness.snapshot(),
+    }
+}
+
+/// Publishes many SV streams from one process, like the merging units of a
+/// bay or a substation, on one transport.
+///
+/// A single timing loop schedules every stream on absolute deadlines of the
+/// same clock, so streams are sample aligned: frames of streams with the
+/// same sample rate and ASDUs per frame share their deadlines and leave in
+/// one batch ([`FrameTransport::send_batch`], `sendmmsg` on a raw socket).
+/// To spread streams over several cores, run one emulator per core, each
+/// pinned with [`MergingUnitEmulator::set_realtime`].
+///
+/// Each stream keeps its own svID, APPID, MACs, VLAN, rate, waveform and
+/// [`PublisherControl`]. The interface, scheduler, launch lead, alignment
+/// and real-time settings of the stream configurations are ignored in favor
+/// of those of the emulator.
+pub struct MergingUnitEmulator<T: FrameTransport = RawSocket> {
+    transport: T,
+    scheduler: Box<dyn Scheduler>,
+    time_aligned: bool,
+    launch_lead: Option<u64>,
+    realtime: Option<RealtimeConfig>,
+    streams: Vec<Stream>,
+    shared: Arc<Shared>,
+}
+
+impl MergingUnitEmulator<RawSocket> {
+    /// Opens a raw socket on `interface` for all the streams.
+    pub fn new<S: Scheduler + 'static>(
+        interface: &str,
+        scheduler: S,
+        time_aligned: bool,
+    ) -> io::Result<Self> {
+        let socket = RawSocket::try_new(interface.to_string(), EthTypes::SampledValues as u16)?;
+        Ok(Self::with_transport(socket, scheduler, time_aligned)?)
+    }
+}
+
+impl<T: FrameTransport> MergingUnitEmulator<T> {
+    /// With `time_aligned`, smpCnt 0 of every stream leaves at the top of
+    /// each second of the scheduler clock, which must be `Realtime` or `Tai`.
+    pub fn with_transport<S: Scheduler + 'static>(
+        transport: T,
+        scheduler: S,
+        time_aligned: bool,
+    ) -> Result<Self, ConfigError> {
+        if time_aligned && scheduler.clock() == Clock::Monotonic {
+            return Err(ConfigError::AlignmentClock(Clock::Monotonic));
+        }
+        Ok(MergingUnitEmulator {
+            transport,
+            scheduler: Box::new(scheduler),
+            time_aligned,
+            launch_lead: None,
+            realtime: None,
+            streams: vec![],
+            shared: Arc::default(),
+        })
+    }
+
+    /// Wakes up `launch_lead` nanoseconds before each deadline and passes
+    /// the deadline to the transport as launch time.
+    pub fn set_launch_lead(&mut self, launch_lead: Option<u64>) {
+        self.launch_lead = launch_lead;
+    }
+
+    /// Real-time setup applied to the emulator thread before the first frame.
+    pub fn set_realtime(&mut self, realtime: Option<RealtimeConfig>) {
+        self.realtime = realtime;
+    }
+
+    /// Adds a stream and returns its control. svIDs must be distinct.
+    pub fn add_stream(&mut self, config: PublisherConfig) -> Result<PublisherControl, ConfigError> {
+        config.validate()?;
+        if self.time_aligned
+            && !config
+                .sample_rate
+                .is_multiple_of(config.asdus_per_frame as u32)
+        {
+            return Err(ConfigError::AsdusPerFrame(config.asdus_per_frame));
+        }
+        if self
+            .streams
+            .iter()
+            .any(|stream| stream.config.sv_id == config.sv_id)
+        {
+            return Err(ConfigError::DuplicateSvId(config.sv_id));
+        }
+        let control = PublisherControl::new(config.simulation);
+        let period = FramePeriod {
+            samples_per_frame: config.asdus_per_frame as u64,
+            sample_rate: config.sample_rate as u64,
+        };
+        self.streams.push(Stream {
+            packet: config.packet(),
+            config,
+            sample: 0,
+            // Set when the emulator starts.
+            timeline: Timeline::starting_at(0, period),
+            frame: 0,
+            next: None,
+            control: control.clone(),
+            counters: Arc::default(),
+        });
+        Ok(control)
+    }
+
+    pub fn stream_count(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Control of stream `index`, in the order they were added.
+    pub fn control(&self, index: usize) -> Option<PublisherControl> {
+        self.streams.get(index).map(|stream| stream.control.clone())
+    }
+
+    pub fn stats(&self) -> EmulatorStats {
+        stats(&self.shared, &self.infos())
+    }
+
+    /// Outcome of the real-time setup, once the emulator started.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime.lock().unwrap().clone()
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    fn infos(&self) -> Vec<StreamInfo> {
+        self.streams
+            .iter()
+            .map(|stream| StreamInfo {
+                sv_id: stream.config.sv_id.clone(),
+                app_id: stream.config.app_id,
+                control: stream.control.clone(),
+                counters: Arc::clone(&stream.counters),
+            })
+            .collect()
+    }
+
+    /// Publishes on the calling thread. Stops after `batches` wake-ups of the
+    /// timing loop, or never when `None`, and returns the frames sent.
+    pub fn run(&mut self, batches: Option<u64>) -> io::Result<u64> {
+        self.shared.running.store(true, Ordering::Release);
+        let result = self.run_while(batches);
+        self.shared.running.store(false, Ordering::Release);
+        result
+    }
+
+    fn run_while(&mut self, batches: Option<u64>) -> io::Result<u64> {
+        if let Some(realtime) = &self.realtime {
+            *self.shared.realtime.lock().unwrap() = Some(realtime.apply());
+        }
+        if self.streams.is_empty() {
+            return Ok(0);
+        }
+        let clock = self.scheduler.clock();
+        let lead = self.launch_lead.unwrap_or(0);
+        // Relative streams share one origin, far enough for the slowest
+        // stream to prepare its first frame in time.
+        let origin = self
+            .streams
+            .iter()
+            .map(|stream| stream.timeline.period().offset(1))
+            .max()
+            .unwrap_or(0)
+            + clock.now()
+            + lead;
+        for stream in &mut self.streams {
+            let period = stream.timeline.period();
+            stream.frame = 0;
+            if self.time_aligned {
+                stream.timeline = Timeline::aligned(clock, period, lead);
+                stream.sample = stream.timeline.first_frame * period.samples_per_frame;
+            } else {
+                stream.timeline = Timeline::starting_at(origin, period);
+            }
+        }
+        let mut second = None;
+        self.refresh_sync(&mut second, clock.now());
+        for stream in &mut self.streams {
+            stream.prepare();
+        }
+
+        let mut count = 0;
+        let mut sent = 0;
+        let mut due = Vec::with_capacity(self.streams.len());
+        while batches.is_none_or(|batches| count < batches)
+            && self.shared.running.load(Ordering::Acquire)
+        {
+            let deadline = self
+                .streams
+                .iter()
+                .map(Stream::deadline)
+                .min()
+                .unwrap_or_default();
+            let lateness = self.scheduler.wait_until(deadline - lead)?;
+            self.shared.lateness.record(lateness);
+
+            due.clear();
+            due.extend(
+                (0..self.streams.len()).filter(|index| self.streams[*index].deadline() == deadline),
+            );
+            let frames: Vec<&[u8]> = due
+                .iter()
+                .filter_map(|index| self.streams[*index].next.as_deref())
+                .collect();
+            if !frames.is_empty() {
+                match self.launch_lead {
+                    Some(_) => self.transport.send_batch_at(&frames, clock, deadline)?,
+                    None => self.transport.send_batch(&frames, None)?,
+                }
+                sent += frames.len() as u64;
+            }
+            count += 1;
+            self.shared.batches.store(count, Ordering::Relaxed);
+
+            self.refresh_sync(&mut second, deadline);
+            for index in &due {
+                let stream = &mut self.streams[*index];
+                let counter = match stream.next {
+                    Some(_) => &stream.counters.sent,
+                    None => &stream.counters.skipped,
+                };
+                counter.fetch_add(1, Ordering::Relaxed);
+                stream.frame += 1;
+                stream.prepare();
+            }
+        }
+        self.transport.flush()?;
+        Ok(sent)
+    }
+
+    /// With time alignment, publishes the configured smpSynch only while the
+    /// clock is synchronized, checked once per second of `time`.
+    fn refresh_sync(&mut self, second: &mut Option<u64>, time: u64) {
+        if !self.time_aligned || *second == Some(time / NANOS_PER_SECOND) {
+            return;
+        }
+        *second = Some(time / NANOS_PER_SECOND);
+        let synchronized = self.scheduler.clock().is_synchronized();
+        for stream in &mut self.streams {
+            let smp_sync = if synchronized {
+                stream.config.smp_sync
+            } else {
+                SampleSync::Internal
+            };
+            for asdu in &mut stream.packet.sampled_value.asdu {
+                asdu.smp_sync = smp_sync;
+            }
+        }
+    }
+
+    /// Starts the timing loop on a dedicated thread.
+    pub fn start(mut self) -> io::Result<EmulatorHandle<T>>
+    where
+        T: Send + 'static,
+    {
+        let shared = Arc::clone(&self.shared);
+        let streams = self.infos();
+        shared.running.store(true, Ordering::Release);
+        let thread = thread::Builder::new()
+            .name("sv-emulator".to_string())
+            .spawn(move || {
+                let result = self.run_while(None);
+                self.shared.running.store(false, Ordering::Release);
+                result.map(|_| self.transport)
+            })?;
+        Ok(EmulatorHandle {
+            shared,
+            streams,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Handle to an emulator running on its own thread. Dropping it stops the
+/// emulator without waiting for the thread.
+pub struct EmulatorHandle<T> {
+    shared: Arc<Shared>,
+    streams: Vec<StreamInfo>,
+    thread: Option<JoinHandle<io::Result<T>>>,
+}
+
+impl<T> EmulatorHandle<T> {
+    /// False once stopped or after the thread hit a send error.
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Acquire)
+    }
+
+    pub fn stats(&self) -> EmulatorStats {
+        stats(&self.shared, &self.streams)
+    }
+
+    pub fn reset_lateness(&self) {
+        self.shared.lateness.reset();
+    }
+
+    /// Control of stream `index`, in the order they were added.
+    pub fn control(&self, index: usize) -> Option<PublisherControl> {
+        self.streams.get(index).map(|stream| stream.control.clone())
+    }
+
+    /// Outcome of the real-time setup, once the thread applied it.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime.lock().unwrap().clone()
+    }
+
+    /// Stops after the batch in progress and returns the transport.
+    pub fn stop(mut self) -> io::Result<T> {
+        self.shared.running.store(false, Ordering::Release);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Emulator thread panicked")),
+            None => Err(io::Error::other("Emulator thread already joined")),
+        }
+    }
+}
+
+impl<T> Drop for EmulatorHandle<T> {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::publisher::scheduler::ClockNanosleep;
+    use crate::network::transport::memory::VirtualSwitch;
+    use crate::protocols::sampled_values::model::Phases;
+    use std::time::{Duration, SystemTime};
+
+    /// Counts the frames of each batch it is given.
+    #[derive(Default)]
+    struct BatchRecorder {
+        batches: Vec<Vec<Vec<u8>>>,
+    }
+
+    impl FrameTransport for BatchRecorder {
+        fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+            self.send_batch(&[data], timestamp)
+        }
+
+        fn send_batch(&mut self, frames: &[&[u8]], _: Option<SystemTime>) -> io::Result<()> {
+            self.batches
+                .push(frames.iter().map(|data| data.to_vec()).collect());
+            Ok(())
+        }
+
+        fn recv_frame(&mut self) -> io::Result<crate::network::transport::Frame> {
+            Err(io::ErrorKind::WouldBlock.into())
+        }
+
+        fn set_read_timeout(&mut self, _: Option<Duration>) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn stream(sv_id: &str, app_id: u16, sample_rate: u32) -> PublisherConfig {
+        PublisherConfig::builder()
+            .sv_id(sv_id)
+            .app_id(app_id)
+            .sample_rate(sample_rate)
+            .nonstandard_rate(true)
+            .waveform(|index: u64, _sample_rate: u32, measures: &mut Phases| {
+                measures.current.a.value = index as i32;
+            })
+            .build()
+            .unwrap()
+    }
+
+    fn scheduler() -> ClockNanosleep {
+        ClockNanosleep {
+            clock: Clock::Monotonic,
+        }
+    }
+
+    #[test]
+    fn streams_share_deadlines_and_batches() {
+        let mut emulator =
+            MergingUnitEmulator::with_transport(BatchRecorder::default(), scheduler(), false)
+                .unwrap();
+        emulator.add_stream(stream("MU01", 0x4000, 4000)).unwrap();
+        emulator.add_stream(stream("MU02", 0x4001, 4000)).unwrap();
+        emulator.add_stream(stream("MU03", 0x4002, 8000)).unwrap();
+        assert_eq!(
+            emulator.add_stream(stream("MU01", 0x4003, 4000)).err(),
+            Some(ConfigError::DuplicateSvId("MU01".to_string()))
+        );
+
+        // 8000 Hz wakes up twice per 4000 Hz frame: batches of 3 and 1.
+        assert_eq!(emulator.run(Some(4)).unwrap(), 8);
+        let stats = emulator.stats();
+        assert_eq!(stats.batches, 4);
+        assert_eq!(stats.streams[2].sv_id, "MU03");
+        assert_eq!(stats.streams[0].frames_sent, 2);
+        assert_eq!(stats.streams[2].frames_sent, 4);
+
+        let recorder = emulator.into_inner();
+        let sizes: Vec<usize> = recorder.batches.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![3, 1, 3, 1]);
+        let ids: Vec<(u16, u16)> = recorder.batches[2]
+            .iter()
+            .map(|data| {
+                let packet = Packet::from_bytes(data);
+                (
+                    packet.sampled_value.app_id,
+                    packet.sampled_value.asdu[0].smp_count,
+                )
+            })
+            .collect();
+        assert_eq!(ids, vec![(0x4000, 1), (0x4001, 1), (0x4002, 2)]);
+    }
+
+    #[test]
+    fn paused_stream_and_threaded_run() {
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let mut emulator =
+            MergingUnitEmulator::with_transport(switch.port(), scheduler(), false).unwrap();
+        emulator.add_stream(stream("MU01", 0x4000, 4000)).unwrap();
+        let paused = emulator.add_stream(stream("MU02", 0x4001, 4000)).unwrap();
+        paused.pause();
+
+        let handle = emulator.start().unwrap();
+        input
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+        // Counters follow each batch, so they cover the first 10 frames once
+        // the 11th arrives.
+        for _ in 0..11 {
+            let packet = Packet::from_bytes(&input.recv_frame().unwrap().data);
+            assert_eq!(packet.sampled_value.app_id, 0x4000);
+        }
+        assert!(handle.is_running());
+        let stats = handle.stats();
+        assert!(stats.streams[0].frames_sent >= 10);
+        assert_eq!(stats.streams[1].frames_sent, 0);
+        assert!(stats.streams[1].frames_skipped > 0);
+        handle.stop().unwrap();
+
+        assert!(MergingUnitEmulator::with_transport(switch.port(), scheduler(), true).is_err());
+    }
+}
@@ -1 +1,10 @@
+pub mod config;
+pub mod control;
+#[cfg(feature = "serde")]
+pub mod control_server;
+pub mod emulator;
+pub mod replay;
 pub mod sampled_value_pub;
+pub mod scheduler;
+#[cfg(feature = "tokio")]
+pub mod tokio_pub;
@@ -0,0 +1,423 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::network::{
+    capture::CaptureReader,
+    eth_types::EthTypes,
+    packet::Packet,
+    publisher::scheduler::{BusyWait, Scheduler},
+    transport::FrameTransport,
+};
+use crate::protocols::ethernet::model::{Ethernet, Vlan};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum VlanRewrite {
+    #[default]
+    Keep,
+    Set(Vlan),
+    Strip,
+}
+
+/// How a capture is replayed. Empty filters let every frame through; a
+/// non-empty filter only keeps Sampled Values frames that match it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReplayConfig {
+    /// Playback speed, 2.0 replays twice as fast as captured.
+    pub speed: f64,
+    /// Number of passes over the capture, `None` repeats forever.
+    pub loops: Option<u32>,
+    pub app_ids: Vec<u16>,
+    pub sv_ids: Vec<String>,
+    pub dst_mac: Option<[u8; 6]>,
+    pub vlan: VlanRewrite,
+    pub app_id: Option<u16>,
+}
+
+impl Default for ReplayConfig {
+    fn default() -> Self {
+        ReplayConfig {
+            speed: 1.0,
+            loops: Some(1),
+            app_ids: vec![],
+            sv_ids: vec![],
+            dst_mac: None,
+            vlan: VlanRewrite::Keep,
+            app_id: None,
+        }
+    }
+}
+
+struct ScheduledFrame {
+    data: Vec<u8>,
+    /// Nanoseconds after the first frame, already scaled by the speed factor.
+    offset: u64,
+}
+
+/// Replays a capture file with its original inter-frame timing. Frames are
+/// filtered and rewritten while loading so the send loop only waits and sends.
+/// The kept frames are held in memory, so the capture (after filtering) must
+/// fit in RAM.
+pub struct Replayer {
+    frames: Vec<ScheduledFrame>,
+    /// Offset of a pass over the capture from the previous one.
+    period: u64,
+    loops: Option<u32>,
+}
+
+impl Replayer {
+    pub fn open<P: AsRef<Path>>(path: P, config: ReplayConfig) -> io::Result<Self> {
+        Self::from_capture(CaptureReader::<BufReader<File>>::open(path)?, config)
+    }
+
+    pub fn from_capture<R: Read>(
+        capture: CaptureReader<R>,
+        config: ReplayConfig,
+    ) -> io::Result<Self> {
+        if !(config.speed > 0.0 && config.speed.is_finite()) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid replay speed {}", config.speed),
+            ));
+        }
+
+        let mut frames: Vec<ScheduledFrame> = vec![];
+        let mut first: Option<SystemTime> = None;
+        for frame in capture {
+            let frame = frame?;
+            if !Self::matches(&config, &frame.data) {
+                continue;
+            }
+            // Frames without a timestamp, or stamped before the previous
+            // one, go out right after it.
+            let previous = frames.last().map_or(0, |frame| frame.offset);
+            let offset = match (first, frame.timestamp) {
+                (Some(first), Some(timestamp)) => {
+                    let elapsed = timestamp
+                        .duration_since(first)
+                        .unwrap_or(Duration::ZERO)
+                        .as_nanos() as f64;
+                    ((elapsed / config.speed) as u64).max(previous)
+                }
+                _ => previous,
+            };
+            first = first.or(frame.timestamp);
+            frames.push(ScheduledFrame {
+                data: Self::rewrite(&config, &frame.data),
+                offset,
+            });
+        }
+
+        // Between passes, keep the spacing of the first two frames.
+        let loop_delay = frames.get(1).map_or(0, |frame| frame.offset);
+        let period = frames.last().map_or(0, |frame| frame.offset) + loop_delay;
+        Ok(Replayer {
+            frames,
+            period,
+            loops: config.loops,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Frames that do not decode as Sampled Values never match a filter.
+    fn matches(config: &ReplayConfig, data: &[u8]) -> bool {
+        if config.app_ids.is_empty() && config.sv_ids.is_empty() {
+            return true;
+        }
+        let Ok(packet) = Packet::try_from_bytes(data) else {
+            return false;
+        };
+        let app_id_matches =
+            config.app_ids.is_empty() || config.app_ids.contains(&packet.sampled_value.app_id);
+        let sv_id_matches = config.sv_ids.is_empty()
+            || packet
+                .sampled_value
+                .asdu
+                .iter()
+                .any(|asdu| config.sv_ids.contains(&asdu.sv_id));
+        app_id_matches && sv_id_matches
+    }
+
+    /// Frames without a complete Ethernet header are passed on unchanged.
+    fn rewrite(config: &ReplayConfig, data: &[u8]) -> Vec<u8> {
+        let Ok(mut ethernet) = Ethernet::try_from_bytes(data) else {
+            return data.to_vec();
+        };
+        let payload = &data[ethernet.header_len()..];
+        if let Some(dst_mac) = config.dst_mac {
+            ethernet.dst_mac = dst_mac;
+        }
+        match config.vlan {
+            VlanRewrite::Keep => {}
+            VlanRewrite::Set(vlan) => ethernet.vlan = Some(vlan),
+            VlanRewrite::Strip => ethernet.vlan = None,
+        }
+
+        let mut bytes = ethernet.to_bytes();
+        let app_id_start = bytes.len();
+        bytes.extend_from_slice(payload);
+        if let Some(app_id) = config.app_id
+            && BigEndian::read_u16(&ethernet.ether_type) == EthTypes::SampledValues as u16
+            && bytes.len() >= app_id_start + 2
+        {
+            BigEndian::write_u16(&mut bytes[app_id_start..app_id_start + 2], app_id);
+        }
+        bytes
+    }
+
+    /// Sends the capture on `transport` and returns the number of frames
+    /// sent. Never returns when looping forever, unless sending fails.
+    pub fn run<T: FrameTransport>(&self, transport: &mut T) -> io::Result<u64> {
+        self.run_with(transport, &mut BusyWait::default())
+    }
+
+    /// Like [`Replayer::run`], waiting for each frame with `scheduler`.
+    /// Deadlines are offsets from the first frame, so rounding never adds up.
+    pub fn run_with<T: FrameTransport, S: Scheduler + ?Sized>(
+        &self,
+        transport: &mut T,
+        scheduler: &mut S,
+    ) -> io::Result<u64> {
+        let start = scheduler.clock().now();
+        let mut pass_offset: u64 = 0;
+        let mut sent: u64 = 0;
+        let mut pass: u32 = 0;
+
+        while self.loops.is_none_or(|loops| pass < loops) && !self.frames.is_empty() {
+            for frame in &self.frames {
+                scheduler.wait_until(start + pass_offset + frame.offset)?;
+                transport.send_frame(&frame.data, None)?;
+                sent += 1;
+            }
+            pass += 1;
+            pass_offset += self.period;
+        }
+        transport.flush()?;
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::network::capture::pcap::PcapWriter;
+    use crate::network::transport::memory::VirtualSwitch;
+
+    fn sv_frame(app_id: u16, sv_id: u8) -> Vec<u8> {
+        let mut frame: Vec<u8> = vec![
+            0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, // MACs
+            0x88, 0xba, // Ethertype
+            0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
+            0x60, 0x5c, // PDU
+            0x80, 0x01, 0x01, // number of ASDU
+            0xa2, 0x57, // sequence of ASDU
+            0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, sv_id, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04,
+            0x00, 0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff,
+            0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+            0x00, 0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00,
+            0x00, 0x20, 0x00, // ASDU
+        ];
+        BigEndian::write_u16(&mut frame[14..16], app_id);
+        frame
+    }
+
+    fn capture(frames: &[Vec<u8>], spacing: Duration) -> Vec<u8> {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut writer = PcapWriter::new(vec![]).unwrap();
+        for (index, frame) in frames.iter().enumerate() {
+            writer
+                .write_frame(frame, start + spacing * index as u32)
+                .unwrap();
+        }
+        writer.into_inner()
+    }
+
+    fn received(port: &mut impl FrameTransport) -> Vec<Vec<u8>> {
+        port.set_read_timeout(Some(Duration::from_millis(10)))
+            .unwrap();
+        let mut frames = vec![];
+        while let Ok(frame) = port.recv_frame() {
+            frames.push(frame.data);
+        }
+        frames
+    }
+
+    #[test]
+    fn replay_keeps_timing_and_loops() {
+        let bytes = capture(
+            &[sv_frame(0x4000, b'0'), sv_frame(0x4001, b'1')],
+            Duration::from_millis(2),
+        );
+        let config = ReplayConfig {
+            loops: Some(2),
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), config).unwrap();
+        let switch = VirtualSwitch::new();
+        let mut output = switch.port();
+        let mut input = switch.port();
+
+        let start = Instant::now();
+        assert_eq!(replayer.run(&mut output).unwrap(), 4);
+        assert!(start.elapsed() >= Duration::from_millis(6));
+        assert_eq!(received(&mut input).len(), 4);
+    }
+
+    #[test]
+    fn speed_factor_shortens_delays() {
+        let bytes = capture(
+            &[sv_frame(0x4000, b'0'), sv_frame(0x4000, b'0')],
+            Duration::from_millis(10),
+        );
+        let config = ReplayConfig {
+            speed: 4.0,
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), config).unwrap();
+        assert_eq!(replayer.frames[1].offset, 2_500_000);
+    }
+
+    #[test]
+    fn offsets_do_not_accumulate_rounding() {
+        let frames = vec![sv_frame(0x4000, b'0'); 4];
+        let bytes = capture(&frames, Duration::from_nanos(10));
+        let config = ReplayConfig {
+            speed: 3.0,
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), config).unwrap();
+        let offsets: Vec<u64> = replayer.frames.iter().map(|frame| frame.offset).collect();
+        assert_eq!(offsets, [0, 3, 6, 10]);
+        assert_eq!(replayer.period, 13);
+    }
+
+    #[test]
+    fn filter_and_rewrite() {
+        let bytes = capture(
+            &[
+                sv_frame(0x4000, b'0'),
+                sv_frame(0x4001, b'1'),
+                sv_frame(0x4000, b'2'),
+            ],
+            Duration::from_micros(250),
+        );
+        let vlan = Vlan {
+            priority: 4,
+            dei: false,
+            id: 10,
+        };
+        let config = ReplayConfig {
+            app_ids: vec![0x4000],
+            sv_ids: vec!["4002".to_string()],
+            dst_mac: Some([0x01, 0x0c, 0xcd, 0x04, 0x00, 0xff]),
+            vlan: VlanRewrite::Set(vlan),
+            app_id: Some(0x4abc),
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), config).unwrap();
+        assert_eq!(replayer.len(), 1);
+
+        let switch = VirtualSwitch::new();
+        let mut output = switch.port();
+        let mut input = switch.port();
+        replayer.run(&mut output).unwrap();
+        let frames = received(&mut input);
+        let packet = Packet::from_bytes(&frames[0]);
+        assert_eq!(
+            packet.ethernet.dst_mac,
+            [0x01, 0x0c, 0xcd, 0x04, 0x00, 0xff]
+        );
+        assert_eq!(packet.ethernet.vlan, Some(vlan));
+        assert_eq!(packet.sampled_value.app_id, 0x4abc);
+        assert_eq!(packet.sampled_value.asdu[0].sv_id, "4002");
+    }
+
+    #[test]
+    fn undecodable_frames_do_not_abort_the_replay() {
+        let good = sv_frame(0x4000, b'0');
+        // 802.1Q tag announced, header cut off before the EtherType.
+        let truncated = vec![
+            0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x81, 0x00,
+            0x80, 0x01,
+        ];
+        let mut malformed = sv_frame(0x4000, b'1');
+        malformed.truncate(30);
+        let bytes = capture(
+            &[truncated.clone(), malformed.clone(), good.clone()],
+            Duration::from_micros(250),
+        );
+        let filtered = ReplayConfig {
+            app_ids: vec![0x4000],
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), filtered)
+                .unwrap();
+        assert_eq!(replayer.len(), 1);
+        assert_eq!(replayer.frames[0].data, good);
+
+        let rewritten = ReplayConfig {
+            dst_mac: Some([0x01, 0x0c, 0xcd, 0x04, 0x00, 0xff]),
+            app_id: Some(0x4abc),
+            ..Default::default()
+        };
+        let replayer =
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), rewritten)
+                .unwrap();
+        assert_eq!(replayer.len(), 3);
+        assert_eq!(replayer.frames[0].data, truncated);
+        assert_eq!(
+            replayer.frames[1].data[..6],
+            [0x01, 0x0c, 0xcd, 0x04, 0x00, 0xff]
+        );
+        assert_eq!(replayer.frames[1].data[14..16], [0x4a, 0xbc]);
+        assert_eq!(replayer.frames[1].data.len(), malformed.len());
+    }
+
+    #[test]
+    fn strip_vlan() {
+        let mut tagged = Ethernet::from_bytes(&sv_frame(0x4000, b'0'));
+        tagged.vlan = Some(Vlan {
+            priority: 4,
+            dei: false,
+            id: 1,
+        });
+        let mut frame = tagged.to_bytes();
+        frame.extend_from_slice(&sv_frame(0x4000, b'0')[14..]);
+        let config = ReplayConfig {
+            vlan: VlanRewrite::Strip,
+            ..Default::default()
+        };
+        assert_eq!(Replayer::rewrite(&config, &frame), sv_frame(0x4000, b'0'));
+    }
+
+    #[test]
+    fn reject_invalid_speed() {
+        let bytes = capture(&[], Duration::ZERO);
+        let config = ReplayConfig {
+            speed: 0.0,
+            ..Default::default()
+        };
+        assert!(
+            Replayer::from_capture(CaptureReader::new(bytes.as_slice()).unwrap(), config).is_err()
+        );
+    }
+}
@@ -1,76 +1,822 @@
-use std::time::Instant;
-
-//use libc::nanosleep;
+use std::io;
+#[cfg(feature = "serde")]
+use std::path::Path;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
 
+#[cfg(feature = "serde")]
+use crate::network::publisher::control_server::ControlServer;
 use crate::{
-    network::{packet::Packet, socket::RawSocket},
-    protocols::{ethernet::model::Ethernet, sampled_values::model::SampledValue},
+    network::{
+        clock::{Clock, NANOS_PER_SECOND},
+        eth_types::EthTypes,
+        packet::Packet,
+        publisher::{
+            config::{ConfigError, PublisherConfig},
+            control::PublisherControl,
+            scheduler::{BusyWait, LatenessStats, Scheduler, SharedLateness},
+        },
+        realtime::{RealtimeConfig, RealtimeReport},
+        socket::RawSocket,
+        transport::FrameTransport,
+    },
+    protocols::sampled_values::{
+        model::SampleSync,
+        waveform::{Channel, WaveformSource},
+    },
 };
 
 pub fn main() {
-    let socket = RawSocket::new("lo".to_string(), 0x88ba_u16);
-
-    let sv_bytes: &[u8] = &[
-        0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
-        0x60, 0x5c, // PDU
-        0x80, 0x01, 0x01, // number of ASDU
-        0xa2, 0x57, // sequence of ASDU
-        0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04, 0x00,
-        0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00,
-        0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00,
-        0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfc,
-        0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00, 0x00, 0x20, 0x00, // ASDU
-    ];
-    let mut config = Packet {
-        ether_type: [0x88, 0xba],
-        ethernet: Ethernet {
-            dst_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
-            src_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
-            ether_type: [0x88, 0xba],
-        },
-        sampled_value: SampledValue::from_bytes(sv_bytes),
+    let config = PublisherConfig::builder()
+        .interface("lo")
+        .app_id(0x4002)
+        .sv_id("4000")
+        .smp_sync(SampleSync::Local)
+        .realtime(Some(RealtimeConfig::new().fifo(80).lock_memory(true)))
+        .build()
+        .unwrap_or_else(|err| panic!("Invalid publisher configuration: {}", err));
+    let publisher =
+        SvPublisher::new(config).unwrap_or_else(|err| panic!("Error opening socket: {}", err));
+    #[cfg(feature = "serde")]
+    let _server = publisher
+        .serve_control(CONTROL_SOCKET)
+        .unwrap_or_else(|err| panic!("Error opening {}: {}", CONTROL_SOCKET, err));
+    let handle = publisher
+        .start()
+        .unwrap_or_else(|err| panic!("Error starting publisher: {}", err));
+    while handle.is_running() {
+        if let Some(report) = handle.realtime_report() {
+            print!("{}", report);
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    if let Err(err) = handle.join() {
+        panic!("Error sending packet: {}", err);
+    }
+}
+
+/// Control socket of the example publisher, see [`ControlServer`].
+#[cfg(feature = "serde")]
+const CONTROL_SOCKET: &str = "/tmp/crabiec61850-pub.sock";
+
+/// Sample rate of [`publish`] for packets that do not carry smpRate.
+const DEFAULT_SAMPLE_RATE: u16 = 4800;
+/// Samples per cycle of the waveform [`publish`] generates, as in 9-2LE.
+const SAMPLES_PER_CYCLE: u32 = 80;
+/// smpMod value for smpRate in samples per second.
+const SAMPLES_PER_SECOND: u16 = 1;
+
+/// Publishes the stream described by a [`PublisherConfig`]. Each frame carries
+/// `asdus_per_frame` consecutive samples from the configured waveform source.
+pub struct SvPublisher<T: FrameTransport = RawSocket> {
+    config: PublisherConfig,
+    transport: T,
+    packet: Packet,
+    sample: u64,
+    shared: Arc<Shared>,
+    control: PublisherControl,
+}
+
+/// State shared between a publisher thread and its handle.
+#[derive(Default)]
+struct Shared {
+    running: AtomicBool,
+    sent: AtomicU64,
+    skipped: AtomicU64,
+    lateness: SharedLateness,
+    realtime: Mutex<Option<RealtimeReport>>,
+}
+
+impl Shared {
+    fn lateness(&self) -> LatenessStats {
+        self.lateness.snapshot()
+    }
+
+    fn realtime(&self) -> Option<RealtimeReport> {
+        self.realtime.lock().unwrap().clone()
+    }
+
+    fn stats(&self, control: &PublisherControl) -> PublisherStats {
+        let lateness = self.lateness();
+        PublisherStats {
+            running: self.running.load(Ordering::Acquire),
+            paused: control.is_paused(),
+            simulation: control.simulation(),
+            frames_sent: self.sent.load(Ordering::Relaxed),
+            frames_skipped: self.skipped.load(Ordering::Relaxed),
+            lateness_min: lateness.min(),
+            lateness_max: lateness.max(),
+            lateness_mean: lateness.mean(),
+        }
+    }
+}
+
+/// Snapshot of a publisher, as reported by its control channel.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct PublisherStats {
+    pub running: bool,
+    pub paused: bool,
+    pub simulation: bool,
+    pub frames_sent: u64,
+    /// Frame deadlines left empty while paused.
+    pub frames_skipped: u64,
+    /// Scheduler lateness in nanoseconds, 0 before the first frame.
+    pub lateness_min: i64,
+    pub lateness_max: i64,
+    pub lateness_mean: f64,
+}
+
+impl SvPublisher<RawSocket> {
+    /// Validates `config` and opens a raw socket on its interface.
+    pub fn new(config: PublisherConfig) -> io::Result<Self> {
+        config.validate()?;
+        let socket = RawSocket::try_new(config.interface.clone(), EthTypes::SampledValues as u16)?;
+        Ok(Self::build(config, socket))
+    }
+}
+
+impl<T: FrameTransport> SvPublisher<T> {
+    /// Publishes on `transport` instead of opening the configured interface.
+    pub fn with_transport(config: PublisherConfig, transport: T) -> Result<Self, ConfigError> {
+        config.validate()?;
+        Ok(Self::build(config, transport))
+    }
+
+    fn build(config: PublisherConfig, transport: T) -> Self {
+        let packet = config.packet();
+        let control = PublisherControl::new(config.simulation);
+        SvPublisher {
+            config,
+            transport,
+            packet,
+            sample: 0,
+            shared: Arc::default(),
+            control,
+        }
+    }
+
+    pub fn config(&self) -> &PublisherConfig {
+        &self.config
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Lateness of the scheduler wake-ups since the publisher was created.
+    pub fn lateness(&self) -> LatenessStats {
+        self.shared.lateness()
+    }
+
+    /// Simulation bit and quality overrides, shared with the running
+    /// publisher.
+    pub fn control(&self) -> PublisherControl {
+        self.control.clone()
+    }
+
+    /// Outcome of the configured real-time setup, once publishing started.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime()
+    }
+
+    pub fn stats(&self) -> PublisherStats {
+        self.shared.stats(&self.control)
+    }
+
+    /// Accepts control commands for this publisher on a Unix socket at
+    /// `path`, see [`ControlServer`].
+    #[cfg(feature = "serde")]
+    pub fn serve_control<P: AsRef<Path>>(&self, path: P) -> io::Result<ControlServer> {
+        serve_control(path, &self.shared, &self.control)
+    }
+
+    /// Publishes on the calling thread. Stops after `frames` frame periods, or
+    /// never when `None`, and returns the number of frames sent, which is
+    /// less when paused.
+    pub fn run(&mut self, frames: Option<u64>) -> io::Result<u64> {
+        self.shared.running.store(true, Ordering::Release);
+        let result = self.run_while(frames);
+        self.shared.running.store(false, Ordering::Release);
+        result
+    }
+
+    fn run_while(&mut self, frames: Option<u64>) -> io::Result<u64> {
+        let SvPublisher {
+            config,
+            transport,
+            packet,
+            sample,
+            shared,
+            control,
+        } = self;
+        if let Some(realtime) = &config.realtime {
+            *shared.realtime.lock().unwrap() = Some(realtime.apply());
+        }
+        let period = FramePeriod {
+            samples_per_frame: config.asdus_per_frame as u64,
+            sample_rate: config.sample_rate as u64,
+        };
+        let clock = config.scheduler.clock();
+        let lead = config.launch_lead.unwrap_or(0);
+        let timeline = if config.time_aligned {
+            let timeline = Timeline::aligned(clock, period, lead);
+            *sample = timeline.first_frame * period.samples_per_frame;
+            timeline
+        } else {
+            Timeline::relative(clock, period, lead)
+        };
+        let synchronized = config.smp_sync;
+        let sample_rate = config.sample_rate as u64;
+        let mut first = true;
+        let next_frame = || {
+            // The clock sync state is refreshed once per second, on the frame
+            // that starts with smpCnt 0.
+            if config.time_aligned && (first || *sample % sample_rate == 0) {
+                let smp_sync = if clock.is_synchronized() {
+                    synchronized
+                } else {
+                    SampleSync::Internal
+                };
+                for asdu in &mut packet.sampled_value.asdu {
+                    asdu.smp_sync = smp_sync;
+                }
+            }
+            first = false;
+            let bytes = fill_frame(
+                &mut config.waveform,
+                control,
+                config.sample_rate,
+                packet,
+                sample,
+            );
+            (!control.is_paused()).then_some(bytes)
+        };
+        send_paced(
+            transport,
+            &mut config.scheduler,
+            config.launch_lead,
+            timeline,
+            frames,
+            shared,
+            next_frame,
+        )
+    }
+
+    /// Starts publishing on a dedicated thread.
+    pub fn start(mut self) -> io::Result<PublisherHandle<T>>
+    where
+        T: Send + 'static,
+    {
+        let shared = self.shared.clone();
+        let control = self.control.clone();
+        shared.running.store(true, Ordering::Release);
+        let thread = thread::Builder::new()
+            .name(format!("sv-pub-{}", self.config.sv_id))
+            .spawn(move || {
+                let result = self.run_while(None);
+                self.shared.running.store(false, Ordering::Release);
+                result.map(|_| self.transport)
+            })?;
+        Ok(PublisherHandle {
+            shared,
+            control,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// Applies the queued waveform changes, fills the ASDUs of `packet` with
+/// consecutive samples, applies the test signalling of `control` and encodes
+/// it. Qualities start out good on every sample, so a cleared override does
+/// not linger.
+pub(crate) fn fill_frame(
+    waveform: &mut Box<dyn WaveformSource>,
+    control: &PublisherControl,
+    sample_rate: u32,
+    packet: &mut Packet,
+    sample: &mut u64,
+) -> Vec<u8> {
+    control.adjust(waveform.as_mut(), *sample, sample_rate);
+    for asdu in &mut packet.sampled_value.asdu {
+        asdu.smp_count = (*sample % sample_rate as u64) as u16;
+        for channel in Channel::ALL {
+            channel.measurement_mut(&mut asdu.measures).quality = 0;
+        }
+        waveform.sample(*sample, sample_rate, &mut asdu.measures);
+        *sample += 1;
+    }
+    control.apply(waveform.as_ref(), packet);
+    packet.to_bytes()
+}
+
+#[cfg(feature = "serde")]
+fn serve_control<P: AsRef<Path>>(
+    path: P,
+    shared: &Arc<Shared>,
+    control: &PublisherControl,
+) -> io::Result<ControlServer> {
+    let shared = Arc::clone(shared);
+    let stats_control = control.clone();
+    ControlServer::bind(path, control.clone(), move || shared.stats(&stats_control))
+}
+
+/// Handle to a publisher running on its own thread. Dropping it stops the
+/// publisher without waiting for the thread.
+pub struct PublisherHandle<T> {
+    shared: Arc<Shared>,
+    control: PublisherControl,
+    thread: Option<JoinHandle<io::Result<T>>>,
+}
+
+impl<T> PublisherHandle<T> {
+    /// False once stopped or after the thread hit a send error.
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Acquire)
+    }
+
+    pub fn frames_sent(&self) -> u64 {
+        self.shared.sent.load(Ordering::Relaxed)
+    }
+
+    /// Snapshot of the scheduler lateness so far.
+    pub fn lateness(&self) -> LatenessStats {
+        self.shared.lateness()
+    }
+
+    pub fn reset_lateness(&self) {
+        self.shared.lateness.reset();
+    }
+
+    /// Outcome of the configured real-time setup, once the thread applied it.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime()
+    }
+
+    pub fn stats(&self) -> PublisherStats {
+        self.shared.stats(&self.control)
+    }
+
+    /// Accepts control commands for the running publisher on a Unix socket
+    /// at `path`, see [`ControlServer`].
+    #[cfg(feature = "serde")]
+    pub fn serve_control<P: AsRef<Path>>(&self, path: P) -> io::Result<ControlServer> {
+        serve_control(path, &self.shared, &self.control)
+    }
+
+    /// Simulation bit and quality overrides of the running publisher.
+    pub fn control(&self) -> PublisherControl {
+        self.control.clone()
+    }
+
+    /// Stops after the frame in progress and returns the transport.
+    pub fn stop(mut self) -> io::Result<T> {
+        self.shared.running.store(false, Ordering::Release);
+        self.wait()
+    }
+
+    /// Waits for the publisher thread, which only returns on error.
+    pub fn join(mut self) -> io::Result<T> {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> io::Result<T> {
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Publisher thread panicked")),
+            None => Err(io::Error::other("Publisher thread already joined")),
+        }
+    }
+}
+
+impl<T> Drop for PublisherHandle<T> {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+    }
+}
+
+/// Frame spacing as a ratio, so fractional periods such as 208 333.3 ns
+/// stay exact over long runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct FramePeriod {
+    pub(crate) samples_per_frame: u64,
+    pub(crate) sample_rate: u64,
+}
+
+impl FramePeriod {
+    /// Nanoseconds from the first frame to frame `frame`.
+    pub(crate) fn offset(&self, frame: u64) -> u64 {
+        (frame as u128 * self.samples_per_frame as u128 * NANOS_PER_SECOND as u128
+            / self.sample_rate as u128) as u64
+    }
+}
+
+/// Absolute frame deadlines: frame `n` of a run is due at
+/// `origin + period.offset(first_frame + n)`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Timeline {
+    origin: u64,
+    pub(crate) first_frame: u64,
+    period: FramePeriod,
+}
+
+impl Timeline {
+    /// Starts one period plus `lead` from now.
+    fn relative(clock: Clock, period: FramePeriod, lead: u64) -> Self {
+        Self::starting_at(clock.now() + lead + period.offset(1), period)
+    }
+
+    /// Frame 0 is due at `origin`.
+    pub(crate) fn starting_at(origin: u64, period: FramePeriod) -> Self {
+        Timeline {
+            origin,
+            first_frame: 0,
+            period,
+        }
+    }
+
+    /// Frames aligned to the top of the second of `clock`: frame 0 of each
+    /// second is due exactly on the second. Starts on the first frame at least
+    /// one period plus `lead` from now.
+    pub(crate) fn aligned(clock: Clock, period: FramePeriod, lead: u64) -> Self {
+        let earliest = clock.now() + lead + period.offset(1);
+        let origin = earliest - earliest % NANOS_PER_SECOND;
+        let frame_ns = period.samples_per_frame as u128 * NANOS_PER_SECOND as u128;
+        let first_frame =
+            ((earliest - origin) as u128 * period.sample_rate as u128).div_ceil(frame_ns) as u64;
+        Timeline {
+            origin,
+            first_frame,
+            period,
+        }
+    }
+
+    pub(crate) fn period(&self) -> FramePeriod {
+        self.period
+    }
+
+    pub(crate) fn deadline(&self, frame: u64) -> u64 {
+        self.origin + self.period.offset(self.first_frame + frame)
+    }
+}
+
+/// Sends the frames produced by `next_frame` on the absolute deadlines of
+/// `timeline`, so rounding and late wake-ups never accumulate. A frame of
+/// `None` leaves its deadline empty. Returns the number of frames sent.
+/// With `launch_lead`, wakes up that long before each deadline and hands the
+/// deadline to the transport as launch time instead of waiting for it. On a
+/// `RawSocket` with `enable_txtime` on the clock of `scheduler` the kernel
+/// ETF qdisc then sends the frame at that exact instant. Flushes the
+/// transport once stopped.
+fn send_paced<T, S, F>(
+    transport: &mut T,
+    scheduler: &mut S,
+    launch_lead: Option<u64>,
+    timeline: Timeline,
+    frames: Option<u64>,
+    shared: &Shared,
+    mut next_frame: F,
+) -> io::Result<u64>
+where
+    T: FrameTransport,
+    S: Scheduler + ?Sized,
+    F: FnMut() -> Option<Vec<u8>>,
+{
+    let clock = scheduler.clock();
+    let mut count: u64 = 0;
+    let mut sent: u64 = 0;
+    let mut bytes = next_frame();
+
+    while frames.is_none_or(|frames| count < frames) && shared.running.load(Ordering::Acquire) {
+        let deadline = timeline.deadline(count);
+        let lateness = scheduler.wait_until(deadline - launch_lead.unwrap_or(0))?;
+        shared.lateness.record(lateness);
+        match &bytes {
+            Some(bytes) => {
+                match launch_lead {
+                    Some(_) => transport.send_batch_at(&[bytes], clock, deadline)?,
+                    None => transport.send_frame(bytes, None)?,
+                }
+                sent += 1;
+                shared.sent.store(sent, Ordering::Relaxed);
+            }
+            None => {
+                shared.skipped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        count += 1;
+        bytes = next_frame();
+    }
+    transport.flush()?;
+    Ok(sent)
+}
+
+/// Sends `config` at the smpRate of its first ASDU on `transport`, 4800
+/// samples per second when it carries none or in another smpMod, advancing
+/// its samples after each frame. Stops after `frames` frames, or never when
+/// `None`.
+pub fn publish<T: FrameTransport>(
+    transport: &mut T,
+    config: &mut Packet,
+    frames: Option<u64>,
+) -> io::Result<()> {
+    publish_with(transport, config, frames, &mut BusyWait::default(), None)?;
+    Ok(())
+}
+
+/// Like [`publish`], paced by `scheduler`, optionally with launch times
+/// `launch_lead` nanoseconds after each wake-up. Returns the lateness
+/// statistics of the run.
+pub fn publish_with<T: FrameTransport, S: Scheduler + ?Sized>(
+    transport: &mut T,
+    config: &mut Packet,
+    frames: Option<u64>,
+    scheduler: &mut S,
+    launch_lead: Option<u64>,
+) -> io::Result<LatenessStats> {
+    let sample_rate = match config.sampled_value.asdu.first() {
+        Some(asdu) if asdu.smp_mode.is_none_or(|mode| mode == SAMPLES_PER_SECOND) => {
+            asdu.smp_rate.filter(|rate| *rate > 0)
+        }
+        _ => None,
+    }
+    .unwrap_or(DEFAULT_SAMPLE_RATE) as u32;
+    let period = FramePeriod {
+        samples_per_frame: config.sampled_value.asdu.len().max(1) as u64,
+        sample_rate: sample_rate as u64,
     };
-    let time_between_packets = 208_333;
-    let time_next_perf = 16;
-    let time_to_bytes_perf = 909;
-    let time_send_perf = 1_500;
-    let precision_diff_time = 55_750;
-    let _tm_spec = libc::timespec {
-        tv_sec: 0,
-        tv_nsec: time_between_packets
-            - time_next_perf
-            - time_to_bytes_perf
-            - time_send_perf
-            - precision_diff_time,
+    let mut first = true;
+    let next_frame = || {
+        if !first {
+            config.sampled_value.advance(sample_rate, SAMPLES_PER_CYCLE);
+        }
+        first = false;
+        Some(config.to_bytes())
     };
-    let mut last = Instant::now();
-    let mut now = Instant::now();
-    let mut bytes = config.to_bytes();
-    let mut diff: i64;
-    let _time_sleep = time_between_packets - 2;
-    let mut compensation: i64 = 0;
-
-    loop {
-        // sleep
-        // unsafe { nanosleep(&_tm_spec, core::ptr::null_mut()) }; // uncomment import nanosleep
-        // socket.send(&config.to_bytes());
-        // config.sampled_value.next();
-        // end sleep
-
-        // busy-wait
-        diff = now.duration_since(last).as_nanos() as i64;
-        if diff >= _time_sleep-compensation {
-            socket.send(&bytes);
-            compensation = diff - _time_sleep;
-            if compensation < 0 {
-                compensation = 0;
-            }
-            last = now;
-            config.sampled_value.next();
-            bytes = config.to_bytes();
+    let shared = Shared::default();
+    shared.running.store(true, Ordering::Release);
+    let timeline = Timeline::relative(scheduler.clock(), period, launch_lead.unwrap_or(0));
+    send_paced(
+        transport,
+        scheduler,
+        launch_lead,
+        timeline,
+        frames,
+        &shared,
+        next_frame,
+    )?;
+    Ok(shared.lateness())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::clock::Clock;
+    use crate::network::publisher::scheduler::{ClockNanosleep, Hybrid};
+    use crate::network::realtime::RealtimeStep;
+    use crate::network::transport::memory::VirtualSwitch;
+    use crate::protocols::{
+        ethernet::model::Ethernet,
+        sampled_values::model::{Phases, SampledValue},
+    };
+
+    fn default_packet() -> Packet {
+        let sv_bytes: &[u8] = &[
+            0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
+            0x60, 0x5c, // PDU
+            0x80, 0x01, 0x01, // number of ASDU
+            0xa2, 0x57, // sequence of ASDU
+            0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04,
+            0x00, 0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff,
+            0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+            0x00, 0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00,
+            0x00, 0x20, 0x00, // ASDU
+        ];
+        Packet {
+            ether_type: [0x88, 0xba],
+            ethernet: Ethernet {
+                dst_mac: [0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01],
+                src_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+                vlan: None,
+                ether_type: [0x88, 0xba],
+            },
+            sampled_value: SampledValue::from_bytes(sv_bytes),
+        }
+    }
+
+    #[test]
+    fn publisher_sends_consecutive_samples() {
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let config = PublisherConfig::builder()
+            .app_id(0x4001)
+            .sv_id("MU01")
+            .sample_rate(4)
+            .nonstandard_rate(true)
+            .asdus_per_frame(2)
+            .scheduler(ClockNanosleep {
+                clock: Clock::Monotonic,
+            })
+            .waveform(|index: u64, _sample_rate: u32, measures: &mut Phases| {
+                measures.current.a.value = index as i32;
+            })
+            .realtime(Some(RealtimeConfig::new().prefault_stack(16 * 1024)))
+            .build()
+            .unwrap();
+        let mut publisher = SvPublisher::with_transport(config, switch.port()).unwrap();
+        assert_eq!(publisher.realtime_report(), None);
+
+        assert_eq!(publisher.run(Some(3)).unwrap(), 3);
+        assert_eq!(publisher.lateness().count(), 3);
+        let report = publisher.realtime_report().unwrap();
+        assert_eq!(report.applied, vec![RealtimeStep::StackPrefault]);
+
+        let samples: Vec<(u16, i32)> = (0..3)
+            .flat_map(|_| {
+                Packet::from_bytes(&input.recv_frame().unwrap().data)
+                    .sampled_value
+                    .asdu
+            })
+            .map(|asdu| (asdu.smp_count, asdu.measures.current.a.value))
+            .collect();
+        assert_eq!(
+            samples,
+            vec![(0, 0), (1, 1), (2, 2), (3, 3), (0, 4), (1, 5)]
+        );
+    }
+
+    #[test]
+    fn control_switches_simulation_and_quality() {
+        use crate::protocols::sampled_values::quality::{
+            OVERFLOW, QualityOverride, TEST, VALIDITY_QUESTIONABLE, Validity,
+        };
+
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let config = PublisherConfig::builder()
+            .simulation(true)
+            .waveform(|_index: u64, _sample_rate: u32, measures: &mut Phases| {
+                measures.voltage.c.quality |= OVERFLOW;
+            })
+            .build()
+            .unwrap();
+        let mut publisher = SvPublisher::with_transport(config, switch.port()).unwrap();
+        let control = publisher.control();
+        control.set_quality(
+            Channel::Va,
+            QualityOverride {
+                validity: Some(Validity::Questionable),
+                test: Some(true),
+            },
+        );
+
+        publisher.run(Some(1)).unwrap();
+        let sampled_value = Packet::from_bytes(&input.recv_frame().unwrap().data).sampled_value;
+        assert!(sampled_value.simulation);
+        let measures = sampled_value.asdu[0].measures;
+        assert_eq!(measures.voltage.a.quality, VALIDITY_QUESTIONABLE | TEST);
+        assert_eq!(measures.voltage.c.quality, OVERFLOW);
+
+        control.set_simulation(false);
+        control.clear_quality();
+        publisher.run(Some(1)).unwrap();
+        let sampled_value = Packet::from_bytes(&input.recv_frame().unwrap().data).sampled_value;
+        assert!(!sampled_value.simulation);
+        assert_eq!(sampled_value.asdu[0].measures.voltage.a.quality, 0);
+    }
+
+    #[test]
+    fn time_aligned_smp_count_follows_the_second() {
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let config = PublisherConfig::builder()
+            .sample_rate(4800)
+            .smp_sync(SampleSync::Global)
+            .time_aligned(true)
+            .scheduler(ClockNanosleep {
+                clock: Clock::Realtime,
+            })
+            .launch_lead(Some(0))
+            .build()
+            .unwrap();
+        let mut publisher = SvPublisher::with_transport(config, switch.port()).unwrap();
+
+        publisher.run(Some(5)).unwrap();
+
+        for _ in 0..5 {
+            let frame = input.recv_frame().unwrap();
+            let deadline = Clock::Realtime.from_system_time(frame.timestamp.unwrap());
+            let asdu = &Packet::from_bytes(&frame.data).sampled_value.asdu[0];
+            let expected = asdu.smp_count as u64 * NANOS_PER_SECOND / 4800;
+            assert!((deadline % NANOS_PER_SECOND).abs_diff(expected) <= 1);
+            assert!(matches!(
+                asdu.smp_sync,
+                SampleSync::Global | SampleSync::Internal
+            ));
+        }
+    }
+
+    #[test]
+    fn start_and_stop_publisher_thread() {
+        let switch = VirtualSwitch::new();
+        let mut input = switch.port();
+        let config = PublisherConfig::builder()
+            .scheduler(Hybrid {
+                clock: Clock::Monotonic,
+                wake_ahead: 50_000,
+            })
+            .build()
+            .unwrap();
+        let handle = SvPublisher::with_transport(config, switch.port())
+            .unwrap()
+            .start()
+            .unwrap();
+
+        let frame = input.recv_frame().unwrap();
+        assert_eq!(Packet::from_bytes(&frame.data).sampled_value.app_id, 0x4000);
+        assert!(handle.is_running());
+        handle.stop().unwrap();
+    }
+
+    #[test]
+    fn launch_times_follow_absolute_deadlines() {
+        let switch = VirtualSwitch::new();
+        let mut output = switch.port();
+        let mut input = switch.port();
+        let mut scheduler = ClockNanosleep {
+            clock: Clock::Realtime,
+        };
+
+        let lateness = publish_with(
+            &mut output,
+            &mut default_packet(),
+            Some(4),
+            &mut scheduler,
+            Some(100_000),
+        )
+        .unwrap();
+        assert_eq!(lateness.count(), 4);
+
+        let launch_times: Vec<u64> = (0..4)
+            .map(|_| {
+                Clock::Realtime.from_system_time(input.recv_frame().unwrap().timestamp.unwrap())
+            })
+            .collect();
+        let gaps: Vec<u64> = launch_times.windows(2).map(|w| w[1] - w[0]).collect();
+        assert!(gaps.iter().all(|gap| *gap == 208_333 || *gap == 208_334));
+        assert_eq!(launch_times[3] - launch_times[0], 625_000);
+    }
+
+    #[test]
+    fn legacy_publish_follows_the_carried_rate() {
+        let switch = VirtualSwitch::new();
+        let mut output = switch.port();
+        let mut input = switch.port();
+        let mut packet = default_packet();
+        for asdu in &mut packet.sampled_value.asdu {
+            asdu.smp_rate = Some(4000);
+            asdu.smp_mode = None;
+            asdu.smp_count = 3998;
+        }
+
+        let start = std::time::Instant::now();
+        publish(&mut output, &mut packet, Some(3)).unwrap();
+        assert!(start.elapsed() >= Duration::from_micros(500));
+        let smp_counts: Vec<u16> = (0..3)
+            .map(|_| {
+                let frame = input.recv_frame().unwrap();
+                Packet::from_bytes(&frame.data).sampled_value.asdu[0].smp_count
+            })
+            .collect();
+        assert_eq!(smp_counts, [3998, 3999, 0]);
+    }
+
+    #[test]
+    fn hybrid_scheduler_sends_every_frame() {
+        let switch = VirtualSwitch::new();
+        let mut output = switch.port();
+        let mut input = switch.port();
+        let mut scheduler = Hybrid {
+            clock: Clock::Monotonic,
+            wake_ahead: 50_000,
+        };
+        let mut packet = default_packet();
+
+        let lateness =
+            publish_with(&mut output, &mut packet, Some(3), &mut scheduler, None).unwrap();
+        assert!(lateness.min() >= 0);
+
+        for smp_count in 0..3 {
+            let frame = input.recv_frame().unwrap();
+            let received = Packet::from_bytes(&frame.data);
+            assert_eq!(received.sampled_value.asdu[0].smp_count, smp_count);
         }
-        now = Instant::now();
-        // end busy-wait
     }
 }
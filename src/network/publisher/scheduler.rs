@@ -0,0 +1,361 @@
+use std::fmt;
+use std::io;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use crate::network::clock::{Clock, NANOS_PER_SECOND};
+
+/// Waits for absolute deadlines, in nanoseconds of `clock()`. Callers compute
+/// each deadline from a fixed start, so a late frame never delays the next.
+pub trait Scheduler: Send {
+    fn clock(&self) -> Clock;
+
+    /// Blocks until `deadline` and returns how late it woke up, in
+    /// nanoseconds. Returns at once with the lateness if it already passed.
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64>;
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for &mut S {
+    fn clock(&self) -> Clock {
+        (**self).clock()
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        (**self).wait_until(deadline)
+    }
+}
+
+impl<S: Scheduler + ?Sized> Scheduler for Box<S> {
+    fn clock(&self) -> Clock {
+        (**self).clock()
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        (**self).wait_until(deadline)
+    }
+}
+
+fn lateness(clock: Clock, deadline: u64) -> i64 {
+    clock.now() as i64 - deadline as i64
+}
+
+fn spin_until(clock: Clock, deadline: u64) {
+    while clock.now() < deadline {
+        std::hint::spin_loop();
+    }
+}
+
+/// Spins on a full core until the deadline. Most precise, most expensive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusyWait {
+    pub clock: Clock,
+}
+
+impl Default for BusyWait {
+    fn default() -> Self {
+        BusyWait {
+            clock: Clock::Monotonic,
+        }
+    }
+}
+
+impl Scheduler for BusyWait {
+    fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        spin_until(self.clock, deadline);
+        Ok(lateness(self.clock, deadline))
+    }
+}
+
+/// Relative `nanosleep` for the time left to the deadline. The remaining time
+/// is recomputed on every call, so only the wake-up latency is lost.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Nanosleep {
+    pub clock: Clock,
+}
+
+impl Scheduler for Nanosleep {
+    fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        loop {
+            let now = self.clock.now();
+            if now >= deadline {
+                return Ok(now as i64 - deadline as i64);
+            }
+            let remaining = deadline - now;
+            let time = libc::timespec {
+                tv_sec: (remaining / NANOS_PER_SECOND) as libc::time_t,
+                tv_nsec: (remaining % NANOS_PER_SECOND) as libc::c_long,
+            };
+            if unsafe { libc::nanosleep(&time, std::ptr::null_mut()) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+/// Absolute `clock_nanosleep(TIMER_ABSTIME)`, immune to the time spent
+/// between computing the deadline and going to sleep.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClockNanosleep {
+    pub clock: Clock,
+}
+
+impl Scheduler for ClockNanosleep {
+    fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        self.clock.sleep_until(deadline)?;
+        Ok(lateness(self.clock, deadline))
+    }
+}
+
+/// Sleeps with `clock_nanosleep(TIMER_ABSTIME)` until `wake_ahead`
+/// nanoseconds before the deadline to absorb the wake-up latency, then spins
+/// the rest of the way.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Hybrid {
+    pub clock: Clock,
+    pub wake_ahead: u64,
+}
+
+impl Scheduler for Hybrid {
+    fn clock(&self) -> Clock {
+        self.clock
+    }
+
+    fn wait_until(&mut self, deadline: u64) -> io::Result<i64> {
+        self.clock
+            .sleep_until(deadline.saturating_sub(self.wake_ahead))?;
+        spin_until(self.clock, deadline);
+        Ok(lateness(self.clock, deadline))
+    }
+}
+
+/// Number of histogram buckets. Bucket 0 counts wake-ups less than 1 µs late
+/// (or early), bucket `i` those late by `[2^(i-1), 2^i)` µs and the last one
+/// everything from about 1 ms.
+pub const LATENESS_BUCKETS: usize = 12;
+
+/// Per-frame lateness of a scheduler, in nanoseconds.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LatenessStats {
+    count: u64,
+    min: i64,
+    max: i64,
+    sum: i128,
+    histogram: [u64; LATENESS_BUCKETS],
+}
+
+impl LatenessStats {
+    pub fn record(&mut self, lateness: i64) {
+        if self.count == 0 {
+            self.min = lateness;
+            self.max = lateness;
+        } else {
+            self.min = self.min.min(lateness);
+            self.max = self.max.max(lateness);
+        }
+        self.count += 1;
+        self.sum += lateness as i128;
+        self.histogram[lateness_bucket(lateness)] += 1;
+    }
+
+    pub fn reset(&mut self) {
+        *self = LatenessStats::default();
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> i64 {
+        self.min
+    }
+
+    pub fn max(&self) -> i64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum as f64 / self.count as f64
+        }
+    }
+
+    pub fn histogram(&self) -> &[u64; LATENESS_BUCKETS] {
+        &self.histogram
+    }
+
+    /// Upper bound of histogram bucket `bucket` in nanoseconds, `None` for
+    /// the last, unbounded one.
+    pub fn bucket_limit(bucket: usize) -> Option<i64> {
+        (bucket < LATENESS_BUCKETS - 1).then(|| 1_000 << bucket)
+    }
+}
+
+fn lateness_bucket(lateness: i64) -> usize {
+    let micros = (lateness.max(0) / 1_000) as u64;
+    ((u64::BITS - micros.leading_zeros()) as usize).min(LATENESS_BUCKETS - 1)
+}
+
+/// [`LatenessStats`] recorded with atomics, so a send loop never waits for
+/// a thread reading or resetting them. A snapshot taken while a frame is
+/// recorded may count that frame in some figures only.
+#[derive(Debug)]
+pub(crate) struct SharedLateness {
+    count: AtomicU64,
+    min: AtomicI64,
+    max: AtomicI64,
+    sum: AtomicI64,
+    histogram: [AtomicU64; LATENESS_BUCKETS],
+}
+
+impl Default for SharedLateness {
+    fn default() -> Self {
+        SharedLateness {
+            count: AtomicU64::new(0),
+            min: AtomicI64::new(i64::MAX),
+            max: AtomicI64::new(i64::MIN),
+            sum: AtomicI64::new(0),
+            histogram: Default::default(),
+        }
+    }
+}
+
+impl SharedLateness {
+    pub(crate) fn record(&self, lateness: i64) {
+        self.min.fetch_min(lateness, Ordering::Relaxed);
+        self.max.fetch_max(lateness, Ordering::Relaxed);
+        self.sum.fetch_add(lateness, Ordering::Relaxed);
+        self.histogram[lateness_bucket(lateness)].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Release);
+    }
+
+    pub(crate) fn snapshot(&self) -> LatenessStats {
+        let count = self.count.load(Ordering::Acquire);
+        if count == 0 {
+            return LatenessStats::default();
+        }
+        LatenessStats {
+            count,
+            min: self.min.load(Ordering::Relaxed),
+            max: self.max.load(Ordering::Relaxed),
+            sum: self.sum.load(Ordering::Relaxed) as i128,
+            histogram: self
+                .histogram
+                .each_ref()
+                .map(|bucket| bucket.load(Ordering::Relaxed)),
+        }
+    }
+
+    pub(crate) fn reset(&self) {
+        self.count.store(0, Ordering::Release);
+        self.min.store(i64::MAX, Ordering::Relaxed);
+        self.max.store(i64::MIN, Ordering::Relaxed);
+        self.sum.store(0, Ordering::Relaxed);
+        for bucket in &self.histogram {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+impl fmt::Display for LatenessStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} frames, lateness min {} ns, max {} ns, mean {:.0} ns",
+            self.count,
+            self.min,
+            self.max,
+            self.mean()
+        )?;
+        for (bucket, count) in self.histogram.iter().enumerate() {
+            match LatenessStats::bucket_limit(bucket) {
+                Some(limit) => writeln!(f, "  < {:>7} ns: {}", limit, count)?,
+                None => writeln!(f, "  >= {:>6} ns: {}", 1_000 << (bucket - 1), count)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedulers_reach_deadline() {
+        let clock = Clock::Monotonic;
+        let mut schedulers: Vec<Box<dyn Scheduler>> = vec![
+            Box::new(BusyWait { clock }),
+            Box::new(Nanosleep { clock }),
+            Box::new(ClockNanosleep { clock }),
+            Box::new(Hybrid {
+                clock,
+                wake_ahead: 50_000,
+            }),
+        ];
+        for scheduler in &mut schedulers {
+            let deadline = clock.now() + 300_000;
+            let lateness = scheduler.wait_until(deadline).unwrap();
+            assert!(lateness >= 0);
+            assert!(clock.now() >= deadline);
+        }
+    }
+
+    #[test]
+    fn past_deadline_reports_lateness() {
+        let mut scheduler = BusyWait::default();
+        let deadline = scheduler.clock().now() - 1_000_000;
+        assert!(scheduler.wait_until(deadline).unwrap() >= 1_000_000);
+    }
+
+    #[test]
+    fn lateness_statistics() {
+        let mut stats = LatenessStats::default();
+        for lateness in [-200, 500, 1_500, 3_000, 5_000_000] {
+            stats.record(lateness);
+        }
+        assert_eq!(stats.count(), 5);
+        assert_eq!(stats.min(), -200);
+        assert_eq!(stats.max(), 5_000_000);
+        assert_eq!(stats.mean(), 1_000_960.0);
+        assert_eq!(stats.histogram()[0], 2);
+        assert_eq!(stats.histogram()[1], 1);
+        assert_eq!(stats.histogram()[2], 1);
+        assert_eq!(stats.histogram()[LATENESS_BUCKETS - 1], 1);
+        assert_eq!(LatenessStats::bucket_limit(2), Some(4_000));
+
+        stats.reset();
+        assert_eq!(stats.count(), 0);
+    }
+
+    #[test]
+    fn shared_lateness_matches_local_statistics() {
+        let shared = SharedLateness::default();
+        let mut local = LatenessStats::default();
+        assert_eq!(shared.snapshot(), local);
+        for lateness in [-200, 500, 1_500, 3_000, 5_000_000] {
+            shared.record(lateness);
+            local.record(lateness);
+        }
+        assert_eq!(shared.snapshot(), local);
+        shared.reset();
+        assert_eq!(shared.snapshot(), LatenessStats::default());
+        shared.record(700);
+        assert_eq!(shared.snapshot().min(), 700);
+    }
+}
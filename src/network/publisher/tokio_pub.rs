@@ -0,0 +1,99 @@
+use std::io;
+use std::time::Duration;
+
+use tokio::time::{MissedTickBehavior, interval};
+
+use crate::network::{async_socket::AsyncRawSocket, packet::Packet};
+
+/// Sampled Values publisher for tokio runtimes.
+///
+/// Tokio timers have millisecond resolution, so frames due within the same
+/// tick are sent back to back: the average rate is kept but the spacing is
+/// not. Use `sampled_value_pub` when inter-frame spacing matters.
+pub struct SvPublisher {
+    socket: AsyncRawSocket,
+    packet: Packet,
+    period: Duration,
+}
+
+impl SvPublisher {
+    pub fn new(socket: AsyncRawSocket, packet: Packet, period: Duration) -> Self {
+        SvPublisher {
+            socket,
+            packet,
+            period,
+        }
+    }
+
+    pub fn packet(&self) -> &Packet {
+        &self.packet
+    }
+
+    /// Sends the current frame and advances the samples.
+    pub async fn publish(&mut self) -> io::Result<()> {
+        self.socket.send(&self.packet.to_bytes()).await?;
+        self.packet.sampled_value.next();
+        Ok(())
+    }
+
+    /// Publishes one frame per period until an error occurs. Dropping the
+    /// returned future stops the publisher.
+    pub async fn run(&mut self) -> io::Result<()> {
+        let mut ticker = interval(self.period);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+        loop {
+            ticker.tick().await;
+            self.publish().await?;
+        }
+    }
+
+    pub fn into_inner(self) -> (AsyncRawSocket, Packet) {
+        (self.socket, self.packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::time::timeout;
+
+    use crate::network::async_socket::loopback;
+    use crate::network::publisher::config::PublisherConfig;
+
+    #[tokio::test]
+    async fn publishes_consecutive_samples() {
+        let (Some(socket), Some(receiver)) = (loopback(), loopback()) else {
+            return;
+        };
+        let packet = PublisherConfig::builder()
+            .app_id(0x4e02)
+            .build()
+            .unwrap()
+            .packet();
+        let mut publisher = SvPublisher::new(socket, packet, Duration::from_micros(250));
+        // Stops once the future is dropped.
+        assert!(
+            timeout(Duration::from_millis(5), publisher.run())
+                .await
+                .is_err()
+        );
+        let published = publisher.packet().sampled_value.asdu[0].smp_count;
+        assert!(published >= 3);
+
+        let mut counts = vec![];
+        timeout(Duration::from_secs(2), async {
+            while counts.len() < 3 {
+                let bytes = receiver.recv().await.unwrap();
+                if let Ok(packet) = Packet::try_from_bytes(&bytes)
+                    && packet.sampled_value.app_id == 0x4e02
+                {
+                    counts.push(packet.sampled_value.asdu[0].smp_count);
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(counts, [0, 1, 2]);
+    }
+}
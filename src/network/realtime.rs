@@ -0,0 +1,276 @@
+use std::fmt;
+use std::io;
+
+use crate::network::clock::Clock;
+use crate::network::publisher::scheduler::{ClockNanosleep, LatenessStats, Scheduler};
+
+/// Size of the memory pages touched when prefaulting.
+const PAGE_SIZE: usize = 4096;
+
+/// Real-time setup of the calling thread: CPU pinning, SCHED_FIFO and locked,
+/// prefaulted memory, so a publisher or subscriber loop is not preempted or
+/// stalled on page faults. Every step is optional and [`RealtimeConfig::apply`]
+/// goes on with the others when one fails, typically for lack of privileges.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RealtimeConfig {
+    /// CPUs the thread may run on. Empty keeps the inherited affinity.
+    pub cpus: Vec<usize>,
+    /// SCHED_FIFO priority, 1 to 99. Needs CAP_SYS_NICE or an RLIMIT_RTPRIO
+    /// at least that high.
+    pub priority: Option<i32>,
+    /// Locks current and future memory with `mlockall`. Needs CAP_IPC_LOCK
+    /// or a large enough RLIMIT_MEMLOCK.
+    pub lock_memory: bool,
+    /// Bytes of stack touched up front, so its pages are resident (and
+    /// locked with `lock_memory`). Must stay below the thread stack size.
+    pub prefault_stack: usize,
+}
+
+/// Step of a [`RealtimeConfig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RealtimeStep {
+    Affinity,
+    Scheduler,
+    MemoryLock,
+    StackPrefault,
+}
+
+/// A step that could not be applied, and why.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RealtimeWarning {
+    pub step: RealtimeStep,
+    pub kind: io::ErrorKind,
+    pub message: String,
+}
+
+impl fmt::Display for RealtimeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hint = match (self.step, self.kind) {
+            (RealtimeStep::Scheduler, io::ErrorKind::PermissionDenied) => {
+                ", grant CAP_SYS_NICE or raise RLIMIT_RTPRIO (ulimit -r)"
+            }
+            (RealtimeStep::MemoryLock, io::ErrorKind::PermissionDenied)
+            | (RealtimeStep::MemoryLock, io::ErrorKind::OutOfMemory) => {
+                ", grant CAP_IPC_LOCK or raise RLIMIT_MEMLOCK (ulimit -l)"
+            }
+            _ => "",
+        };
+        write!(
+            f,
+            "Real-time setup degraded, {:?} not applied: {}{}",
+            self.step, self.message, hint
+        )
+    }
+}
+
+/// Outcome of [`RealtimeConfig::apply`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RealtimeReport {
+    pub applied: Vec<RealtimeStep>,
+    pub warnings: Vec<RealtimeWarning>,
+}
+
+impl RealtimeReport {
+    /// True when every requested step was applied.
+    pub fn is_complete(&self) -> bool {
+        self.warnings.is_empty()
+    }
+
+    fn record(&mut self, step: RealtimeStep, result: io::Result<()>) {
+        match result {
+            Ok(()) => self.applied.push(step),
+            Err(err) => self.warnings.push(RealtimeWarning {
+                step,
+                kind: err.kind(),
+                message: err.to_string(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for RealtimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Real-time steps applied: {:?}", self.applied)?;
+        for warning in &self.warnings {
+            writeln!(f, "{}", warning)?;
+        }
+        Ok(())
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl RealtimeConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cpus(mut self, cpus: impl IntoIterator<Item = usize>) -> Self {
+        self.cpus = cpus.into_iter().collect();
+        self
+    }
+
+    pub fn fifo(mut self, priority: i32) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn lock_memory(mut self, lock_memory: bool) -> Self {
+        self.lock_memory = lock_memory;
+        self
+    }
+
+    pub fn prefault_stack(mut self, bytes: usize) -> Self {
+        self.prefault_stack = bytes;
+        self
+    }
+
+    /// Applies the configuration to the calling thread. Memory is locked
+    /// before the stack is prefaulted, so the touched pages stay resident.
+    /// Failed steps are left in the report for the caller to print.
+    pub fn apply(&self) -> RealtimeReport {
+        let mut report = RealtimeReport::default();
+        if !self.cpus.is_empty() {
+            report.record(RealtimeStep::Affinity, set_affinity(&self.cpus));
+        }
+        if let Some(priority) = self.priority {
+            report.record(RealtimeStep::Scheduler, set_fifo(priority));
+        }
+        if self.lock_memory {
+            report.record(RealtimeStep::MemoryLock, lock_memory());
+        }
+        if self.prefault_stack > 0 {
+            prefault_stack(self.prefault_stack);
+            report.record(RealtimeStep::StackPrefault, Ok(()));
+        }
+        report
+    }
+}
+
+/// Pins the calling thread to `cpus`.
+pub fn set_affinity(cpus: &[usize]) -> io::Result<()> {
+    let max = libc::CPU_SETSIZE as usize;
+    if let Some(cpu) = cpus.iter().find(|cpu| **cpu >= max) {
+        return Err(invalid(format!(
+            "CPU {} is beyond the {} CPU set",
+            cpu, max
+        )));
+    }
+    let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    for cpu in cpus {
+        unsafe { libc::CPU_SET(*cpu, &mut set) };
+    }
+    // Pid 0 is the calling thread.
+    let result = unsafe { libc::sched_setaffinity(0, size_of::<libc::cpu_set_t>(), &set) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Switches the calling thread to SCHED_FIFO at `priority`.
+pub fn set_fifo(priority: i32) -> io::Result<()> {
+    let (min, max) = unsafe {
+        (
+            libc::sched_get_priority_min(libc::SCHED_FIFO),
+            libc::sched_get_priority_max(libc::SCHED_FIFO),
+        )
+    };
+    if !(min..=max).contains(&priority) {
+        return Err(invalid(format!(
+            "SCHED_FIFO priority {} is outside {}..={}",
+            priority, min, max
+        )));
+    }
+    let param = libc::sched_param {
+        sched_priority: priority,
+    };
+    let result =
+        unsafe { libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param) };
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::from_raw_os_error(result))
+    }
+}
+
+/// Locks the current and future memory of the process.
+pub fn lock_memory() -> io::Result<()> {
+    if unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
+
+/// Touches `bytes` of the stack below the caller, one page at a time.
+#[inline(never)]
+pub fn prefault_stack(bytes: usize) {
+    let mut page = [0u8; PAGE_SIZE];
+    std::hint::black_box(&mut page);
+    if bytes > PAGE_SIZE {
+        prefault_stack(bytes - PAGE_SIZE);
+    }
+    // Keeps the frame alive across the call, so it is not turned into a loop
+    // reusing one page.
+    std::hint::black_box(&page);
+}
+
+/// Writes every page of `buffer`, so later accesses do not fault.
+pub fn prefault(buffer: &mut [u8]) {
+    for index in (0..buffer.len()).step_by(PAGE_SIZE) {
+        buffer[index] = std::hint::black_box(buffer[index]);
+    }
+}
+
+/// Measures the scheduling latency of the calling thread in the style of
+/// cyclictest: sleeps until `count` absolute deadlines `interval` nanoseconds
+/// apart with `clock_nanosleep` and records how late each wake-up was. Run it
+/// after [`RealtimeConfig::apply`] to judge the host.
+pub fn measure_latency(clock: Clock, interval: u64, count: u64) -> io::Result<LatenessStats> {
+    let mut scheduler = ClockNanosleep { clock };
+    let mut stats = LatenessStats::default();
+    let start = clock.now() + interval;
+    for index in 0..count {
+        stats.record(scheduler.wait_until(start + index * interval)?);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn failed_steps_become_warnings() {
+        // On a thread of its own, in case the tests run with privileges.
+        let report = thread::spawn(|| {
+            RealtimeConfig::new()
+                .cpus([libc::CPU_SETSIZE as usize])
+                .fifo(0)
+                .prefault_stack(64 * 1024)
+                .apply()
+        })
+        .join()
+        .unwrap();
+        assert_eq!(report.applied, vec![RealtimeStep::StackPrefault]);
+        assert_eq!(report.warnings.len(), 2);
+        assert_eq!(report.warnings[1].step, RealtimeStep::Scheduler);
+        assert_eq!(report.warnings[1].kind, io::ErrorKind::InvalidInput);
+        assert!(!report.is_complete());
+        assert!(RealtimeConfig::new().apply().is_complete());
+    }
+
+    #[test]
+    fn latency_is_measured() {
+        let mut buffer = vec![0u8; 3 * PAGE_SIZE];
+        prefault(&mut buffer);
+        let stats = measure_latency(Clock::Monotonic, 100_000, 20).unwrap();
+        assert_eq!(stats.count(), 20);
+        assert!(stats.min() >= 0);
+    }
+}
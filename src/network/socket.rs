@@ -1,30 +1,75 @@
 use libc::{
-    AF_PACKET, ARPHRD_LOOPBACK, SO_BINDTODEVICE, SOCK_RAW, SOL_SOCKET, htons, if_nametoindex, recv,
-    sendto, setsockopt, sockaddr_ll, socket,
+    AF_PACKET, ARPHRD_LOOPBACK, BPF_ABS, BPF_H, BPF_JEQ, BPF_JMP, BPF_K, BPF_LD, BPF_RET, BPF_W,
+    F_GETFL, F_SETFL, O_NONBLOCK, PACKET_OUTGOING, SCM_TIMESTAMPNS, SCM_TXTIME, SKF_AD_OFF,
+    SKF_AD_PKTTYPE, SO_ATTACH_FILTER, SO_BINDTODEVICE, SO_RCVTIMEO, SO_TIMESTAMPNS, SO_TXTIME,
+    SOCK_RAW, SOF_TXTIME_DEADLINE_MODE, SOF_TXTIME_REPORT_ERRORS, SOL_PACKET, SOL_SOCKET, close,
+    fcntl, htons, if_nametoindex, recvmsg, sendmsg, sendto, setsockopt, sock_filter, sock_fprog,
+    sock_txtime, sockaddr_ll, socket,
 };
 use std::ffi::CString;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::time::{Duration, SystemTime};
+
+use crate::network::clock::Clock;
+use crate::network::eth_types::EthTypes;
+use crate::network::transport::{Frame, FrameTransport};
+
+// PACKET_AUXDATA from linux/if_packet.h, which the libc crate lacks.
+const PACKET_AUXDATA: libc::c_int = 8;
+const TP_STATUS_VLAN_VALID: u32 = 1 << 4;
+const TP_STATUS_VLAN_TPID_VALID: u32 = 1 << 6;
+
+#[repr(C)]
+struct TpacketAuxdata {
+    tp_status: u32,
+    tp_len: u32,
+    tp_snaplen: u32,
+    tp_mac: u16,
+    tp_net: u16,
+    tp_vlan_tci: u16,
+    tp_vlan_tpid: u16,
+}
 
 pub struct RawSocket {
     pub sock: libc::c_int,
     pub iface: String,
     pub protocol: i32,
     pub sockaddr: sockaddr_ll,
+    /// Clock of the SO_TXTIME launch times, once `enable_txtime` succeeded.
+    pub txtime: Option<Clock>,
 }
 
 impl RawSocket {
     pub fn new(iface: String, protocol: u16) -> Self {
+        match Self::try_new(iface, protocol) {
+            Ok(socket) => socket,
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    pub fn try_new(iface: String, protocol: u16) -> io::Result<Self> {
+        let interface_name = CString::new(iface.clone())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let if_index = unsafe { if_nametoindex(interface_name.as_ptr()) };
+        if if_index == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Interface {} not found", iface),
+            ));
+        }
+
         let eth_p = htons(protocol) as i32;
         let sock = unsafe { socket(AF_PACKET, SOCK_RAW, eth_p) };
 
         if sock == -1 {
-            panic!(
-                "Failed to create socket: {}",
-                std::io::Error::last_os_error()
-            );
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Failed to create socket: {}", err),
+            ));
         }
 
-        let interface_name = CString::new(iface.clone()).unwrap();
-
         let result = unsafe {
             setsockopt(
                 sock,
@@ -35,18 +80,42 @@ impl RawSocket {
             )
         };
         if result == -1 {
-            panic!(
-                "Failed to bind socket to interface: {}",
-                std::io::Error::last_os_error()
-            );
+            let err = io::Error::last_os_error();
+            unsafe { close(sock) };
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Failed to bind socket to interface: {}", err),
+            ));
         }
 
-        let mut if_index = unsafe { if_nametoindex(iface.as_ptr() as *const libc::c_char) };
-
-        if if_index == 0 {
-            println!("Interface {} not found, using loopback\nError: {}", iface, std::io::Error::last_os_error());
-            if_index = 1;
+        // Most drivers strip the 802.1Q tag of received frames. With
+        // PACKET_AUXDATA the kernel reports it, so `try_recv` can put it back.
+        // SO_TIMESTAMPNS has it stamp every frame when it arrives.
+        let options = [
+            (SOL_PACKET, PACKET_AUXDATA, "PACKET_AUXDATA"),
+            (SOL_SOCKET, SO_TIMESTAMPNS, "SO_TIMESTAMPNS"),
+        ];
+        for (level, option, name) in options {
+            let enable: libc::c_int = 1;
+            let result = unsafe {
+                setsockopt(
+                    sock,
+                    level,
+                    option,
+                    &enable as *const libc::c_int as *const libc::c_void,
+                    std::mem::size_of_val(&enable) as libc::socklen_t,
+                )
+            };
+            if result == -1 {
+                let err = io::Error::last_os_error();
+                unsafe { close(sock) };
+                return Err(io::Error::new(
+                    err.kind(),
+                    format!("Failed to enable {}: {}", name, err),
+                ));
+            }
         }
+
         let sockaddr = sockaddr_ll {
             sll_family: AF_PACKET as u16,
             sll_protocol: eth_p as u16,
@@ -57,36 +126,245 @@ impl RawSocket {
             sll_addr: [0u8; 8],
         };
 
-        RawSocket {
+        Ok(RawSocket {
             sock,
             iface,
             protocol: eth_p,
             sockaddr,
+            txtime: None,
+        })
+    }
+
+    /// Switches the socket between blocking and non-blocking mode. In
+    /// non-blocking mode `try_recv` and `try_send` return
+    /// `io::ErrorKind::WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        let flags = unsafe { fcntl(self.sock, F_GETFL) };
+        if flags == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let flags = if nonblocking {
+            flags | O_NONBLOCK
+        } else {
+            flags & !O_NONBLOCK
+        };
+        if unsafe { fcntl(self.sock, F_SETFL, flags) } == -1 {
+            return Err(io::Error::last_os_error());
         }
+        Ok(())
     }
 
-    pub fn recv(&self) -> Vec<u8> {
-        let mut buffer = [0u8; 65536];
-        let packet_size = unsafe {
-            recv(
+    /// Keeps only incoming frames of `ether_type`, untagged or inside one
+    /// 802.1Q tag, with a classic BPF program run by the kernel. A socket
+    /// listening to every EtherType then no longer copies each frame of the
+    /// link, nor the frames this host sends, to user space. Tags stripped by
+    /// the driver are not in the data the program sees, so such frames show
+    /// `ether_type` right after the addresses.
+    pub fn attach_filter(&self, ether_type: u16) -> io::Result<()> {
+        let ld = (BPF_LD | BPF_ABS) as u16;
+        let jeq = (BPF_JMP | BPF_JEQ | BPF_K) as u16;
+        let ret = (BPF_RET | BPF_K) as u16;
+        let jump = |code, k, jt, jf| sock_filter { code, jt, jf, k };
+        let statement = |code, k| jump(code, k, 0, 0);
+        let mut program: [sock_filter; 9] = [
+            statement(ld | BPF_W as u16, (SKF_AD_OFF + SKF_AD_PKTTYPE) as u32),
+            jump(jeq, PACKET_OUTGOING as u32, 6, 0),
+            statement(ld | BPF_H as u16, 12),
+            jump(jeq, ether_type as u32, 3, 0),
+            jump(jeq, 0x8100, 0, 3),
+            statement(ld | BPF_H as u16, 16),
+            jump(jeq, ether_type as u32, 0, 1),
+            // Accepts the whole frame.
+            statement(ret, u32::MAX),
+            statement(ret, 0),
+        ];
+        let fprog = sock_fprog {
+            len: program.len() as libc::c_ushort,
+            filter: program.as_mut_ptr(),
+        };
+        let result = unsafe {
+            setsockopt(
                 self.sock,
-                buffer.as_mut_ptr() as *mut libc::c_void,
-                buffer.len(),
-                0,
+                SOL_SOCKET,
+                SO_ATTACH_FILTER,
+                &fprog as *const sock_fprog as *const libc::c_void,
+                std::mem::size_of_val(&fprog) as libc::socklen_t,
             )
         };
+        if result == -1 {
+            let err = io::Error::last_os_error();
+            return Err(io::Error::new(
+                err.kind(),
+                format!("Failed to attach the socket filter: {}", err),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Bounds how long `recv` waits for a frame. `None` waits forever.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let timeout = timeout.unwrap_or_default();
+        let timeval = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        let result = unsafe {
+            setsockopt(
+                self.sock,
+                SOL_SOCKET,
+                SO_RCVTIMEO,
+                &timeval as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of_val(&timeval) as libc::socklen_t,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Receives one frame, with the 802.1Q tag stripped by the driver put
+    /// back in place.
+    pub fn try_recv(&self) -> io::Result<Vec<u8>> {
+        self.try_recv_frame().map(|frame| frame.data)
+    }
+
+    /// Like `try_recv`, with the kernel receive timestamp of the frame.
+    /// Frames sent by this host, which ETH_P_ALL sockets see as well, are
+    /// skipped.
+    pub fn try_recv_frame(&self) -> io::Result<Frame> {
+        loop {
+            if let Some(frame) = self.recv_incoming()? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Receives one frame, `None` when it was outgoing.
+    fn recv_incoming(&self) -> io::Result<Option<Frame>> {
+        let mut buffer = [0u8; 65536];
+        let mut address: sockaddr_ll = unsafe { std::mem::zeroed() };
+        let mut iov = libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buffer.len(),
+        };
+        // u64 storage keeps the control buffer aligned for cmsghdr.
+        let mut control = [0u64; 16];
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_name = &mut address as *mut sockaddr_ll as *mut libc::c_void;
+        msg.msg_namelen = std::mem::size_of_val(&address) as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = std::mem::size_of_val(&control) as _;
+
+        let packet_size = unsafe { recvmsg(self.sock, &mut msg, 0) };
         if packet_size == -1 {
-            panic!(
-                "Error receiving packet: {}",
-                std::io::Error::last_os_error()
-            );
+            return Err(io::Error::last_os_error());
+        }
+        if address.sll_pkttype == PACKET_OUTGOING {
+            return Ok(None);
         }
-        buffer[..packet_size as usize].to_vec()
+        let mut bytes = buffer[..packet_size as usize].to_vec();
+        let ancillary = unsafe { Ancillary::parse(&msg) };
+        if let Some(tag) = ancillary.vlan_tag
+            && bytes.len() >= 12
+        {
+            bytes.splice(12..12, tag);
+        }
+        Ok(Some(Frame {
+            data: bytes,
+            timestamp: ancillary.timestamp,
+        }))
     }
 
-    pub fn send(&self, data: &[u8]) {
+    pub fn recv(&self) -> Vec<u8> {
+        match self.try_recv() {
+            Ok(bytes) => bytes,
+            Err(err) => panic!("Error receiving packet: {}", err),
+        }
+    }
+
+    /// Enables SO_TXTIME so frames sent with `send_at` leave the interface at
+    /// their launch time. Needs an ETF qdisc on the interface to be honoured;
+    /// `deadline_mode` lets the qdisc send as soon as possible instead of
+    /// holding frames until their launch time.
+    pub fn enable_txtime(&mut self, clock: Clock, deadline_mode: bool) -> io::Result<()> {
+        let mut flags = SOF_TXTIME_REPORT_ERRORS;
+        if deadline_mode {
+            flags |= SOF_TXTIME_DEADLINE_MODE;
+        }
+        let config = sock_txtime {
+            clockid: clock.id(),
+            flags,
+        };
+        let result = unsafe {
+            setsockopt(
+                self.sock,
+                SOL_SOCKET,
+                SO_TXTIME,
+                &config as *const sock_txtime as *const libc::c_void,
+                std::mem::size_of_val(&config) as libc::socklen_t,
+            )
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        self.txtime = Some(clock);
+        Ok(())
+    }
+
+    fn destination(&self, data: &[u8]) -> io::Result<sockaddr_ll> {
+        if data.len() < 12 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Frame of {} bytes is shorter than its MAC addresses",
+                    data.len()
+                ),
+            ));
+        }
         let mut sockaddr = self.sockaddr;
         sockaddr.sll_addr[..6].copy_from_slice(&data[6..(6 + 6)]);
+        Ok(sockaddr)
+    }
+
+    /// Sends `data` with an SCM_TXTIME launch time, in nanoseconds of the
+    /// clock given to `enable_txtime`.
+    pub fn send_at(&self, data: &[u8], launch_time: u64) -> io::Result<()> {
+        let mut sockaddr = self.destination(data)?;
+        let mut iov = libc::iovec {
+            iov_base: data.as_ptr() as *mut libc::c_void,
+            iov_len: data.len(),
+        };
+        // u64 storage keeps the control buffer aligned for cmsghdr.
+        let mut control = [0u64; 4];
+        let control_len = unsafe { libc::CMSG_SPACE(std::mem::size_of::<u64>() as u32) };
+
+        let mut msg: libc::msghdr = unsafe { std::mem::zeroed() };
+        msg.msg_name = &mut sockaddr as *mut sockaddr_ll as *mut libc::c_void;
+        msg.msg_namelen = std::mem::size_of_val(&sockaddr) as libc::socklen_t;
+        msg.msg_iov = &mut iov;
+        msg.msg_iovlen = 1;
+        msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        msg.msg_controllen = control_len as _;
+
+        let result = unsafe {
+            let cmsg = libc::CMSG_FIRSTHDR(&msg);
+            (*cmsg).cmsg_level = SOL_SOCKET;
+            (*cmsg).cmsg_type = SCM_TXTIME;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(std::mem::size_of::<u64>() as u32) as _;
+            std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u64, launch_time);
+            sendmsg(self.sock, &msg, 0)
+        };
+        if result == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn try_send(&self, data: &[u8]) -> io::Result<()> {
+        let sockaddr = self.destination(data)?;
         let result = unsafe {
             sendto(
                 self.sock,
@@ -98,7 +376,273 @@ impl RawSocket {
             )
         };
         if result == -1 {
-            panic!("Error sending packet: {}", std::io::Error::last_os_error());
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Sends `frames` with as few `sendmmsg` calls as the kernel allows.
+    pub fn try_send_batch(&self, frames: &[&[u8]]) -> io::Result<()> {
+        let mut addresses = frames
+            .iter()
+            .map(|data| self.destination(data))
+            .collect::<io::Result<Vec<sockaddr_ll>>>()?;
+        let mut iovecs: Vec<libc::iovec> = frames
+            .iter()
+            .map(|data| libc::iovec {
+                iov_base: data.as_ptr() as *mut libc::c_void,
+                iov_len: data.len(),
+            })
+            .collect();
+        let mut messages: Vec<libc::mmsghdr> = addresses
+            .iter_mut()
+            .zip(iovecs.iter_mut())
+            .map(|(address, iov)| {
+                let mut message: libc::mmsghdr = unsafe { std::mem::zeroed() };
+                message.msg_hdr.msg_name = address as *mut sockaddr_ll as *mut libc::c_void;
+                message.msg_hdr.msg_namelen = std::mem::size_of::<sockaddr_ll>() as libc::socklen_t;
+                message.msg_hdr.msg_iov = iov;
+                message.msg_hdr.msg_iovlen = 1;
+                message
+            })
+            .collect();
+        let mut offset = 0;
+        while offset < messages.len() {
+            // sendmmsg may stop early, for instance on a full queue.
+            let result = unsafe {
+                libc::sendmmsg(
+                    self.sock,
+                    messages[offset..].as_mut_ptr(),
+                    (messages.len() - offset) as libc::c_uint,
+                    0,
+                )
+            };
+            if result == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            offset += result as usize;
+        }
+        Ok(())
+    }
+
+    pub fn send(&self, data: &[u8]) {
+        if let Err(err) = self.try_send(data) {
+            panic!("Error sending packet: {}", err);
+        }
+    }
+}
+
+/// Control messages of a received frame.
+#[derive(Default)]
+struct Ancillary {
+    /// The 802.1Q tag reported through PACKET_AUXDATA for a frame received
+    /// without it.
+    vlan_tag: Option<[u8; 4]>,
+    /// SO_TIMESTAMPNS receive time.
+    timestamp: Option<SystemTime>,
+}
+
+impl Ancillary {
+    /// # Safety
+    ///
+    /// `msg` must come from a successful `recvmsg` call.
+    unsafe fn parse(msg: &libc::msghdr) -> Ancillary {
+        let mut ancillary = Ancillary::default();
+        let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(msg) };
+        while !cmsg.is_null() {
+            let header = unsafe { &*cmsg };
+            let data = unsafe { libc::CMSG_DATA(cmsg) };
+            if header.cmsg_level == SOL_PACKET && header.cmsg_type == PACKET_AUXDATA {
+                let auxdata = unsafe { std::ptr::read_unaligned(data as *const TpacketAuxdata) };
+                if auxdata.tp_status & TP_STATUS_VLAN_VALID != 0 {
+                    let tpid = if auxdata.tp_status & TP_STATUS_VLAN_TPID_VALID != 0 {
+                        auxdata.tp_vlan_tpid
+                    } else {
+                        EthTypes::Vlan as u16
+                    };
+                    let [tpid_high, tpid_low] = tpid.to_be_bytes();
+                    let [tci_high, tci_low] = auxdata.tp_vlan_tci.to_be_bytes();
+                    ancillary.vlan_tag = Some([tpid_high, tpid_low, tci_high, tci_low]);
+                }
+            } else if header.cmsg_level == SOL_SOCKET && header.cmsg_type == SCM_TIMESTAMPNS {
+                let time = unsafe { std::ptr::read_unaligned(data as *const libc::timespec) };
+                ancillary.timestamp = Some(
+                    SystemTime::UNIX_EPOCH + Duration::new(time.tv_sec as u64, time.tv_nsec as u32),
+                );
+            }
+            cmsg = unsafe { libc::CMSG_NXTHDR(msg, cmsg) };
+        }
+        ancillary
+    }
+}
+
+impl FrameTransport for RawSocket {
+    /// With SO_TXTIME enabled, `timestamp` is used as the launch time.
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        match (self.txtime, timestamp) {
+            (Some(clock), Some(timestamp)) => self.send_at(data, clock.from_system_time(timestamp)),
+            _ => self.try_send(data),
+        }
+    }
+
+    /// One `sendmmsg` for the whole batch, unless it carries a launch time.
+    fn send_batch(&mut self, frames: &[&[u8]], timestamp: Option<SystemTime>) -> io::Result<()> {
+        match (self.txtime, timestamp) {
+            (Some(clock), Some(timestamp)) => {
+                let launch_time = clock.from_system_time(timestamp);
+                frames
+                    .iter()
+                    .try_for_each(|data| self.send_at(data, launch_time))
+            }
+            _ => self.try_send_batch(frames),
+        }
+    }
+
+    /// Launch times on the SO_TXTIME clock go to the kernel untouched. Any
+    /// other clock is refused rather than converted, as each conversion
+    /// reads both clocks again and would add jitter to the launch times.
+    fn send_batch_at(
+        &mut self,
+        frames: &[&[u8]],
+        clock: Clock,
+        launch_time: u64,
+    ) -> io::Result<()> {
+        match self.txtime {
+            Some(txtime) if txtime == clock => frames
+                .iter()
+                .try_for_each(|data| self.send_at(data, launch_time)),
+            Some(txtime) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Launch times on {:?} for SO_TXTIME on {:?}, pace with the same clock",
+                    clock, txtime
+                ),
+            )),
+            None => self.try_send_batch(frames),
+        }
+    }
+
+    /// Frames carry their SO_TIMESTAMPNS kernel receive time.
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        self.try_recv_frame()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        RawSocket::set_read_timeout(self, timeout)
+    }
+}
+
+impl AsRawFd for RawSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock
+    }
+}
+
+impl AsFd for RawSocket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        // The descriptor is owned by `self` and closed on drop only.
+        unsafe { BorrowedFd::borrow_raw(self.sock) }
+    }
+}
+
+impl Drop for RawSocket {
+    fn drop(&mut self) {
+        unsafe { close(self.sock) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_interface_is_not_found() {
+        let err = RawSocket::try_new("sv-missing0".to_string(), 0x88ba)
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn short_frames_are_invalid_input() {
+        let socket = match RawSocket::try_new("lo".to_string(), 0x88ba) {
+            Ok(socket) => socket,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("{}", err),
+        };
+        let kind = |result: io::Result<()>| result.unwrap_err().kind();
+        assert_eq!(
+            kind(socket.try_send(&[0x01; 11])),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(socket.send_at(&[0x01; 6], 0)),
+            io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            kind(socket.try_send_batch(&[&[0x01; 60], &[]])),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    #[test]
+    fn launch_times_need_the_txtime_clock() {
+        let mut socket = match RawSocket::try_new("lo".to_string(), 0x88ba) {
+            Ok(socket) => socket,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("{}", err),
+        };
+        socket.enable_txtime(Clock::Tai, false).unwrap();
+        let err = socket
+            .send_batch_at(&[&[0u8; 60]], Clock::Monotonic, Clock::Monotonic.now())
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn filter_keeps_only_the_ether_type() {
+        let receiver = match RawSocket::try_new("lo".to_string(), libc::ETH_P_ALL as u16) {
+            Ok(socket) => socket,
+            Err(err) if err.kind() == io::ErrorKind::PermissionDenied => return,
+            Err(err) => panic!("{}", err),
+        };
+        receiver
+            .attach_filter(EthTypes::SampledValues as u16)
+            .unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let sender = RawSocket::try_new("lo".to_string(), libc::ETH_P_ALL as u16).unwrap();
+
+        let header = [
+            0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x02, 0x00, 0x00, 0x00, 0x4e, 0x04,
+        ];
+        let frame = |ether_types: &[u8]| {
+            let mut frame = header.to_vec();
+            frame.extend_from_slice(ether_types);
+            frame.resize(60, 0);
+            frame
+        };
+        let arp = frame(&[0x08, 0x06]);
+        let tagged_ip = frame(&[0x81, 0x00, 0x00, 0x05, 0x08, 0x00]);
+        let untagged = frame(&[0x88, 0xba]);
+        let tagged = frame(&[0x81, 0x00, 0x80, 0x05, 0x88, 0xba]);
+        for data in [&arp, &tagged_ip, &untagged, &tagged] {
+            sender.try_send(data).unwrap();
+        }
+
+        let mut received = vec![];
+        while let Ok(data) = receiver.try_recv() {
+            assert!(data.len() >= 18);
+            let ether_type = match &data[12..14] {
+                [0x81, 0x00] => &data[16..18],
+                ether_type => ether_type,
+            };
+            assert_eq!(ether_type, [0x88, 0xba]);
+            if data[..12] == header {
+                received.push(data);
+            }
         }
+        assert_eq!(received, vec![untagged, tagged]);
     }
 }
@@ -1 +1,5 @@
+pub mod receive_stats;
 pub mod sampled_value_sub;
+pub mod supervision;
+#[cfg(feature = "tokio")]
+pub mod tokio_sub;
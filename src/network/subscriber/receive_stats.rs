@@ -0,0 +1,555 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::network::subscriber::supervision::StreamKey;
+
+/// Linear sub-buckets per power of two, as a number of bits: 16 sub-buckets
+/// keep every bucket within 1/16 (6.25 %) of its values.
+const SUB_BUCKET_BITS: u32 = 4;
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+const BUCKETS: usize = SUB_BUCKETS * (u64::BITS - SUB_BUCKET_BITS + 1) as usize;
+const NANOS_PER_SECOND: i128 = 1_000_000_000;
+
+/// HDR-style histogram of nanosecond values: exact below 16 ns, then 16
+/// linear buckets per power of two, so the relative error stays below
+/// 6.25 % from nanoseconds to centuries. Also keeps the exact count, min,
+/// max, mean and standard deviation.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    min: u64,
+    max: u64,
+    mean: f64,
+    /// Sum of squared deviations from the mean (Welford).
+    m2: f64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            min: 0,
+            max: 0,
+            mean: 0.0,
+            m2: 0.0,
+        }
+    }
+}
+
+impl Histogram {
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            return value as usize;
+        }
+        let exponent = u64::BITS - 1 - value.leading_zeros();
+        let shift = exponent - SUB_BUCKET_BITS;
+        let sub_bucket = (value >> shift) as usize & (SUB_BUCKETS - 1);
+        SUB_BUCKETS * (shift as usize + 1) + sub_bucket
+    }
+
+    /// Lowest and highest value counted in `bucket`.
+    pub fn bucket_range(bucket: usize) -> (u64, u64) {
+        if bucket < SUB_BUCKETS {
+            return (bucket as u64, bucket as u64);
+        }
+        let shift = (bucket / SUB_BUCKETS - 1) as u32;
+        let low = ((SUB_BUCKETS + bucket % SUB_BUCKETS) as u64) << shift;
+        (low, low + ((1u64 << shift) - 1))
+    }
+
+    pub fn record(&mut self, value: u64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        let delta = value as f64 - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value as f64 - self.mean);
+        self.buckets[Self::bucket(value)] += 1;
+    }
+
+    pub fn reset(&mut self) {
+        *self = Histogram::default();
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> u64 {
+        self.min
+    }
+
+    pub fn max(&self) -> u64 {
+        self.max
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Population standard deviation.
+    pub fn stddev(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            (self.m2 / self.count as f64).sqrt()
+        }
+    }
+
+    /// Value below which `percentile` percent of the values lie, as the
+    /// upper end of its bucket clamped to the exact maximum. 0 when empty.
+    pub fn percentile(&self, percentile: f64) -> u64 {
+        if self.count == 0 {
+            return 0;
+        }
+        let rank =
+            ((percentile.clamp(0.0, 100.0) / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_range(bucket).1.clamp(self.min, self.max);
+            }
+        }
+        self.max
+    }
+
+    /// Non-empty buckets as (lowest value, highest value, count).
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(bucket, count)| {
+                let (low, high) = Self::bucket_range(bucket);
+                (low, high, *count)
+            })
+    }
+}
+
+/// Receive statistics of one stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamReceiveStats {
+    pub stream: StreamKey,
+    pub frames: u64,
+    pub samples: u64,
+    pub first: SystemTime,
+    pub last: SystemTime,
+    /// True when every frame carried a receive time from the transport, such
+    /// as the kernel's SO_TIMESTAMPNS, rather than one taken after reading it.
+    pub timestamped: bool,
+    /// Time between consecutive frames of the stream, in nanoseconds.
+    pub gaps: Histogram,
+    /// Time from the nominal instant of the last sample of each frame to the
+    /// frame's receive time, in nanoseconds. Only recorded for frames of a
+    /// time-synchronised stream with a transport receive time; frames
+    /// stamped before their nominal instant, as when the clocks disagree,
+    /// count as 0.
+    pub latency: Histogram,
+}
+
+impl StreamReceiveStats {
+    /// Time from the first to the last frame.
+    pub fn span(&self) -> Duration {
+        self.last.duration_since(self.first).unwrap_or_default()
+    }
+
+    /// Measured frame rate, in frames per second, over the span of the
+    /// frames. 0 before the second frame.
+    pub fn frame_rate(&self) -> f64 {
+        self.rate(self.frames)
+    }
+
+    /// Measured sample rate, in samples per second, over the span of the
+    /// frames.
+    pub fn sample_rate(&self) -> f64 {
+        self.rate(self.samples)
+    }
+
+    fn rate(&self, count: u64) -> f64 {
+        let span = self.span().as_secs_f64();
+        if self.frames < 2 || span == 0.0 {
+            return 0.0;
+        }
+        // The span starts with the first frame, so it holds one frame less.
+        count as f64 * (self.frames - 1) as f64 / self.frames as f64 / span
+    }
+}
+
+impl fmt::Display for StreamReceiveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let micros = |nanos: f64| nanos / 1_000.0;
+        write!(
+            f,
+            "{}: {} frames, {} samples, {:.1} frames/s, {:.1} samples/s, gap min {:.1} us, \
+             mean {:.1} us, max {:.1} us, stddev {:.1} us, p99 {:.1} us, p99.9 {:.1} us",
+            self.stream,
+            self.frames,
+            self.samples,
+            self.frame_rate(),
+            self.sample_rate(),
+            micros(self.gaps.min() as f64),
+            micros(self.gaps.mean()),
+            micros(self.gaps.max() as f64),
+            micros(self.gaps.stddev()),
+            micros(self.gaps.percentile(99.0) as f64),
+            micros(self.gaps.percentile(99.9) as f64),
+        )?;
+        if self.latency.count() > 0 {
+            write!(
+                f,
+                ", latency min {:.1} us, mean {:.1} us, max {:.1} us, p99 {:.1} us",
+                micros(self.latency.min() as f64),
+                micros(self.latency.mean()),
+                micros(self.latency.max() as f64),
+                micros(self.latency.percentile(99.0) as f64),
+            )?;
+        }
+        if !self.timestamped {
+            write!(f, " (user space timestamps)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Last sample of a received frame, for the arrival latency.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleTiming {
+    pub smp_count: u16,
+    /// smpRate in samples per second, when the stream carries it. Otherwise
+    /// the rate is learned from the first smpCnt wrap of the stream.
+    pub sample_rate: Option<u32>,
+    /// True when smpSynch says the samples follow a synchronised clock, so
+    /// smpCnt 0 is taken at the top of each second.
+    pub synchronized: bool,
+}
+
+/// smpCnt wrap of a stream, learned from the samples it carries.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Wrap {
+    previous: u16,
+    highest: u16,
+    sample_rate: Option<u32>,
+}
+
+impl Wrap {
+    /// Follows `smp_count` and returns the wrap rate once known.
+    fn observe(&mut self, smp_count: u16) -> Option<u32> {
+        // A wrap goes from near the rate to near 0; reordering does not.
+        if smp_count < self.previous / 2 {
+            self.sample_rate = Some(self.highest as u32 + 1);
+            self.highest = smp_count;
+        }
+        self.highest = self.highest.max(smp_count);
+        self.previous = smp_count;
+        self.sample_rate
+    }
+}
+
+/// Per-stream receive statistics: frame and sample counts, measured rates,
+/// inter-arrival gaps and, for time-synchronised streams, arrival latency.
+/// Feed it every received frame with [`ReceiveStats::record`] and
+/// [`ReceiveStats::record_timing`]; `SvSubscriber` does on its receive
+/// thread.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ReceiveStats {
+    streams: BTreeMap<StreamKey, StreamReceiveStats>,
+    wraps: BTreeMap<StreamKey, Wrap>,
+}
+
+impl ReceiveStats {
+    /// Records a frame of `stream` carrying `samples` ASDUs, received at
+    /// `timestamp`. `timestamped` tells whether the time came with the frame.
+    pub fn record(
+        &mut self,
+        stream: &StreamKey,
+        samples: u64,
+        timestamp: SystemTime,
+        timestamped: bool,
+    ) {
+        match self.streams.get_mut(stream) {
+            Some(stats) => {
+                let gap = timestamp.duration_since(stats.last).unwrap_or_default();
+                stats.gaps.record(gap.as_nanos() as u64);
+                stats.frames += 1;
+                stats.samples += samples;
+                stats.last = stats.last.max(timestamp);
+                stats.timestamped &= timestamped;
+            }
+            None => {
+                self.streams.insert(
+                    stream.clone(),
+                    StreamReceiveStats {
+                        stream: stream.clone(),
+                        frames: 1,
+                        samples,
+                        first: timestamp,
+                        last: timestamp,
+                        timestamped,
+                        gaps: Histogram::default(),
+                        latency: Histogram::default(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records the arrival latency of a frame of `stream` already passed to
+    /// [`ReceiveStats::record`], from the `timing` of its last sample. smpCnt
+    /// n is nominally taken n / rate seconds after the top of the second
+    /// closest to `timestamp`. Skipped without a transport receive time, for
+    /// unsynchronised streams and until the sample rate is known.
+    pub fn record_timing(
+        &mut self,
+        stream: &StreamKey,
+        timing: SampleTiming,
+        timestamp: SystemTime,
+        timestamped: bool,
+    ) {
+        let wrap = self.wraps.entry(stream.clone()).or_default();
+        let learned = wrap.observe(timing.smp_count);
+        let Some(sample_rate) = timing.sample_rate.or(learned).filter(|rate| *rate > 0) else {
+            return;
+        };
+        let Some(stats) = self.streams.get_mut(stream) else {
+            return;
+        };
+        if !timestamped || !timing.synchronized {
+            return;
+        }
+        let Ok(time) = timestamp.duration_since(SystemTime::UNIX_EPOCH) else {
+            return;
+        };
+        let time = time.as_nanos() as i128;
+        let offset = timing.smp_count as i128 * NANOS_PER_SECOND / sample_rate as i128;
+        // The second whose nominal instant is closest to the receive time.
+        let second = (time - offset + NANOS_PER_SECOND / 2).div_euclid(NANOS_PER_SECOND);
+        let latency = time - (second * NANOS_PER_SECOND + offset);
+        stats.latency.record(latency.max(0) as u64);
+    }
+
+    pub fn streams(&self) -> Vec<StreamReceiveStats> {
+        self.streams.values().cloned().collect()
+    }
+
+    pub fn stream(&self, stream: &StreamKey) -> Option<&StreamReceiveStats> {
+        self.streams.get(stream)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty()
+    }
+
+    /// Clears the statistics, keeping the sample rates learned from smpCnt
+    /// wraps.
+    pub fn reset(&mut self) {
+        self.streams.clear();
+    }
+}
+
+impl fmt::Display for ReceiveStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for stats in self.streams.values() {
+            writeln!(f, "{}", stats)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::publisher::config::PublisherConfig;
+    use crate::network::subscriber::sampled_value_sub::SvSubscriber;
+    use crate::network::transport::{FrameTransport, memory::VirtualSwitch};
+    use std::sync::mpsc;
+
+    #[test]
+    fn histogram_buckets_and_percentiles() {
+        for value in [0, 15, 16, 17, 31, 32, 250_000, u64::MAX] {
+            let (low, high) = Histogram::bucket_range(Histogram::bucket(value));
+            assert!(low <= value && value <= high, "{}", value);
+            assert!((high - low) as f64 <= value as f64 / 16.0);
+        }
+        assert_eq!(Histogram::bucket(u64::MAX), BUCKETS - 1);
+
+        let mut histogram = Histogram::default();
+        for value in 1..=1000 {
+            histogram.record(value * 1_000);
+        }
+        assert_eq!(histogram.count(), 1000);
+        assert_eq!(histogram.min(), 1_000);
+        assert_eq!(histogram.max(), 1_000_000);
+        assert_eq!(histogram.mean(), 500_500.0);
+        assert!((histogram.stddev() - 288_675.0).abs() < 1.0);
+        let p50 = histogram.percentile(50.0);
+        assert!((500_000..=500_000 + 500_000 / 16).contains(&p50));
+        assert_eq!(histogram.percentile(100.0), 1_000_000);
+        assert_eq!(
+            histogram.buckets().map(|(_, _, count)| count).sum::<u64>(),
+            1000
+        );
+        histogram.reset();
+        assert_eq!(histogram.percentile(50.0), 0);
+    }
+
+    #[test]
+    fn per_stream_rates_and_gaps() {
+        let key = StreamKey {
+            app_id: 0x4000,
+            sv_id: "MU01".to_string(),
+        };
+        let mut stats = ReceiveStats::default();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(100);
+        for frame in 0..=4000u64 {
+            // 250 us apart, every other frame 10 us late.
+            let jitter = if frame % 2 == 1 { 10_000 } else { 0 };
+            let time = start + Duration::from_nanos(frame * 250_000 + jitter);
+            stats.record(&key, 1, time, true);
+        }
+        let stream = stats.stream(&key).unwrap();
+        assert_eq!(stream.frames, 4001);
+        assert_eq!(stream.span(), Duration::from_secs(1));
+        assert!((stream.frame_rate() - 4000.0).abs() < 1e-6);
+        assert!((stream.sample_rate() - 4000.0).abs() < 1e-6);
+        assert_eq!(stream.gaps.min(), 240_000);
+        assert_eq!(stream.gaps.max(), 260_000);
+        assert!((stream.gaps.mean() - 250_000.0).abs() < 1e-6);
+        assert!((stream.gaps.stddev() - 10_000.0).abs() < 1e-3);
+        assert!(stats.to_string().starts_with(
+            "MU01 (0x4000): 4001 frames, 4001 samples, 4000.0 frames/s, 4000.0 samples/s"
+        ));
+
+        stats.record(&key, 1, start, false);
+        assert!(!stats.streams()[0].timestamped);
+        stats.reset();
+        assert!(stats.is_empty());
+    }
+
+    #[test]
+    fn latency_from_the_nominal_sample_time() {
+        let key = StreamKey {
+            app_id: 0x4000,
+            sv_id: "MU01".to_string(),
+        };
+        let mut stats = ReceiveStats::default();
+        let second = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        // Frames of two samples at 4000 samples/s, from the end of a second
+        // into the next, each received 150 us after its last sample.
+        for frame in 0..8u64 {
+            let smp_count = ((3996 + frame * 2 + 1) % 4000) as u16;
+            let nominal = second + Duration::from_nanos((3996 + frame * 2 + 1) * 250_000);
+            let timestamp = nominal + Duration::from_micros(150);
+            let timing = SampleTiming {
+                smp_count,
+                sample_rate: None,
+                synchronized: true,
+            };
+            stats.record(&key, 2, timestamp, true);
+            stats.record_timing(&key, timing, timestamp, true);
+        }
+        // The rate is only known from the wrap on, after the first 2 frames.
+        let latency = &stats.stream(&key).unwrap().latency;
+        assert_eq!(latency.count(), 6);
+        assert_eq!((latency.min(), latency.max()), (150_000, 150_000));
+        assert_eq!(stats.stream(&key).unwrap().gaps.min(), 500_000);
+        assert!(stats.to_string().contains("latency min 150.0 us"));
+
+        // Learned rates survive a reset; unsynchronised streams and user
+        // space timestamps are left out.
+        stats.reset();
+        let timing = SampleTiming {
+            smp_count: 13,
+            sample_rate: None,
+            synchronized: true,
+        };
+        let timestamp = second + Duration::from_micros(3_260);
+        stats.record(&key, 2, timestamp, true);
+        stats.record_timing(&key, timing, timestamp, true);
+        stats.record_timing(&key, timing, timestamp, false);
+        let unsynchronized = SampleTiming {
+            synchronized: false,
+            ..timing
+        };
+        stats.record_timing(&key, unsynchronized, timestamp, true);
+        let latency = &stats.stream(&key).unwrap().latency;
+        assert_eq!((latency.count(), latency.max()), (1, 10_000));
+
+        // A carried smpRate is used right away; early frames count as 0.
+        let other = StreamKey {
+            app_id: 0x4001,
+            sv_id: "MU02".to_string(),
+        };
+        let timing = SampleTiming {
+            smp_count: 480,
+            sample_rate: Some(4800),
+            synchronized: true,
+        };
+        let timestamp = second + Duration::from_micros(99_990);
+        stats.record(&other, 1, timestamp, true);
+        stats.record_timing(&other, timing, timestamp, true);
+        let latency = &stats.stream(&other).unwrap().latency;
+        assert_eq!((latency.count(), latency.max()), (1, 0));
+    }
+
+    #[test]
+    fn subscriber_collects_and_summarizes() {
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let subscriber = SvSubscriber::with_transport(switch.port());
+        let (sender, summaries) = mpsc::channel();
+        subscriber.summarize(Duration::from_millis(50), move |summary| {
+            sender.send(summary.streams()).unwrap()
+        });
+        let handle = subscriber.start().unwrap();
+        let frame = PublisherConfig::builder()
+            .sv_id("MU01")
+            .asdus_per_frame(2)
+            .build()
+            .unwrap()
+            .packet()
+            .to_bytes();
+        let start = SystemTime::now();
+        for index in 0..3 {
+            let time = start + Duration::from_micros(500 * index);
+            publisher_port.send_frame(&frame, Some(time)).unwrap();
+        }
+
+        let summary = loop {
+            let summary = summaries.recv_timeout(Duration::from_secs(1)).unwrap();
+            if !summary.is_empty() {
+                break summary;
+            }
+        };
+        assert_eq!(summary[0].stream.sv_id, "MU01");
+        assert_eq!((summary[0].frames, summary[0].samples), (3, 6));
+        // The switch port passes the send times on as receive times.
+        assert!(summary[0].timestamped);
+        assert_eq!(summary[0].gaps.min(), 500_000);
+        assert!(
+            summary[0]
+                .to_string()
+                .contains("3 frames, 6 samples, 2000.0 frames/s, 4000.0 samples/s")
+        );
+        // Later summaries only cover their own interval.
+        assert!(
+            summaries
+                .recv_timeout(Duration::from_secs(1))
+                .unwrap()
+                .is_empty()
+        );
+
+        assert_eq!(handle.receive_stats()[0].frames, 3);
+        handle.reset_receive_stats();
+        assert!(handle.receive_stats().is_empty());
+        handle.stop().unwrap();
+    }
+}
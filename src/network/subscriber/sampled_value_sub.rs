@@ -1,11 +1,873 @@
-use crate::network::socket::RawSocket;
+use std::io;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicBool, AtomicU64, Ordering},
+    mpsc::{self, Receiver, SyncSender, TrySendError},
+};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::network::subscriber::{
+    receive_stats::{ReceiveStats, SampleTiming, StreamReceiveStats},
+    supervision::{StreamEvent, StreamKey, StreamSupervision, SupervisionConfig, Supervisor},
+};
+use crate::network::{
+    eth_types::EthTypes,
+    packet::Packet,
+    realtime::{RealtimeConfig, RealtimeReport},
+    socket::RawSocket,
+    transport::FrameTransport,
+};
+use crate::protocols::{
+    ethernet::model::{Ethernet, Vlan},
+    sampled_values::model::{Asdu, SampleSync},
+};
+
+/// How often a subscriber thread waiting for frames checks whether it should
+/// stop.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// smpMod value for smpRate in samples per second.
+const SAMPLES_PER_SECOND: u16 = 1;
 
 pub fn main() {
-    let socket = RawSocket::new("lo".to_string(), 0x88ba_u16);
+    let mut subscriber = match SvSubscriber::new("lo") {
+        Ok(subscriber) => subscriber,
+        Err(err) => panic!("Error opening subscriber: {}", err),
+    };
+    subscriber.supervise(
+        SubscriptionFilter::new(),
+        SupervisionConfig::new(4800, Duration::from_secs(1)),
+        |event| println!("{}", event),
+    );
+    subscriber.summarize(Duration::from_secs(10), |summary| print!("{}", summary));
+    if let Err(err) = subscriber.run(None) {
+        panic!("Error receiving packet: {}", err);
+    }
+}
+
+/// Decodes every frame received on `transport` and hands it to `on_packet`,
+/// or the decoding error for frames that are not valid Sampled Values.
+/// Stops after `frames` frames, or never when `None`.
+pub fn subscribe<T, F>(transport: &mut T, frames: Option<u64>, mut on_packet: F) -> io::Result<()>
+where
+    T: FrameTransport,
+    F: FnMut(io::Result<Packet>),
+{
+    let mut received: u64 = 0;
+    while frames.is_none_or(|frames| received < frames) {
+        let frame = transport.recv_frame()?;
+        on_packet(Packet::try_from_bytes(&frame.data));
+        received += 1;
+    }
+    Ok(())
+}
+
+/// Criteria selecting the ASDUs of a subscription. Criteria left unset match
+/// anything, so the default filter receives every stream.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SubscriptionFilter {
+    pub dst_mac: Option<[u8; 6]>,
+    /// VLAN id. Untagged frames do not match once set.
+    pub vlan: Option<u16>,
+    pub app_id: Option<u16>,
+    pub sv_id: Option<String>,
+    pub conf_rev: Option<u32>,
+}
+
+impl SubscriptionFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dst_mac(mut self, dst_mac: [u8; 6]) -> Self {
+        self.dst_mac = Some(dst_mac);
+        self
+    }
+
+    pub fn vlan(mut self, vlan: u16) -> Self {
+        self.vlan = Some(vlan);
+        self
+    }
+
+    pub fn app_id(mut self, app_id: u16) -> Self {
+        self.app_id = Some(app_id);
+        self
+    }
+
+    pub fn sv_id(mut self, sv_id: impl Into<String>) -> Self {
+        self.sv_id = Some(sv_id.into());
+        self
+    }
+
+    pub fn conf_rev(mut self, conf_rev: u32) -> Self {
+        self.conf_rev = Some(conf_rev);
+        self
+    }
+
+    /// Checks the frame level criteria: destination MAC, VLAN and APPID.
+    pub fn matches_frame(&self, packet: &Packet) -> bool {
+        self.dst_mac
+            .is_none_or(|dst_mac| dst_mac == packet.ethernet.dst_mac)
+            && self
+                .vlan
+                .is_none_or(|vlan| packet.ethernet.vlan.is_some_and(|tag| tag.id == vlan))
+            && self
+                .app_id
+                .is_none_or(|app_id| app_id == packet.sampled_value.app_id)
+    }
+
+    /// Checks the ASDU level criteria: svID and confRev.
+    pub fn matches_asdu(&self, asdu: &Asdu) -> bool {
+        self.sv_id.as_ref().is_none_or(|sv_id| *sv_id == asdu.sv_id)
+            && self
+                .conf_rev
+                .is_none_or(|conf_rev| conf_rev == asdu.conf_rev)
+    }
+}
+
+/// One ASDU delivered to a subscription, with the header fields of the frame
+/// that carried it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ReceivedAsdu {
+    /// Receive time reported by the transport, or taken when the frame was
+    /// read when it reports none.
+    pub timestamp: SystemTime,
+    pub src_mac: [u8; 6],
+    pub dst_mac: [u8; 6],
+    pub vlan: Option<Vlan>,
+    pub app_id: u16,
+    pub simulation: bool,
+    /// Position of the ASDU in its frame.
+    pub index: usize,
+    pub asdu: Asdu,
+}
+
+/// Identifies a subscription, to remove it with `unsubscribe`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+enum Sink {
+    Callback(Box<dyn FnMut(&ReceivedAsdu) + Send>),
+    Channel(SyncSender<ReceivedAsdu>),
+}
+
+struct Subscription {
+    id: SubscriptionId,
+    filter: SubscriptionFilter,
+    sink: Sink,
+}
+
+/// Receiving end of a channel subscription. Iterating blocks for the next
+/// ASDU and ends once the subscriber stopped or the subscription was removed.
+pub struct AsduReceiver {
+    id: SubscriptionId,
+    receiver: Receiver<ReceivedAsdu>,
+}
+
+impl AsduReceiver {
+    pub fn id(&self) -> SubscriptionId {
+        self.id
+    }
+
+    /// `None` when nothing is queued, or the subscription ended.
+    pub fn try_recv(&self) -> Option<ReceivedAsdu> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// `None` after `timeout` without an ASDU, or once the subscription ended.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<ReceivedAsdu> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for AsduReceiver {
+    type Item = ReceivedAsdu;
+
+    fn next(&mut self) -> Option<ReceivedAsdu> {
+        self.receiver.recv().ok()
+    }
+}
+
+/// Stream supervision run by a subscriber on the ASDUs matching `filter`.
+struct Supervision {
+    filter: SubscriptionFilter,
+    supervisor: Supervisor,
+    on_event: Box<dyn FnMut(StreamEvent) + Send>,
+}
+
+/// Periodic receive statistics summaries.
+struct Summaries {
+    interval: Duration,
+    next: Instant,
+    stats: ReceiveStats,
+    on_summary: Box<dyn FnMut(&ReceiveStats) + Send>,
+}
+
+/// Counters of a subscriber.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubscriberStats {
+    pub frames_received: u64,
+    /// Frames that are not Sampled Values or failed to decode.
+    pub frames_malformed: u64,
+    pub asdus_delivered: u64,
+    /// ASDUs dropped because a channel subscription was full.
+    pub asdus_dropped: u64,
+}
+
+/// State shared between a subscriber thread and its handle.
+#[derive(Default)]
+struct Shared {
+    running: AtomicBool,
+    next_id: AtomicU64,
+    subscriptions: Mutex<Vec<Subscription>>,
+    supervision: Mutex<Option<Supervision>>,
+    receive_stats: Mutex<ReceiveStats>,
+    summaries: Mutex<Option<Summaries>>,
+    realtime: Mutex<Option<RealtimeReport>>,
+    received: AtomicU64,
+    malformed: AtomicU64,
+    delivered: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn add(&self, filter: SubscriptionFilter, sink: Sink) -> SubscriptionId {
+        let id = SubscriptionId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.subscriptions
+            .lock()
+            .unwrap()
+            .push(Subscription { id, filter, sink });
+        id
+    }
+
+    fn subscribe_channel(&self, filter: SubscriptionFilter, capacity: usize) -> AsduReceiver {
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let id = self.add(filter, Sink::Channel(sender));
+        AsduReceiver { id, receiver }
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        let count = subscriptions.len();
+        subscriptions.retain(|subscription| subscription.id != id);
+        subscriptions.len() != count
+    }
+
+    fn stats(&self) -> SubscriberStats {
+        SubscriberStats {
+            frames_received: self.received.load(Ordering::Relaxed),
+            frames_malformed: self.malformed.load(Ordering::Relaxed),
+            asdus_delivered: self.delivered.load(Ordering::Relaxed),
+            asdus_dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    fn supervise(
+        &self,
+        filter: SubscriptionFilter,
+        config: SupervisionConfig,
+        on_event: Box<dyn FnMut(StreamEvent) + Send>,
+    ) {
+        *self.supervision.lock().unwrap() = Some(Supervision {
+            filter,
+            supervisor: Supervisor::new(config),
+            on_event,
+        });
+    }
+
+    fn supervision(&self) -> Vec<StreamSupervision> {
+        self.supervision
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|supervision| supervision.supervisor.streams())
+            .unwrap_or_default()
+    }
+
+    fn reset_supervision(&self) {
+        if let Some(supervision) = self.supervision.lock().unwrap().as_mut() {
+            supervision.supervisor.reset_counters();
+        }
+    }
+
+    fn summarize(&self, interval: Duration, on_summary: Box<dyn FnMut(&ReceiveStats) + Send>) {
+        *self.summaries.lock().unwrap() = Some(Summaries {
+            interval,
+            next: Instant::now() + interval,
+            stats: ReceiveStats::default(),
+            on_summary,
+        });
+    }
+
+    /// Records the arrival of `packet` in the receive statistics of its
+    /// stream, named after its first ASDU, with the latency of its last one.
+    fn record_arrival(&self, packet: &Packet, timestamp: SystemTime, timestamped: bool) {
+        let asdus = &packet.sampled_value.asdu;
+        let (Some(first), Some(last)) = (asdus.first(), asdus.last()) else {
+            return;
+        };
+        let key = StreamKey {
+            app_id: packet.sampled_value.app_id,
+            sv_id: first.sv_id.clone(),
+        };
+        let samples = asdus.len() as u64;
+        let timing = SampleTiming {
+            smp_count: last.smp_count,
+            sample_rate: match last.smp_mode {
+                None | Some(SAMPLES_PER_SECOND) => last.smp_rate.map(u32::from),
+                Some(_) => None,
+            },
+            synchronized: last.smp_sync != SampleSync::Internal,
+        };
+        let record = |stats: &mut ReceiveStats| {
+            stats.record(&key, samples, timestamp, timestamped);
+            stats.record_timing(&key, timing, timestamp, timestamped);
+        };
+        record(&mut self.receive_stats.lock().unwrap());
+        if let Some(summaries) = self.summaries.lock().unwrap().as_mut() {
+            record(&mut summaries.stats);
+        }
+    }
+
+    /// Hands the ASDUs of `packet` to every subscription they match and to
+    /// the supervision. Channel subscriptions whose receiver is gone are
+    /// removed.
+    fn dispatch(&self, packet: &Packet, timestamp: SystemTime) {
+        let received: Vec<ReceivedAsdu> = packet
+            .sampled_value
+            .asdu
+            .iter()
+            .enumerate()
+            .map(|(index, asdu)| ReceivedAsdu {
+                timestamp,
+                src_mac: packet.ethernet.src_mac,
+                dst_mac: packet.ethernet.dst_mac,
+                vlan: packet.ethernet.vlan,
+                app_id: packet.sampled_value.app_id,
+                simulation: packet.sampled_value.simulation,
+                index,
+                asdu: asdu.clone(),
+            })
+            .collect();
+
+        let mut subscriptions = self.subscriptions.lock().unwrap();
+        subscriptions.retain_mut(|subscription| {
+            if !subscription.filter.matches_frame(packet) {
+                return true;
+            }
+            for received in &received {
+                if !subscription.filter.matches_asdu(&received.asdu) {
+                    continue;
+                }
+                match &mut subscription.sink {
+                    Sink::Callback(callback) => callback(received),
+                    Sink::Channel(sender) => match sender.try_send(received.clone()) {
+                        Ok(()) => {}
+                        Err(TrySendError::Full(_)) => {
+                            self.dropped.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        Err(TrySendError::Disconnected(_)) => return false,
+                    },
+                }
+                self.delivered.fetch_add(1, Ordering::Relaxed);
+            }
+            true
+        });
+        drop(subscriptions);
+
+        if let Some(supervision) = self.supervision.lock().unwrap().as_mut()
+            && supervision.filter.matches_frame(packet)
+        {
+            let Supervision {
+                filter,
+                supervisor,
+                on_event,
+            } = supervision;
+            for received in &received {
+                if filter.matches_asdu(&received.asdu) {
+                    supervisor.observe(received, &mut *on_event);
+                }
+            }
+        }
+    }
+
+    /// Checks the stream timeouts and hands out a summary when one is due.
+    fn tick(&self) {
+        if let Some(supervision) = self.supervision.lock().unwrap().as_mut() {
+            supervision
+                .supervisor
+                .check_timeouts(SystemTime::now(), &mut supervision.on_event);
+        }
+        if let Some(summaries) = self.summaries.lock().unwrap().as_mut() {
+            let now = Instant::now();
+            if now >= summaries.next {
+                (summaries.on_summary)(&summaries.stats);
+                summaries.stats.reset();
+                // Skips the summaries missed while the thread was stalled.
+                while summaries.next <= now {
+                    summaries.next += summaries.interval;
+                }
+            }
+        }
+    }
+}
+
+/// Receives Sampled Values on one transport and delivers the decoded ASDUs to
+/// any number of independent subscriptions, each with its own
+/// [`SubscriptionFilter`]: through a callback run on the receive thread, or a
+/// bounded channel read as an iterator. Frames that fail to decode are
+/// counted and skipped.
+pub struct SvSubscriber<T: FrameTransport = RawSocket> {
+    transport: T,
+    realtime: Option<RealtimeConfig>,
+    shared: Arc<Shared>,
+}
+
+impl SvSubscriber<RawSocket> {
+    /// Opens a raw socket receiving Sampled Values on `interface`. It
+    /// listens to every EtherType: the kernel drops the 802.1Q tag of frames
+    /// delivered to a Sampled Values socket when no VLAN interface claims
+    /// them, which would defeat VLAN filters. A socket filter keeps the other
+    /// EtherTypes in the kernel.
+    pub fn new(interface: &str) -> io::Result<Self> {
+        let socket = RawSocket::try_new(interface.to_string(), libc::ETH_P_ALL as u16)?;
+        socket.attach_filter(EthTypes::SampledValues as u16)?;
+        Ok(Self::with_transport(socket))
+    }
+}
+
+impl<T: FrameTransport> SvSubscriber<T> {
+    pub fn with_transport(transport: T) -> Self {
+        SvSubscriber {
+            transport,
+            realtime: None,
+            shared: Arc::new(Shared::default()),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.transport
+    }
+
+    /// Real-time setup applied to the receive thread before the first frame.
+    pub fn set_realtime(&mut self, realtime: Option<RealtimeConfig>) {
+        self.realtime = realtime;
+    }
+
+    /// Outcome of the real-time setup, once receiving started.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime.lock().unwrap().clone()
+    }
+
+    /// Calls `callback` on the receive thread for every matching ASDU. The
+    /// callback must return quickly and must not subscribe or unsubscribe.
+    pub fn subscribe<F>(&self, filter: SubscriptionFilter, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&ReceivedAsdu) + Send + 'static,
+    {
+        self.shared.add(filter, Sink::Callback(Box::new(callback)))
+    }
+
+    /// Queues matching ASDUs on a channel of `capacity` ASDUs. When the
+    /// channel is full, new ASDUs are dropped and counted rather than
+    /// stalling the receive thread.
+    pub fn subscribe_channel(&self, filter: SubscriptionFilter, capacity: usize) -> AsduReceiver {
+        self.shared.subscribe_channel(filter, capacity)
+    }
+
+    /// Removes a subscription, ending its channel. False when it was unknown.
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.shared.unsubscribe(id)
+    }
+
+    pub fn stats(&self) -> SubscriberStats {
+        self.shared.stats()
+    }
+
+    /// Supervises the streams matching `filter`, see [`Supervisor`], and
+    /// calls `on_event` on the receive thread for what it finds. Replaces the
+    /// previous supervision. Timeouts are checked after every frame and, on a
+    /// transport with a read timeout, while the line is idle.
+    pub fn supervise<F>(&self, filter: SubscriptionFilter, config: SupervisionConfig, on_event: F)
+    where
+        F: FnMut(StreamEvent) + Send + 'static,
+    {
+        self.shared.supervise(filter, config, Box::new(on_event))
+    }
+
+    /// State and counters of the supervised streams.
+    pub fn supervision(&self) -> Vec<StreamSupervision> {
+        self.shared.supervision()
+    }
+
+    pub fn reset_supervision(&self) {
+        self.shared.reset_supervision()
+    }
+
+    /// Receive statistics per stream since the start or the last reset,
+    /// computed from the transport's receive timestamps when it has them.
+    pub fn receive_stats(&self) -> Vec<StreamReceiveStats> {
+        self.shared.receive_stats.lock().unwrap().streams()
+    }
+
+    pub fn reset_receive_stats(&self) {
+        self.shared.receive_stats.lock().unwrap().reset();
+    }
+
+    /// Calls `on_summary` on the receive thread every `interval` with the
+    /// receive statistics of that interval, e.g. to print them. Replaces the
+    /// previous summaries. Like timeouts, summaries are checked after every
+    /// frame and, on a transport with a read timeout, while the line is idle.
+    pub fn summarize<F>(&self, interval: Duration, on_summary: F)
+    where
+        F: FnMut(&ReceiveStats) + Send + 'static,
+    {
+        self.shared.summarize(interval, Box::new(on_summary))
+    }
+
+    /// Receives on the calling thread. Stops after `frames` frames, or never
+    /// when `None`, or when the transport runs out of frames, and returns the
+    /// number of frames received.
+    pub fn run(&mut self, frames: Option<u64>) -> io::Result<u64> {
+        self.shared.running.store(true, Ordering::Release);
+        let result = self.run_while(frames);
+        self.shared.running.store(false, Ordering::Release);
+        result
+    }
+
+    fn run_while(&mut self, frames: Option<u64>) -> io::Result<u64> {
+        if let Some(realtime) = &self.realtime {
+            *self.shared.realtime.lock().unwrap() = Some(realtime.apply());
+        }
+        let mut received: u64 = 0;
+        while self.shared.running.load(Ordering::Acquire)
+            && frames.is_none_or(|frames| received < frames)
+        {
+            let frame = match self.transport.recv_frame() {
+                Ok(frame) => frame,
+                // The read timeout only bounds how late a stop is noticed.
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    self.shared.tick();
+                    continue;
+                }
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            };
+            if other_ether_type(&frame.data) {
+                continue;
+            }
+            let timestamp = frame.timestamp.unwrap_or_else(SystemTime::now);
+            received += 1;
+            self.shared.received.fetch_add(1, Ordering::Relaxed);
+            match Packet::try_from_bytes(&frame.data) {
+                Ok(packet) => {
+                    self.shared
+                        .record_arrival(&packet, timestamp, frame.timestamp.is_some());
+                    self.shared.dispatch(&packet, timestamp);
+                }
+                Err(_) => {
+                    self.shared.malformed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            self.shared.tick();
+        }
+        Ok(received)
+    }
+
+    /// Starts receiving on a dedicated thread. Changes the read timeout of
+    /// the transport, so the thread notices a stop while the line is idle.
+    pub fn start(mut self) -> io::Result<SubscriberHandle<T>>
+    where
+        T: Send + 'static,
+    {
+        self.transport.set_read_timeout(Some(POLL_INTERVAL))?;
+        let shared = self.shared.clone();
+        shared.running.store(true, Ordering::Release);
+        let thread = thread::Builder::new()
+            .name("sv-sub".to_string())
+            .spawn(move || {
+                let result = self.run_while(None);
+                self.shared.running.store(false, Ordering::Release);
+                // Ends the channel subscriptions, so their iterators finish.
+                self.shared.subscriptions.lock().unwrap().clear();
+                result.map(|_| self.transport)
+            })?;
+        Ok(SubscriberHandle {
+            shared,
+            thread: Some(thread),
+        })
+    }
+}
+
+/// True for complete Ethernet headers of anything but Sampled Values, which
+/// a socket listening to every EtherType receives as well.
+fn other_ether_type(data: &[u8]) -> bool {
+    Ethernet::try_from_bytes(data).is_ok_and(|ethernet| {
+        u16::from_be_bytes(ethernet.ether_type) != EthTypes::SampledValues as u16
+    })
+}
+
+/// Handle to a subscriber running on its own thread. Subscriptions can be
+/// added and removed while it runs. Dropping it stops the subscriber without
+/// waiting for the thread.
+pub struct SubscriberHandle<T> {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<io::Result<T>>>,
+}
+
+impl<T> SubscriberHandle<T> {
+    /// False once stopped or after the thread hit a receive error.
+    pub fn is_running(&self) -> bool {
+        self.shared.running.load(Ordering::Acquire)
+    }
+
+    /// See [`SvSubscriber::subscribe`].
+    pub fn subscribe<F>(&self, filter: SubscriptionFilter, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&ReceivedAsdu) + Send + 'static,
+    {
+        self.shared.add(filter, Sink::Callback(Box::new(callback)))
+    }
+
+    /// See [`SvSubscriber::subscribe_channel`].
+    pub fn subscribe_channel(&self, filter: SubscriptionFilter, capacity: usize) -> AsduReceiver {
+        self.shared.subscribe_channel(filter, capacity)
+    }
+
+    pub fn unsubscribe(&self, id: SubscriptionId) -> bool {
+        self.shared.unsubscribe(id)
+    }
+
+    pub fn stats(&self) -> SubscriberStats {
+        self.shared.stats()
+    }
+
+    /// Outcome of the real-time setup, once the thread applied it.
+    pub fn realtime_report(&self) -> Option<RealtimeReport> {
+        self.shared.realtime.lock().unwrap().clone()
+    }
+
+    /// See [`SvSubscriber::supervise`].
+    pub fn supervise<F>(&self, filter: SubscriptionFilter, config: SupervisionConfig, on_event: F)
+    where
+        F: FnMut(StreamEvent) + Send + 'static,
+    {
+        self.shared.supervise(filter, config, Box::new(on_event))
+    }
+
+    pub fn supervision(&self) -> Vec<StreamSupervision> {
+        self.shared.supervision()
+    }
+
+    pub fn reset_supervision(&self) {
+        self.shared.reset_supervision()
+    }
+
+    /// See [`SvSubscriber::receive_stats`].
+    pub fn receive_stats(&self) -> Vec<StreamReceiveStats> {
+        self.shared.receive_stats.lock().unwrap().streams()
+    }
+
+    pub fn reset_receive_stats(&self) {
+        self.shared.receive_stats.lock().unwrap().reset();
+    }
+
+    /// See [`SvSubscriber::summarize`].
+    pub fn summarize<F>(&self, interval: Duration, on_summary: F)
+    where
+        F: FnMut(&ReceiveStats) + Send + 'static,
+    {
+        self.shared.summarize(interval, Box::new(on_summary))
+    }
+
+    /// Stops within the read timeout, ends every subscription and returns the
+    /// transport.
+    pub fn stop(mut self) -> io::Result<T> {
+        self.shared.running.store(false, Ordering::Release);
+        match self.thread.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(io::Error::other("Subscriber thread panicked")),
+            None => Err(io::Error::other("Subscriber thread already joined")),
+        }
+    }
+}
+
+impl<T> Drop for SubscriberHandle<T> {
+    fn drop(&mut self) {
+        self.shared.running.store(false, Ordering::Release);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::publisher::config::PublisherConfig;
+    use crate::network::publisher::sampled_value_pub::publish;
+    use crate::network::transport::memory::VirtualSwitch;
+    use crate::protocols::{ethernet::model::Ethernet, sampled_values::model::SampledValue};
+
+    fn stream(app_id: u16, sv_id: &str, vlan: Option<u16>) -> Packet {
+        PublisherConfig::builder()
+            .app_id(app_id)
+            .sv_id(sv_id)
+            .vlan(vlan.map(|id| Vlan {
+                priority: 4,
+                dei: false,
+                id,
+            }))
+            .asdus_per_frame(2)
+            .build()
+            .unwrap()
+            .packet()
+    }
+
+    #[test]
+    fn publish_and_subscribe_over_virtual_switch() {
+        let sv_bytes: &[u8] = &[
+            0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
+            0x60, 0x5c, // PDU
+            0x80, 0x01, 0x01, // number of ASDU
+            0xa2, 0x57, // sequence of ASDU
+            0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04,
+            0x00, 0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff,
+            0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+            0x00, 0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00,
+            0x00, 0x20, 0x00, // ASDU
+        ];
+        let mut packet = Packet {
+            ether_type: [0x88, 0xba],
+            ethernet: Ethernet {
+                dst_mac: [0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01],
+                src_mac: [0x00, 0x00, 0x00, 0x00, 0x00, 0x01],
+                vlan: None,
+                ether_type: [0x88, 0xba],
+            },
+            sampled_value: SampledValue::from_bytes(sv_bytes),
+        };
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let mut subscriber_port = switch.port();
+
+        publisher_port.send_frame(&[0x01; 10], None).unwrap();
+        publish(&mut publisher_port, &mut packet, Some(3)).unwrap();
+
+        let mut smp_counts = vec![];
+        let mut errors = 0;
+        subscribe(&mut subscriber_port, Some(4), |packet| match packet {
+            Ok(packet) => smp_counts.push(packet.sampled_value.asdu[0].smp_count),
+            Err(_) => errors += 1,
+        })
+        .unwrap();
+        assert_eq!(smp_counts, vec![0, 1, 2]);
+        assert_eq!(errors, 1);
+    }
+
+    #[test]
+    fn independent_subscriptions_on_one_transport() {
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let subscriber = SvSubscriber::with_transport(switch.port());
+
+        let all = subscriber.subscribe_channel(SubscriptionFilter::new(), 16);
+        let tagged = subscriber.subscribe_channel(SubscriptionFilter::new().vlan(5), 16);
+        let (sender, counted) = mpsc::channel();
+        subscriber.subscribe(
+            SubscriptionFilter::new()
+                .app_id(0x4001)
+                .sv_id("B")
+                .conf_rev(1),
+            move |received| sender.send(received.asdu.sv_id.clone()).unwrap(),
+        );
+        let unknown = subscriber.subscribe_channel(SubscriptionFilter::new().conf_rev(2), 16);
+        let handle = subscriber.start().unwrap();
+
+        let frames = [
+            stream(0x4000, "A", None).to_bytes(),
+            vec![0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00],
+            // An ARP header, ignored rather than counted.
+            vec![
+                0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06,
+            ],
+            stream(0x4001, "B", Some(5)).to_bytes(),
+        ];
+        for frame in &frames {
+            publisher_port.send_frame(frame, None).unwrap();
+        }
+
+        let first = all.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(first.asdu.sv_id, "A");
+        assert_eq!(first.app_id, 0x4000);
+        assert_eq!(first.index, 0);
+        assert_eq!(all.recv_timeout(Duration::from_secs(1)).unwrap().index, 1);
+        let second = tagged.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(second.asdu.sv_id, "B");
+        assert_eq!(second.vlan.map(|vlan| vlan.id), Some(5));
+        assert_eq!(counted.recv_timeout(Duration::from_secs(1)).unwrap(), "B");
+        assert!(handle.unsubscribe(unknown.id()));
+        assert!(!handle.unsubscribe(unknown.id()));
+
+        // The last delivery may still be in progress.
+        let deadline = std::time::Instant::now() + Duration::from_secs(1);
+        while handle.stats().asdus_delivered < 8 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(1));
+        }
+        let stats = handle.stats();
+        assert_eq!(stats.frames_received, 3);
+        assert_eq!(stats.frames_malformed, 1);
+        assert_eq!(stats.asdus_delivered, 8);
+        assert_eq!(stats.asdus_dropped, 0);
+
+        handle.stop().unwrap();
+        // Stopping ends the channels after the ASDUs still queued.
+        assert_eq!(
+            all.map(|received| received.asdu.sv_id).collect::<Vec<_>>(),
+            ["B", "B"]
+        );
+        assert_eq!(tagged.count(), 1);
+        assert_eq!(unknown.count(), 0);
+    }
+
+    #[test]
+    fn full_channels_drop_instead_of_blocking() {
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let mut subscriber = SvSubscriber::with_transport(switch.port());
+        let small = subscriber.subscribe_channel(SubscriptionFilter::new(), 1);
+        let frame = stream(0x4000, "A", None).to_bytes();
+        publisher_port.send_frame(&frame, None).unwrap();
+
+        assert_eq!(subscriber.run(Some(1)).unwrap(), 1);
+        assert_eq!(subscriber.stats().asdus_delivered, 1);
+        assert_eq!(subscriber.stats().asdus_dropped, 1);
+        assert!(small.try_recv().is_some());
+        assert!(small.try_recv().is_none());
+    }
+
+    #[test]
+    fn realtime_setup_applies_on_the_receive_thread() {
+        use crate::network::realtime::RealtimeStep;
+
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let mut subscriber = SvSubscriber::with_transport(switch.port());
+        subscriber.set_realtime(Some(RealtimeConfig::new().prefault_stack(16 * 1024)));
+        assert_eq!(subscriber.realtime_report(), None);
+        let handle = subscriber.start().unwrap();
+        let asdus = handle.subscribe_channel(SubscriptionFilter::new(), 4);
+        let frame = stream(0x4000, "A", None).to_bytes();
+        publisher_port.send_frame(&frame, None).unwrap();
+        assert!(asdus.recv_timeout(Duration::from_secs(1)).is_some());
 
-    loop {
-        let bytes = socket.recv();
-        let packet = crate::network::packet::Packet::from_bytes(&bytes);
-        println!("{:?}", packet);
+        let report = handle.realtime_report().unwrap();
+        assert_eq!(report.applied, vec![RealtimeStep::StackPrefault]);
+        handle.stop().unwrap();
     }
 }
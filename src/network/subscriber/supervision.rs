@@ -0,0 +1,747 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use crate::network::subscriber::sampled_value_sub::ReceivedAsdu;
+use crate::protocols::sampled_values::model::SampleSync;
+
+/// Number of recent smpCnt values remembered per stream to tell duplicates
+/// from samples arriving out of order, and of lost ones remembered to tell
+/// late samples from a sequence restarting further back.
+const RECENT_SAMPLES: usize = 64;
+/// Consecutive samples behind the sequence, and never reported lost, after
+/// which the sequence is taken to continue from them.
+const RESYNC_SAMPLES: u32 = 4;
+/// smpMod value for smpRate in samples per second.
+const SAMPLES_PER_SECOND: u16 = 1;
+
+/// A stream, as told apart by a subscriber.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct StreamKey {
+    pub app_id: u16,
+    pub sv_id: String,
+}
+
+impl StreamKey {
+    pub fn of(received: &ReceivedAsdu) -> Self {
+        StreamKey {
+            app_id: received.app_id,
+            sv_id: received.asdu.sv_id.clone(),
+        }
+    }
+}
+
+impl fmt::Display for StreamKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:#06x})", self.sv_id, self.app_id)
+    }
+}
+
+/// Settings of a [`Supervisor`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupervisionConfig {
+    /// smpCnt wraps to 0 at this rate, unless the stream has its own in
+    /// `rates` or carries smpRate in samples per second.
+    pub sample_rate: u32,
+    /// Silence after which a stream is reported as timed out.
+    pub timeout: Duration,
+    /// Sample rates of streams by svID, when they differ from `sample_rate`.
+    pub rates: BTreeMap<String, u32>,
+}
+
+impl SupervisionConfig {
+    pub fn new(sample_rate: u32, timeout: Duration) -> Self {
+        SupervisionConfig {
+            sample_rate,
+            timeout,
+            rates: BTreeMap::new(),
+        }
+    }
+
+    pub fn stream_rate(mut self, sv_id: impl Into<String>, sample_rate: u32) -> Self {
+        self.rates.insert(sv_id.into(), sample_rate);
+        self
+    }
+
+    fn rate_of(&self, sv_id: &str) -> u32 {
+        self.rates
+            .get(sv_id)
+            .copied()
+            .unwrap_or(self.sample_rate)
+            .max(1)
+    }
+}
+
+/// Something a [`Supervisor`] noticed on a stream.
+#[derive(Clone, Debug, PartialEq)]
+pub enum SupervisionEvent {
+    /// First sample of the stream.
+    Started,
+    /// Samples `expected` up to, but not including, `received` are missing.
+    Lost {
+        expected: u16,
+        received: u16,
+        count: u16,
+    },
+    /// `smp_count` was received again.
+    Duplicate {
+        smp_count: u16,
+    },
+    /// `received` arrived after later samples. When it was counted as
+    /// lost, it no longer is.
+    OutOfOrder {
+        expected: u16,
+        received: u16,
+    },
+    /// The sequence went back from `expected` to `received` and carried on
+    /// from there, as when a publisher restarts. The samples that followed
+    /// `received` up to this event are part of the jump, not out of order.
+    Jumped {
+        expected: u16,
+        received: u16,
+    },
+    /// smpCnt at or beyond the sample rate of the stream.
+    SmpCntOutOfRange {
+        smp_count: u16,
+        sample_rate: u32,
+    },
+    ConfRevChanged {
+        from: u32,
+        to: u32,
+    },
+    DatasetChanged {
+        from: Option<String>,
+        to: Option<String>,
+    },
+    SmpSynchChanged {
+        from: SampleSync,
+        to: SampleSync,
+    },
+    SimulationChanged {
+        simulation: bool,
+    },
+    /// Nothing received for longer than the timeout.
+    Timeout {
+        silence: Duration,
+    },
+    /// First sample after a timeout. The smpCnt sequence restarts from it.
+    Resumed {
+        silence: Duration,
+    },
+}
+
+/// A [`SupervisionEvent`] of one stream. `time` is the receive time of the
+/// sample, or the time of the check for timeouts.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamEvent {
+    pub stream: StreamKey,
+    pub time: SystemTime,
+    pub event: SupervisionEvent,
+}
+
+impl fmt::Display for StreamEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.stream)?;
+        match &self.event {
+            SupervisionEvent::Started => write!(f, "started"),
+            SupervisionEvent::Lost {
+                expected,
+                received,
+                count,
+            } => write!(
+                f,
+                "{} samples lost, smpCnt {} expected, {} received",
+                count, expected, received
+            ),
+            SupervisionEvent::Duplicate { smp_count } => {
+                write!(f, "smpCnt {} duplicated", smp_count)
+            }
+            SupervisionEvent::OutOfOrder { expected, received } => {
+                write!(f, "smpCnt {} out of order, {} expected", received, expected)
+            }
+            SupervisionEvent::Jumped { expected, received } => {
+                write!(
+                    f,
+                    "smpCnt jumped back to {}, {} expected",
+                    received, expected
+                )
+            }
+            SupervisionEvent::SmpCntOutOfRange {
+                smp_count,
+                sample_rate,
+            } => write!(
+                f,
+                "smpCnt {} out of range at {} samples/s",
+                smp_count, sample_rate
+            ),
+            SupervisionEvent::ConfRevChanged { from, to } => {
+                write!(f, "confRev changed from {} to {}", from, to)
+            }
+            SupervisionEvent::DatasetChanged { from, to } => {
+                write!(f, "datSet changed from {:?} to {:?}", from, to)
+            }
+            SupervisionEvent::SmpSynchChanged { from, to } => {
+                write!(f, "smpSynch changed from {:?} to {:?}", from, to)
+            }
+            SupervisionEvent::SimulationChanged { simulation } => {
+                write!(
+                    f,
+                    "simulation {}",
+                    if *simulation { "set" } else { "cleared" }
+                )
+            }
+            SupervisionEvent::Timeout { silence } => write!(f, "timed out after {:?}", silence),
+            SupervisionEvent::Resumed { silence } => write!(f, "resumed after {:?}", silence),
+        }
+    }
+}
+
+/// Counters of one stream since it started or since the last reset.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StreamCounters {
+    pub samples: u64,
+    pub lost: u64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+    pub jumps: u64,
+    pub out_of_range: u64,
+    pub conf_rev_changes: u64,
+    pub dataset_changes: u64,
+    pub smp_synch_changes: u64,
+    pub simulation_changes: u64,
+    pub timeouts: u64,
+}
+
+/// Snapshot of a supervised stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StreamSupervision {
+    pub stream: StreamKey,
+    pub sample_rate: u32,
+    pub counters: StreamCounters,
+    pub last_smp_count: u16,
+    pub last_seen: SystemTime,
+    pub timed_out: bool,
+}
+
+struct StreamState {
+    sample_rate: u32,
+    /// Latest smpCnt of the in-order sequence.
+    last: u16,
+    recent: VecDeque<u16>,
+    /// Latest smpCnt values counted as lost.
+    missing: VecDeque<u16>,
+    /// Consecutive samples behind the sequence that were never lost.
+    behind: Option<BackwardRun>,
+    conf_rev: u32,
+    dataset: Option<String>,
+    smp_sync: SampleSync,
+    simulation: bool,
+    last_seen: SystemTime,
+    timed_out: bool,
+    counters: StreamCounters,
+}
+
+/// Samples following each other behind the in-order sequence.
+struct BackwardRun {
+    /// smpCnt the sequence expected when the run started.
+    expected: u16,
+    first: u16,
+    last: u16,
+    samples: u32,
+}
+
+impl StreamState {
+    fn remember(&mut self, smp_count: u16) {
+        if self.recent.len() == RECENT_SAMPLES {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(smp_count);
+    }
+
+    /// Remembers the last of the `count` samples lost before `smp_count`.
+    fn remember_missing(&mut self, smp_count: u16, count: u32) {
+        let rate = self.sample_rate;
+        for back in (1..=count.min(RECENT_SAMPLES as u32)).rev() {
+            if self.missing.len() == RECENT_SAMPLES {
+                self.missing.pop_front();
+            }
+            self.missing
+                .push_back(((smp_count as u32 + rate - back) % rate) as u16);
+        }
+    }
+
+    /// Classifies `smp_count` against the sequence so far.
+    fn sequence(&mut self, smp_count: u16) -> Option<SupervisionEvent> {
+        let rate = self.sample_rate;
+        if smp_count as u32 >= rate {
+            self.counters.out_of_range += 1;
+            return Some(SupervisionEvent::SmpCntOutOfRange {
+                smp_count,
+                sample_rate: rate,
+            });
+        }
+        if self.recent.contains(&smp_count) {
+            self.counters.duplicates += 1;
+            return Some(SupervisionEvent::Duplicate { smp_count });
+        }
+        self.remember(smp_count);
+        let expected = ((self.last as u32 + 1) % rate) as u16;
+        let ahead = (smp_count as u32 + rate - expected as u32) % rate;
+        if ahead == 0 {
+            self.behind = None;
+            self.last = smp_count;
+            return None;
+        }
+        if ahead >= rate / 2 {
+            // Further than half a second ahead the sample is more likely
+            // behind.
+            return Some(self.behind(expected, smp_count));
+        }
+        self.behind = None;
+        self.last = smp_count;
+        self.counters.lost += ahead as u64;
+        self.remember_missing(smp_count, ahead);
+        Some(SupervisionEvent::Lost {
+            expected,
+            received: smp_count,
+            count: ahead as u16,
+        })
+    }
+
+    /// Classifies `smp_count`, behind the sequence: a late sample when it
+    /// was lost, or else part of a run that the sequence moves back to once
+    /// it is long enough.
+    fn behind(&mut self, expected: u16, smp_count: u16) -> SupervisionEvent {
+        if let Some(position) = self.missing.iter().position(|lost| *lost == smp_count) {
+            self.missing.remove(position);
+            self.counters.lost = self.counters.lost.saturating_sub(1);
+            self.counters.out_of_order += 1;
+            return SupervisionEvent::OutOfOrder {
+                expected,
+                received: smp_count,
+            };
+        }
+        let rate = self.sample_rate;
+        let run = match self.behind.take() {
+            Some(run) if (run.last as u32 + 1) % rate == smp_count as u32 => BackwardRun {
+                last: smp_count,
+                samples: run.samples + 1,
+                ..run
+            },
+            _ => BackwardRun {
+                expected,
+                first: smp_count,
+                last: smp_count,
+                samples: 1,
+            },
+        };
+        if run.samples < RESYNC_SAMPLES {
+            self.behind = Some(run);
+            self.counters.out_of_order += 1;
+            return SupervisionEvent::OutOfOrder {
+                expected,
+                received: smp_count,
+            };
+        }
+        // The earlier samples of the run were counted as out of order.
+        self.counters.out_of_order = self
+            .counters
+            .out_of_order
+            .saturating_sub(RESYNC_SAMPLES as u64 - 1);
+        self.counters.jumps += 1;
+        self.last = smp_count;
+        self.missing.clear();
+        SupervisionEvent::Jumped {
+            expected: run.expected,
+            received: run.first,
+        }
+    }
+}
+
+/// Supervises the streams seen by a subscriber: smpCnt continuity, wrapping
+/// at each stream's rate, changes of confRev, datSet, smpSynch and the
+/// simulation bit, and timeouts. Every finding is reported as a
+/// [`StreamEvent`] and counted per stream.
+///
+/// Feed it the received ASDUs with [`Supervisor::observe`] and call
+/// [`Supervisor::check_timeouts`] regularly, even while nothing is received.
+/// `SvSubscriber::supervise` does both on the receive thread.
+pub struct Supervisor {
+    config: SupervisionConfig,
+    streams: BTreeMap<StreamKey, StreamState>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisionConfig) -> Self {
+        Supervisor {
+            config,
+            streams: BTreeMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &SupervisionConfig {
+        &self.config
+    }
+
+    /// Checks one received ASDU and hands what it reveals to `on_event`.
+    pub fn observe<F: FnMut(StreamEvent)>(&mut self, received: &ReceivedAsdu, mut on_event: F) {
+        let key = StreamKey::of(received);
+        let asdu = &received.asdu;
+        let time = received.timestamp;
+        let mut report = |stream: &StreamKey, event| {
+            on_event(StreamEvent {
+                stream: stream.clone(),
+                time,
+                event,
+            })
+        };
+
+        // smpRate in samples per second is what smpCnt wraps at.
+        let sample_rate = match (asdu.smp_rate, asdu.smp_mode) {
+            (Some(rate), None | Some(SAMPLES_PER_SECOND)) if rate > 0 => rate as u32,
+            _ => self.config.rate_of(&asdu.sv_id),
+        };
+        let Some(state) = self.streams.get_mut(&key) else {
+            let mut state = StreamState {
+                sample_rate,
+                last: asdu.smp_count,
+                recent: VecDeque::with_capacity(RECENT_SAMPLES),
+                missing: VecDeque::with_capacity(RECENT_SAMPLES),
+                behind: None,
+                conf_rev: asdu.conf_rev,
+                dataset: asdu.dataset.clone(),
+                smp_sync: asdu.smp_sync,
+                simulation: received.simulation,
+                last_seen: time,
+                timed_out: false,
+                counters: StreamCounters {
+                    samples: 1,
+                    ..StreamCounters::default()
+                },
+            };
+            state.remember(asdu.smp_count);
+            report(&key, SupervisionEvent::Started);
+            self.streams.insert(key, state);
+            return;
+        };
+
+        state.counters.samples += 1;
+        state.sample_rate = sample_rate;
+        if state.timed_out {
+            state.timed_out = false;
+            state.last = asdu.smp_count;
+            state.recent.clear();
+            state.missing.clear();
+            state.behind = None;
+            state.remember(asdu.smp_count);
+            let silence = time.duration_since(state.last_seen).unwrap_or_default();
+            report(&key, SupervisionEvent::Resumed { silence });
+        } else if let Some(event) = state.sequence(asdu.smp_count) {
+            report(&key, event);
+        }
+        state.last_seen = state.last_seen.max(time);
+
+        if asdu.conf_rev != state.conf_rev {
+            state.counters.conf_rev_changes += 1;
+            let from = std::mem::replace(&mut state.conf_rev, asdu.conf_rev);
+            report(
+                &key,
+                SupervisionEvent::ConfRevChanged {
+                    from,
+                    to: asdu.conf_rev,
+                },
+            );
+        }
+        if asdu.dataset != state.dataset {
+            state.counters.dataset_changes += 1;
+            let from = std::mem::replace(&mut state.dataset, asdu.dataset.clone());
+            report(
+                &key,
+                SupervisionEvent::DatasetChanged {
+                    from,
+                    to: asdu.dataset.clone(),
+                },
+            );
+        }
+        if asdu.smp_sync != state.smp_sync {
+            state.counters.smp_synch_changes += 1;
+            let from = std::mem::replace(&mut state.smp_sync, asdu.smp_sync);
+            report(
+                &key,
+                SupervisionEvent::SmpSynchChanged {
+                    from,
+                    to: asdu.smp_sync,
+                },
+            );
+        }
+        if received.simulation != state.simulation {
+            state.counters.simulation_changes += 1;
+            state.simulation = received.simulation;
+            report(
+                &key,
+                SupervisionEvent::SimulationChanged {
+                    simulation: received.simulation,
+                },
+            );
+        }
+    }
+
+    /// Reports the streams silent for longer than the timeout at `now`, once
+    /// per silence.
+    pub fn check_timeouts<F: FnMut(StreamEvent)>(&mut self, now: SystemTime, mut on_event: F) {
+        for (key, state) in &mut self.streams {
+            let silence = now.duration_since(state.last_seen).unwrap_or_default();
+            if !state.timed_out && silence > self.config.timeout {
+                state.timed_out = true;
+                state.counters.timeouts += 1;
+                on_event(StreamEvent {
+                    stream: key.clone(),
+                    time: now,
+                    event: SupervisionEvent::Timeout { silence },
+                });
+            }
+        }
+    }
+
+    pub fn streams(&self) -> Vec<StreamSupervision> {
+        self.streams
+            .iter()
+            .map(|(key, state)| StreamSupervision {
+                stream: key.clone(),
+                sample_rate: state.sample_rate,
+                counters: state.counters,
+                last_smp_count: state.last,
+                last_seen: state.last_seen,
+                timed_out: state.timed_out,
+            })
+            .collect()
+    }
+
+    pub fn counters(&self, stream: &StreamKey) -> Option<StreamCounters> {
+        self.streams.get(stream).map(|state| state.counters)
+    }
+
+    /// Zeroes the counters, keeping the state of every stream.
+    pub fn reset_counters(&mut self) {
+        for state in self.streams.values_mut() {
+            state.counters = StreamCounters::default();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::publisher::config::PublisherConfig;
+    use crate::network::subscriber::sampled_value_sub::{SubscriptionFilter, SvSubscriber};
+    use crate::network::transport::{FrameTransport, memory::VirtualSwitch};
+    use std::sync::mpsc;
+
+    fn sample(smp_count: u16, millis: u64) -> ReceivedAsdu {
+        let packet = PublisherConfig::builder()
+            .app_id(0x4000)
+            .sv_id("MU01")
+            .build()
+            .unwrap()
+            .packet();
+        let mut asdu = packet.sampled_value.asdu[0].clone();
+        asdu.smp_count = smp_count;
+        ReceivedAsdu {
+            timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(millis),
+            src_mac: packet.ethernet.src_mac,
+            dst_mac: packet.ethernet.dst_mac,
+            vlan: None,
+            app_id: 0x4000,
+            simulation: false,
+            index: 0,
+            asdu,
+        }
+    }
+
+    #[test]
+    fn sequence_and_header_changes() {
+        let mut supervisor = Supervisor::new(
+            SupervisionConfig::new(4800, Duration::from_millis(10)).stream_rate("MU01", 4000),
+        );
+        let mut events = vec![];
+        let mut feed = |supervisor: &mut Supervisor, received: ReceivedAsdu| {
+            supervisor.observe(&received, |event| events.push(event.event));
+        };
+        for smp_count in [3998, 3999, 0, 1] {
+            feed(&mut supervisor, sample(smp_count, 0));
+        }
+        feed(&mut supervisor, sample(1, 0));
+        feed(&mut supervisor, sample(5, 1));
+        feed(&mut supervisor, sample(3, 1));
+        feed(&mut supervisor, sample(4000, 1));
+        let changed = |smp_count, millis| {
+            let mut received = sample(smp_count, millis);
+            received.asdu.conf_rev += 1;
+            received.asdu.smp_sync = SampleSync::Global;
+            received.simulation = true;
+            received
+        };
+        feed(&mut supervisor, changed(6, 1));
+
+        assert_eq!(
+            events,
+            [
+                SupervisionEvent::Started,
+                SupervisionEvent::Duplicate { smp_count: 1 },
+                SupervisionEvent::Lost {
+                    expected: 2,
+                    received: 5,
+                    count: 3
+                },
+                SupervisionEvent::OutOfOrder {
+                    expected: 6,
+                    received: 3
+                },
+                SupervisionEvent::SmpCntOutOfRange {
+                    smp_count: 4000,
+                    sample_rate: 4000
+                },
+                SupervisionEvent::ConfRevChanged { from: 1, to: 2 },
+                SupervisionEvent::SmpSynchChanged {
+                    from: SampleSync::Internal,
+                    to: SampleSync::Global
+                },
+                SupervisionEvent::SimulationChanged { simulation: true },
+            ]
+        );
+
+        let mut timeouts = vec![];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_millis(20);
+        supervisor.check_timeouts(now, |event| timeouts.push(event));
+        supervisor.check_timeouts(now, |event| timeouts.push(event));
+        assert_eq!(timeouts.len(), 1);
+        assert_eq!(
+            timeouts[0].to_string(),
+            "MU01 (0x4000): timed out after 19ms"
+        );
+        let mut resumed = vec![];
+        supervisor.observe(&changed(100, 30), |event| resumed.push(event.event));
+        assert_eq!(
+            resumed,
+            [SupervisionEvent::Resumed {
+                silence: Duration::from_millis(29)
+            }]
+        );
+
+        let stream = &supervisor.streams()[0];
+        assert_eq!(stream.sample_rate, 4000);
+        assert_eq!(stream.last_smp_count, 100);
+        assert_eq!(
+            stream.counters,
+            StreamCounters {
+                samples: 10,
+                // smpCnt 3 arrived late, so it is no longer lost.
+                lost: 2,
+                duplicates: 1,
+                out_of_order: 1,
+                out_of_range: 1,
+                conf_rev_changes: 1,
+                smp_synch_changes: 1,
+                simulation_changes: 1,
+                timeouts: 1,
+                ..StreamCounters::default()
+            }
+        );
+        supervisor.reset_counters();
+        assert_eq!(
+            supervisor.counters(&stream.stream),
+            Some(StreamCounters::default())
+        );
+    }
+
+    #[test]
+    fn backward_jump_resynchronises_once() {
+        let mut supervisor = Supervisor::new(SupervisionConfig::new(4800, Duration::from_secs(1)));
+        let mut events = vec![];
+        let sequence = [1000, 1001, 1004, 1002, 100, 101, 102, 103, 104, 105];
+        for smp_count in sequence {
+            supervisor.observe(&sample(smp_count, 0), |event| events.push(event.event));
+        }
+        assert_eq!(
+            events,
+            [
+                SupervisionEvent::Started,
+                SupervisionEvent::Lost {
+                    expected: 1002,
+                    received: 1004,
+                    count: 2
+                },
+                SupervisionEvent::OutOfOrder {
+                    expected: 1005,
+                    received: 1002
+                },
+                SupervisionEvent::OutOfOrder {
+                    expected: 1005,
+                    received: 100
+                },
+                SupervisionEvent::OutOfOrder {
+                    expected: 1005,
+                    received: 101
+                },
+                SupervisionEvent::OutOfOrder {
+                    expected: 1005,
+                    received: 102
+                },
+                SupervisionEvent::Jumped {
+                    expected: 1005,
+                    received: 100
+                },
+            ]
+        );
+        let counters = supervisor.streams()[0].counters;
+        assert_eq!(counters.lost, 1);
+        assert_eq!(counters.out_of_order, 1);
+        assert_eq!(counters.jumps, 1);
+        assert_eq!(supervisor.streams()[0].last_smp_count, 105);
+    }
+
+    #[test]
+    fn carried_sample_rate_sets_the_wrap() {
+        let mut supervisor = Supervisor::new(SupervisionConfig::new(4800, Duration::from_secs(1)));
+        let mut events = vec![];
+        for smp_count in [3998, 3999, 0] {
+            let mut received = sample(smp_count, 0);
+            received.asdu.smp_rate = Some(4000);
+            received.asdu.smp_mode = Some(SAMPLES_PER_SECOND);
+            supervisor.observe(&received, |event| events.push(event.event));
+        }
+        assert_eq!(events, [SupervisionEvent::Started]);
+        assert_eq!(supervisor.streams()[0].sample_rate, 4000);
+    }
+
+    #[test]
+    fn subscriber_reports_idle_streams() {
+        let switch = VirtualSwitch::new();
+        let mut publisher_port = switch.port();
+        let subscriber = SvSubscriber::with_transport(switch.port());
+        let (sender, events) = mpsc::channel();
+        subscriber.supervise(
+            SubscriptionFilter::new().app_id(0x4000),
+            SupervisionConfig::new(4000, Duration::from_millis(20)),
+            move |event| sender.send(event.event).unwrap(),
+        );
+        let handle = subscriber.start().unwrap();
+        let mut packet = PublisherConfig::builder()
+            .app_id(0x4000)
+            .build()
+            .unwrap()
+            .packet();
+        for smp_count in [0, 1, 3] {
+            packet.sampled_value.asdu[0].smp_count = smp_count;
+            publisher_port.send_frame(&packet.to_bytes(), None).unwrap();
+        }
+
+        let next = || events.recv_timeout(Duration::from_secs(1)).unwrap();
+        assert_eq!(next(), SupervisionEvent::Started);
+        assert!(matches!(next(), SupervisionEvent::Lost { count: 1, .. }));
+        // Reported from the idle receive loop.
+        assert!(matches!(next(), SupervisionEvent::Timeout { .. }));
+        assert_eq!(handle.supervision()[0].counters.timeouts, 1);
+        handle.stop().unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use crate::network::{async_socket::AsyncRawSocket, packet::Packet};
+
+/// Sampled Values subscriber for tokio runtimes, yielding every decoded frame
+/// received on the socket. A frame that does not decode is an
+/// `io::ErrorKind::InvalidData` error, after which receiving can go on.
+pub struct SvSubscriber {
+    socket: AsyncRawSocket,
+}
+
+impl SvSubscriber {
+    pub fn new(socket: AsyncRawSocket) -> Self {
+        SvSubscriber { socket }
+    }
+
+    pub async fn recv(&self) -> io::Result<Packet> {
+        let bytes = self.socket.recv().await?;
+        Packet::try_from_bytes(&bytes)
+    }
+
+    pub fn into_inner(self) -> AsyncRawSocket {
+        self.socket
+    }
+}
+
+impl Stream for SvSubscriber {
+    type Item = io::Result<Packet>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.socket
+            .poll_recv(cx)
+            .map(|result| Some(result.and_then(|bytes| Packet::try_from_bytes(&bytes))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::future::poll_fn;
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use crate::network::async_socket::loopback;
+    use crate::network::publisher::config::PublisherConfig;
+
+    #[tokio::test]
+    async fn malformed_frame_is_an_error() {
+        let (Some(sender), Some(socket)) = (loopback(), loopback()) else {
+            return;
+        };
+        let mut subscriber = SvSubscriber::new(socket);
+        let frame = PublisherConfig::builder()
+            .app_id(0x4e03)
+            .build()
+            .unwrap()
+            .packet()
+            .to_bytes();
+        // The SV header of the frame with its PDU cut off.
+        sender.send(&frame[..22]).await.unwrap();
+        sender.send(&frame).await.unwrap();
+
+        let malformed = timeout(Duration::from_secs(2), async {
+            let mut malformed = 0;
+            loop {
+                let next = poll_fn(|cx| Pin::new(&mut subscriber).poll_next(cx)).await;
+                match next.unwrap() {
+                    Ok(packet) if packet.sampled_value.app_id == 0x4e03 => return malformed,
+                    Ok(_) => {}
+                    Err(err) => {
+                        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                        malformed += 1;
+                    }
+                }
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(malformed, 1);
+
+        sender.send(&frame).await.unwrap();
+        let packet = timeout(Duration::from_secs(2), subscriber.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.to_bytes(), frame);
+    }
+}
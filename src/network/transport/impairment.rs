@@ -0,0 +1,678 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::network::eth_types::EthTypes;
+use crate::network::transport::{Frame, FrameTransport};
+use crate::protocols::sampled_values::model::{ASDUTags, PDUTags};
+use crate::random::SplitMix64;
+
+/// When an [`ImpairmentRule`] fires, counted in frames offered to the
+/// [`ImpairedTransport`] from 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    /// On each frame with this probability.
+    Probability(f64),
+    /// On frames `offset`, `offset + period`, `offset + 2 * period`...
+    Every { period: u64, offset: u64 },
+    /// Once, on this frame.
+    At(u64),
+}
+
+impl Trigger {
+    fn fires(&self, frame: u64, rng: &mut SplitMix64) -> bool {
+        match *self {
+            Trigger::Probability(probability) => rng.chance(probability),
+            Trigger::Every { period, offset } => {
+                frame >= offset && (frame - offset).is_multiple_of(period.max(1))
+            }
+            Trigger::At(at) => frame == at,
+        }
+    }
+}
+
+/// What happens to a frame when its rule fires.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Impairment {
+    /// Drops this frame and the `count - 1` next ones.
+    Drop { count: u32 },
+    /// Sends the frame twice.
+    Duplicate,
+    /// Sends the frame after the next one.
+    Swap,
+    /// Sends the frame `delay` plus a uniform `[0, jitter)` later. Without
+    /// launch times the frame is held and released by a later send, so the
+    /// delay is rounded up to the frame period.
+    Delay { delay: Duration, jitter: Duration },
+    /// Flips `count` random bits after the Ethernet addresses.
+    FlipBits { count: u32 },
+    /// Advances smpCnt by one from this frame on, as if a sample was lost.
+    SkipSmpCnt,
+    /// Holds smpCnt back by one from this frame on, so it repeats a count.
+    RepeatSmpCnt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ImpairmentRule {
+    pub trigger: Trigger,
+    pub impairment: Impairment,
+}
+
+/// Impairments to inject, reproducible from `seed`: the same seed and rules
+/// impair the same frames in the same way.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpairmentConfig {
+    pub seed: u64,
+    /// smpCnt wraps at this many samples, for skipped and repeated counts.
+    pub sample_rate: u32,
+    /// Rules are checked in order on each frame; several may fire at once.
+    pub rules: Vec<ImpairmentRule>,
+}
+
+impl ImpairmentConfig {
+    pub fn new(seed: u64, sample_rate: u32) -> Self {
+        ImpairmentConfig {
+            seed,
+            sample_rate,
+            rules: vec![],
+        }
+    }
+
+    pub fn with(mut self, trigger: Trigger, impairment: Impairment) -> Self {
+        self.rules.push(ImpairmentRule {
+            trigger,
+            impairment,
+        });
+        self
+    }
+}
+
+/// Impairment actually applied to a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Injected {
+    Dropped { count: u32 },
+    Duplicated,
+    Swapped,
+    Delayed(Duration),
+    FlippedBits(Vec<usize>),
+    SkippedSmpCnt,
+    RepeatedSmpCnt,
+}
+
+/// Entry of the [`ImpairmentLog`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImpairmentEvent {
+    /// Index of the impaired frame among the frames offered.
+    pub frame: u64,
+    /// smpCnt of the first ASDU of the frame, as generated.
+    pub smp_count: Option<u16>,
+    pub time: SystemTime,
+    pub injected: Injected,
+}
+
+impl fmt::Display for ImpairmentEvent {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "frame {}", self.frame)?;
+        if let Some(smp_count) = self.smp_count {
+            write!(f, " smpCnt {}", smp_count)?;
+        }
+        match &self.injected {
+            Injected::Dropped { count } => write!(f, ": dropped {} frame(s)", count),
+            Injected::Duplicated => write!(f, ": duplicated"),
+            Injected::Swapped => write!(f, ": swapped with the next frame"),
+            Injected::Delayed(delay) => write!(f, ": delayed {} µs", delay.as_micros()),
+            Injected::FlippedBits(bits) => write!(f, ": flipped bits {:?}", bits),
+            Injected::SkippedSmpCnt => write!(f, ": skipped a smpCnt"),
+            Injected::RepeatedSmpCnt => write!(f, ": repeated a smpCnt"),
+        }
+    }
+}
+
+/// Every impairment injected by an [`ImpairedTransport`]. Clones share the
+/// same log, so it can be read while the publisher owns the transport.
+#[derive(Clone, Debug, Default)]
+pub struct ImpairmentLog {
+    events: Arc<Mutex<Vec<ImpairmentEvent>>>,
+}
+
+impl ImpairmentLog {
+    pub fn events(&self) -> Vec<ImpairmentEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// Removes and returns the events logged so far.
+    pub fn take(&self) -> Vec<ImpairmentEvent> {
+        std::mem::take(&mut *self.events.lock().unwrap())
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn push(&self, event: ImpairmentEvent) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+/// smpCnt of the first ASDU of an SV frame.
+fn smp_count(data: &[u8]) -> Option<u16> {
+    let offset = *smp_count_offsets(data)?.first()?;
+    Some(u16::from_be_bytes([data[offset], data[offset + 1]]))
+}
+
+/// Offsets of the smpCnt value of every ASDU of an SV frame, found by walking
+/// the BER headers without decoding the frame. `None` for other frames and
+/// for malformed ones.
+fn smp_count_offsets(data: &[u8]) -> Option<Vec<usize>> {
+    let apdu = match data.get(12..14)? {
+        [0x81, 0x00] => 18,
+        _ => 14,
+    };
+    if data.get(apdu - 2..apdu)? != (EthTypes::SampledValues as u16).to_be_bytes() {
+        return None;
+    }
+    let (tag, mut at, pdu_end) = triplet(data, apdu + 8)?;
+    if tag != PDUTags::PDU as u8 {
+        return None;
+    }
+    // noASDU and the optional security come before seqASDU.
+    let (mut at, seq_end) = loop {
+        let (tag, start, end) = triplet(data, at)?;
+        if end > pdu_end {
+            return None;
+        }
+        if tag == PDUTags::SeqAsdu as u8 {
+            break (start, end);
+        }
+        at = end;
+    };
+    let mut offsets = vec![];
+    while at < seq_end {
+        let (tag, mut field, asdu_end) = triplet(data, at)?;
+        if tag != PDUTags::ASDU as u8 || asdu_end > seq_end {
+            return None;
+        }
+        // svID and the optional datSet come before smpCnt.
+        loop {
+            let (tag, start, end) = triplet(data, field)?;
+            if end > asdu_end {
+                return None;
+            }
+            if tag == ASDUTags::SmpCount as u8 {
+                if end - start != 2 {
+                    return None;
+                }
+                offsets.push(start);
+                break;
+            }
+            field = end;
+        }
+        at = asdu_end;
+    }
+    Some(offsets)
+}
+
+/// Tag, value start and value end of the BER triplet at `at` of `data`, or
+/// `None` when it runs past the end.
+fn triplet(data: &[u8], at: usize) -> Option<(u8, usize, usize)> {
+    let tag = *data.get(at)?;
+    let first = *data.get(at + 1)?;
+    let (length, start) = if first < 0x80 {
+        (first as usize, at + 2)
+    } else {
+        let size = (first & 0x7f) as usize;
+        if !(1..=4).contains(&size) {
+            return None;
+        }
+        let bytes = data.get(at + 2..at + 2 + size)?;
+        let length = bytes
+            .iter()
+            .fold(0, |length, byte| length << 8 | *byte as usize);
+        (length, at + 2 + size)
+    };
+    let end = start.checked_add(length)?;
+    (end <= data.len()).then_some((tag, start, end))
+}
+
+/// Wraps a transport and impairs the frames sent through it, for testing how
+/// subscribers cope with lost, duplicated, reordered, delayed, corrupted or
+/// miscounted frames. Received frames pass through untouched.
+///
+/// Frames still held back for a delay or a swap are sent by
+/// [`FrameTransport::flush`], which publishers call when they stop.
+pub struct ImpairedTransport<T: FrameTransport> {
+    inner: T,
+    config: ImpairmentConfig,
+    log: ImpairmentLog,
+    frame: u64,
+    dropping: u32,
+    smp_count_offset: i64,
+    swapped: Option<Frame>,
+    delayed: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl<T: FrameTransport> ImpairedTransport<T> {
+    pub fn new(inner: T, config: ImpairmentConfig) -> Self {
+        ImpairedTransport {
+            inner,
+            config,
+            log: ImpairmentLog::default(),
+            frame: 0,
+            dropping: 0,
+            smp_count_offset: 0,
+            swapped: None,
+            delayed: VecDeque::new(),
+        }
+    }
+
+    pub fn config(&self) -> &ImpairmentConfig {
+        &self.config
+    }
+
+    pub fn log(&self) -> ImpairmentLog {
+        self.log.clone()
+    }
+
+    /// Frames offered so far, impaired or not.
+    pub fn frames(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn release_delayed(&mut self) -> io::Result<()> {
+        let now = Instant::now();
+        while let Some(position) = self.delayed.iter().position(|(due, _)| *due <= now) {
+            let (_, data) = self.delayed.remove(position).unwrap();
+            self.inner.send_frame(&data, None)?;
+        }
+        Ok(())
+    }
+
+    fn record(&self, smp_count: Option<u16>, injected: Injected) {
+        self.log.push(ImpairmentEvent {
+            frame: self.frame,
+            smp_count,
+            time: SystemTime::now(),
+            injected,
+        });
+    }
+
+    /// Shifts the smpCnt of every ASDU of the frame by the current offset,
+    /// in place. Frames that are not well-formed SV pass unchanged.
+    fn shift_smp_count(&self, data: &mut [u8]) {
+        if self.smp_count_offset == 0 {
+            return;
+        }
+        let Some(offsets) = smp_count_offsets(data) else {
+            return;
+        };
+        let sample_rate = self.config.sample_rate.max(1) as i64;
+        for offset in offsets {
+            let smp_count = u16::from_be_bytes([data[offset], data[offset + 1]]);
+            let shifted = (smp_count as i64 + self.smp_count_offset).rem_euclid(sample_rate);
+            data[offset..offset + 2].copy_from_slice(&(shifted as u16).to_be_bytes());
+        }
+    }
+
+    fn impair(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        if self.dropping > 0 {
+            self.dropping -= 1;
+            return Ok(());
+        }
+        let mut rng = SplitMix64::at(self.config.seed, self.frame);
+        let fired: Vec<Impairment> = self
+            .config
+            .rules
+            .iter()
+            .filter(|rule| rule.trigger.fires(self.frame, &mut rng))
+            .map(|rule| rule.impairment)
+            .collect();
+        if fired.is_empty() && self.smp_count_offset == 0 && self.swapped.is_none() {
+            return self.inner.send_frame(data, timestamp);
+        }
+        let smp_count = smp_count(data);
+
+        if let Some(count) = fired.iter().find_map(|impairment| match impairment {
+            Impairment::Drop { count } => Some(*count),
+            _ => None,
+        }) {
+            self.record(smp_count, Injected::Dropped { count });
+            self.dropping = count.saturating_sub(1);
+            return Ok(());
+        }
+
+        for impairment in &fired {
+            match impairment {
+                Impairment::SkipSmpCnt => {
+                    self.smp_count_offset += 1;
+                    self.record(smp_count, Injected::SkippedSmpCnt);
+                }
+                Impairment::RepeatSmpCnt => {
+                    self.smp_count_offset -= 1;
+                    self.record(smp_count, Injected::RepeatedSmpCnt);
+                }
+                _ => {}
+            }
+        }
+        let mut data = data.to_vec();
+        self.shift_smp_count(&mut data);
+        let mut timestamp = timestamp;
+        let mut delay = None;
+        let mut copies = 1;
+        let mut swap = false;
+
+        for impairment in &fired {
+            match *impairment {
+                Impairment::FlipBits { count } if data.len() > 12 => {
+                    let bits = (0..count)
+                        .map(|_| 96 + (rng.next_u64() % ((data.len() as u64 - 12) * 8)) as usize)
+                        .collect::<Vec<_>>();
+                    for bit in &bits {
+                        data[bit / 8] ^= 0x80 >> (bit % 8);
+                    }
+                    self.record(smp_count, Injected::FlippedBits(bits));
+                }
+                Impairment::Duplicate => {
+                    copies = 2;
+                    self.record(smp_count, Injected::Duplicated);
+                }
+                Impairment::Swap if self.swapped.is_none() => swap = true,
+                Impairment::Delay {
+                    delay: base,
+                    jitter,
+                } => {
+                    delay = Some(base + jitter.mul_f64(rng.next_f64()));
+                }
+                _ => {}
+            }
+        }
+
+        // Only what is actually done to the frame gets logged: a frame held
+        // for its delay is not also swapped.
+        if let Some(delay) = delay {
+            self.record(smp_count, Injected::Delayed(delay));
+            match timestamp {
+                Some(launch) => timestamp = Some(launch + delay),
+                None => {
+                    for _ in 0..copies {
+                        self.delayed
+                            .push_back((Instant::now() + delay, data.clone()));
+                    }
+                    return Ok(());
+                }
+            }
+        }
+        if swap {
+            self.record(smp_count, Injected::Swapped);
+            self.swapped = Some(Frame { data, timestamp });
+            return Ok(());
+        }
+        match self.swapped.take() {
+            Some(held) => {
+                // With launch times the frames also trade them, or the qdisc
+                // would put them back in order.
+                let (first, second) = match (timestamp, held.timestamp) {
+                    (Some(current), Some(earlier)) => (Some(earlier), Some(current)),
+                    _ => (timestamp, held.timestamp),
+                };
+                for _ in 0..copies {
+                    self.inner.send_frame(&data, first)?;
+                }
+                self.inner.send_frame(&held.data, second)
+            }
+            None => {
+                for _ in 0..copies {
+                    self.inner.send_frame(&data, timestamp)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<T: FrameTransport> FrameTransport for ImpairedTransport<T> {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        self.release_delayed()?;
+        let held = self.swapped.is_some();
+        let result = self.impair(data, timestamp);
+        self.frame += 1;
+        result?;
+        // The frame after a swapped one was dropped or delayed.
+        if held && let Some(frame) = self.swapped.take() {
+            self.inner.send_frame(&frame.data, frame.timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Sends the frames held back for a delay or a swap now, then flushes
+    /// the inner transport.
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some(frame) = self.swapped.take() {
+            self.inner.send_frame(&frame.data, frame.timestamp)?;
+        }
+        while let Some((_, data)) = self.delayed.pop_front() {
+            self.inner.send_frame(&data, None)?;
+        }
+        self.inner.flush()
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        self.inner.recv_frame()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.inner.set_read_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::packet::Packet;
+    use crate::network::publisher::config::PublisherConfig;
+    use crate::network::transport::memory::VirtualSwitch;
+
+    /// SV frames with smpCnt 0 to `count - 1`.
+    fn frames(count: u16) -> Vec<Vec<u8>> {
+        let mut packet = PublisherConfig::builder().build().unwrap().packet();
+        (0..count)
+            .map(|smp_count| {
+                packet.sampled_value.asdu[0].smp_count = smp_count;
+                packet.to_bytes()
+            })
+            .collect()
+    }
+
+    fn run(config: ImpairmentConfig, count: u16) -> (Vec<Vec<u8>>, ImpairmentLog) {
+        let switch = VirtualSwitch::new();
+        let mut transport = ImpairedTransport::new(switch.port(), config);
+        let mut receiver = switch.port();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(1)))
+            .unwrap();
+        for frame in frames(count) {
+            transport.send_frame(&frame, None).unwrap();
+        }
+        transport.flush().unwrap();
+        let mut received = vec![];
+        while let Ok(frame) = receiver.recv_frame() {
+            received.push(frame.data);
+        }
+        (received, transport.log())
+    }
+
+    fn counts(counts: &[u16]) -> Vec<Option<u16>> {
+        counts.iter().copied().map(Some).collect()
+    }
+
+    #[test]
+    fn patterns_drop_duplicate_swap_and_miscount() {
+        let config = ImpairmentConfig::new(1, 4800)
+            .with(Trigger::At(1), Impairment::Drop { count: 2 })
+            .with(Trigger::At(4), Impairment::Duplicate)
+            .with(Trigger::At(5), Impairment::Swap)
+            .with(Trigger::At(7), Impairment::SkipSmpCnt)
+            .with(Trigger::At(9), Impairment::RepeatSmpCnt);
+        let (received, log) = run(config, 11);
+        let received = received
+            .iter()
+            .map(|data| smp_count(data))
+            .collect::<Vec<_>>();
+        assert_eq!(received, counts(&[0, 3, 4, 4, 6, 5, 8, 9, 9, 10]));
+        let events = log.events();
+        assert_eq!(events.len(), 5);
+        assert_eq!(events[0].injected, Injected::Dropped { count: 2 });
+        assert_eq!(events[0].smp_count, Some(1));
+        assert_eq!(events[3].frame, 7);
+        assert_eq!(events[3].to_string(), "frame 7 smpCnt 7: skipped a smpCnt");
+    }
+
+    #[test]
+    fn seeded_probabilities_are_reproducible() {
+        let config = ImpairmentConfig::new(7, 4800)
+            .with(Trigger::Probability(0.2), Impairment::Drop { count: 1 })
+            .with(
+                Trigger::Every {
+                    period: 10,
+                    offset: 3,
+                },
+                Impairment::FlipBits { count: 3 },
+            );
+        let (first, log) = run(config.clone(), 100);
+        let (second, again) = run(config, 100);
+        assert_eq!(first, second);
+        let injected = |log: &ImpairmentLog| {
+            log.events()
+                .into_iter()
+                .map(|event| (event.frame, event.injected))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(injected(&log), injected(&again));
+
+        let dropped = log
+            .events()
+            .iter()
+            .filter(|event| matches!(event.injected, Injected::Dropped { .. }))
+            .count();
+        assert!((10..=30).contains(&dropped));
+        assert_eq!(first.len(), 100 - dropped);
+        for event in log.events() {
+            if let Injected::FlippedBits(bits) = &event.injected {
+                assert_eq!(bits.len(), 3);
+                assert!(bits.iter().all(|bit| *bit >= 96));
+            }
+        }
+    }
+
+    #[test]
+    fn delay_moves_launch_time_or_holds_frame() {
+        let switch = VirtualSwitch::new();
+        let config = ImpairmentConfig::new(0, 4800).with(
+            Trigger::At(0),
+            Impairment::Delay {
+                delay: Duration::from_millis(1),
+                jitter: Duration::ZERO,
+            },
+        );
+        let mut transport = ImpairedTransport::new(switch.port(), config.clone());
+        let mut receiver = switch.port();
+        let launch = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+        let frame = &frames(1)[0];
+        transport.send_frame(frame, Some(launch)).unwrap();
+        assert_eq!(
+            receiver.recv_frame().unwrap().timestamp,
+            Some(launch + Duration::from_millis(1))
+        );
+
+        let mut transport = ImpairedTransport::new(switch.port(), config);
+        transport.send_frame(frame, None).unwrap();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(1)))
+            .unwrap();
+        assert!(receiver.recv_frame().is_err());
+        std::thread::sleep(Duration::from_millis(2));
+        transport.send_frame(frame, None).unwrap();
+        assert_eq!(receiver.recv_frame().unwrap().data, *frame);
+        assert_eq!(transport.log().len(), 1);
+        assert_eq!(transport.frames(), 2);
+    }
+
+    #[test]
+    fn miscounts_patch_frames_in_place_and_skip_malformed_ones() {
+        let config = ImpairmentConfig::new(0, 4800).with(Trigger::At(0), Impairment::RepeatSmpCnt);
+        let switch = VirtualSwitch::new();
+        let mut transport = ImpairedTransport::new(switch.port(), config);
+        let mut receiver = switch.port();
+        let frames = frames(2);
+        transport.send_frame(&frames[0], None).unwrap();
+        let truncated = &frames[1][..frames[1].len() - 10];
+        transport.send_frame(truncated, None).unwrap();
+        transport.send_frame(&frames[1], None).unwrap();
+
+        let mut expected = frames[0].clone();
+        let offset = smp_count_offsets(&expected).unwrap()[0];
+        expected[offset..offset + 2].copy_from_slice(&4799u16.to_be_bytes());
+        assert_eq!(receiver.recv_frame().unwrap().data, expected);
+        assert_eq!(
+            Packet::from_bytes(&expected).sampled_value.asdu[0].smp_count,
+            4799
+        );
+        assert_eq!(receiver.recv_frame().unwrap().data, truncated);
+        assert_eq!(smp_count(&receiver.recv_frame().unwrap().data), Some(0));
+    }
+
+    #[test]
+    fn held_delay_is_not_logged_as_a_swap() {
+        let config = ImpairmentConfig::new(0, 4800)
+            .with(Trigger::At(1), Impairment::Swap)
+            .with(
+                Trigger::At(1),
+                Impairment::Delay {
+                    delay: Duration::from_secs(60),
+                    jitter: Duration::ZERO,
+                },
+            );
+        let (received, log) = run(config, 3);
+        let received = received
+            .iter()
+            .map(|data| smp_count(data))
+            .collect::<Vec<_>>();
+        assert_eq!(received, counts(&[0, 2, 1]));
+        let injected = log
+            .events()
+            .into_iter()
+            .map(|event| event.injected)
+            .collect::<Vec<_>>();
+        assert_eq!(injected, vec![Injected::Delayed(Duration::from_secs(60))]);
+    }
+
+    #[test]
+    fn stopped_publisher_flushes_held_frames() {
+        use crate::network::publisher::sampled_value_pub::SvPublisher;
+
+        let switch = VirtualSwitch::new();
+        let mut receiver = switch.port();
+        let config = ImpairmentConfig::new(0, 4800).with(Trigger::At(2), Impairment::Swap);
+        let transport = ImpairedTransport::new(switch.port(), config);
+        let publisher_config = PublisherConfig::builder().build().unwrap();
+        let mut publisher = SvPublisher::with_transport(publisher_config, transport).unwrap();
+        assert_eq!(publisher.run(Some(3)).unwrap(), 3);
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(1)))
+            .unwrap();
+        let mut received = vec![];
+        while let Ok(frame) = receiver.recv_frame() {
+            received.push(smp_count(&frame.data));
+        }
+        assert_eq!(received, counts(&[0, 1, 2]));
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender, channel};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::network::transport::{Frame, FrameTransport};
+
+struct PortEntry {
+    id: usize,
+    sender: Sender<Frame>,
+}
+
+#[derive(Default)]
+struct SwitchState {
+    next_id: usize,
+    ports: Vec<PortEntry>,
+}
+
+/// In-memory Ethernet hub: every frame sent on one port is delivered to all
+/// the other ports. Lets publishers and subscribers run without privileges.
+#[derive(Clone, Default)]
+pub struct VirtualSwitch {
+    state: Arc<Mutex<SwitchState>>,
+}
+
+impl VirtualSwitch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn port(&self) -> MemoryPort {
+        let (sender, receiver) = channel();
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.ports.push(PortEntry { id, sender });
+        MemoryPort {
+            id,
+            state: Arc::clone(&self.state),
+            receiver,
+            read_timeout: None,
+        }
+    }
+
+    pub fn port_count(&self) -> usize {
+        self.state.lock().unwrap().ports.len()
+    }
+}
+
+pub struct MemoryPort {
+    id: usize,
+    state: Arc<Mutex<SwitchState>>,
+    receiver: Receiver<Frame>,
+    read_timeout: Option<Duration>,
+}
+
+impl FrameTransport for MemoryPort {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        let frame = Frame {
+            data: data.to_vec(),
+            timestamp: Some(timestamp.unwrap_or_else(SystemTime::now)),
+        };
+        let state = self.state.lock().unwrap();
+        for port in state.ports.iter().filter(|port| port.id != self.id) {
+            // A port whose receiver is gone is removed by its own drop.
+            let _ = port.sender.send(frame.clone());
+        }
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        match self.read_timeout {
+            None => self
+                .receiver
+                .recv()
+                .map_err(|err| io::Error::new(io::ErrorKind::BrokenPipe, err)),
+            Some(timeout) => match self.receiver.recv_timeout(timeout) {
+                Ok(frame) => Ok(frame),
+                Err(RecvTimeoutError::Timeout) => Err(io::ErrorKind::WouldBlock.into()),
+                Err(err) => Err(io::Error::new(io::ErrorKind::BrokenPipe, err)),
+            },
+        }
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
+}
+
+impl Drop for MemoryPort {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.ports.retain(|port| port.id != self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_reach_every_other_port() {
+        let switch = VirtualSwitch::new();
+        let mut a = switch.port();
+        let mut b = switch.port();
+        let mut c = switch.port();
+
+        a.send_frame(&[1, 2, 3], None).unwrap();
+
+        assert_eq!(b.recv_frame().unwrap().data, vec![1, 2, 3]);
+        assert_eq!(c.recv_frame().unwrap().data, vec![1, 2, 3]);
+        a.set_read_timeout(Some(Duration::from_millis(1))).unwrap();
+        assert_eq!(
+            a.recv_frame().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+
+    #[test]
+    fn timestamp_is_kept() {
+        let switch = VirtualSwitch::new();
+        let mut a = switch.port();
+        let mut b = switch.port();
+        let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs(10);
+
+        a.send_frame(&[0], Some(timestamp)).unwrap();
+
+        assert_eq!(b.recv_frame().unwrap().timestamp, Some(timestamp));
+    }
+
+    #[test]
+    fn dropped_port_is_removed() {
+        let switch = VirtualSwitch::new();
+        let a = switch.port();
+        let b = switch.port();
+        assert_eq!(switch.port_count(), 2);
+        drop(a);
+        assert_eq!(switch.port_count(), 1);
+        drop(b);
+        assert_eq!(switch.port_count(), 0);
+    }
+}
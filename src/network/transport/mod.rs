@@ -0,0 +1,117 @@
+pub mod impairment;
+pub mod memory;
+
+use std::io;
+use std::time::{Duration, SystemTime};
+
+use crate::network::clock::Clock;
+
+/// Raw Ethernet frame together with the time it was captured, when known.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub data: Vec<u8>,
+    pub timestamp: Option<SystemTime>,
+}
+
+/// Anything able to carry raw Ethernet frames: a raw socket, a capture file
+/// or an in-memory switch port.
+///
+/// `recv_frame` blocks until a frame is available or the read timeout
+/// expires, in which case it fails with `io::ErrorKind::WouldBlock`.
+/// Sources that run out of frames fail with `io::ErrorKind::UnexpectedEof`.
+pub trait FrameTransport {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()>;
+
+    /// Sends `frames` in order, all with the same `timestamp`. Transports
+    /// that can hand several frames to the kernel at once override this.
+    fn send_batch(&mut self, frames: &[&[u8]], timestamp: Option<SystemTime>) -> io::Result<()> {
+        for data in frames {
+            self.send_frame(data, timestamp)?;
+        }
+        Ok(())
+    }
+
+    /// Sends `frames` to leave at `launch_time`, in nanoseconds of `clock`.
+    /// Transports with launch times of their own take them as they are; the
+    /// others get them as a `SystemTime` through `send_batch`.
+    fn send_batch_at(
+        &mut self,
+        frames: &[&[u8]],
+        clock: Clock,
+        launch_time: u64,
+    ) -> io::Result<()> {
+        self.send_batch(frames, Some(clock.to_system_time(launch_time)))
+    }
+
+    /// Sends the frames the transport still holds back. Publishers call it
+    /// when they stop.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame>;
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+
+impl<T: FrameTransport + ?Sized> FrameTransport for &mut T {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        (**self).send_frame(data, timestamp)
+    }
+
+    fn send_batch(&mut self, frames: &[&[u8]], timestamp: Option<SystemTime>) -> io::Result<()> {
+        (**self).send_batch(frames, timestamp)
+    }
+
+    fn send_batch_at(
+        &mut self,
+        frames: &[&[u8]],
+        clock: Clock,
+        launch_time: u64,
+    ) -> io::Result<()> {
+        (**self).send_batch_at(frames, clock, launch_time)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        (**self).recv_frame()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+}
+
+impl<T: FrameTransport + ?Sized> FrameTransport for Box<T> {
+    fn send_frame(&mut self, data: &[u8], timestamp: Option<SystemTime>) -> io::Result<()> {
+        (**self).send_frame(data, timestamp)
+    }
+
+    fn send_batch(&mut self, frames: &[&[u8]], timestamp: Option<SystemTime>) -> io::Result<()> {
+        (**self).send_batch(frames, timestamp)
+    }
+
+    fn send_batch_at(
+        &mut self,
+        frames: &[&[u8]],
+        clock: Clock,
+        launch_time: u64,
+    ) -> io::Result<()> {
+        (**self).send_batch_at(frames, clock, launch_time)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (**self).flush()
+    }
+
+    fn recv_frame(&mut self) -> io::Result<Frame> {
+        (**self).recv_frame()
+    }
+
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        (**self).set_read_timeout(timeout)
+    }
+}
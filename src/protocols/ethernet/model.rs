@@ -1,24 +1,130 @@
+use std::io;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::network::eth_types::EthTypes;
+
+/// IEEE 802.1Q tag. IEC 61850-9-2 recommends priority 4 for Sampled Values.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct Vlan {
+    pub priority: u8,
+    pub dei: bool,
+    pub id: u16,
+}
+
 #[derive(PartialEq, Debug)]
 pub struct Ethernet {
     pub dst_mac: [u8; 6],
     pub src_mac: [u8; 6],
+    pub vlan: Option<Vlan>,
     pub ether_type: [u8; 2],
 }
 
+impl Vlan {
+    pub fn from_bytes(bytes: &[u8]) -> Vlan {
+        let tci = BigEndian::read_u16(&bytes[0..2]);
+        Vlan {
+            priority: (tci >> 13) as u8,
+            dei: (tci >> 12) & 1 == 1,
+            id: tci & 0x0fff,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = vec![0; 4];
+        BigEndian::write_u16(&mut bytes[0..2], EthTypes::Vlan as u16);
+        let tci =
+            ((self.priority as u16 & 0x7) << 13) | ((self.dei as u16) << 12) | (self.id & 0x0fff);
+        BigEndian::write_u16(&mut bytes[2..4], tci);
+        bytes
+    }
+}
+
 impl Ethernet {
     pub fn from_bytes(bytes: &[u8]) -> Ethernet {
-        Ethernet {
+        Self::try_from_bytes(bytes).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Decodes the header, failing when `bytes` is too short to hold it.
+    pub fn try_from_bytes(bytes: &[u8]) -> io::Result<Ethernet> {
+        let tagged =
+            bytes.len() >= 14 && BigEndian::read_u16(&bytes[12..14]) == EthTypes::Vlan as u16;
+        let header_len = if tagged { 18 } else { 14 };
+        if bytes.len() < header_len {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Ethernet header needs {} bytes, frame has {}",
+                    header_len,
+                    bytes.len()
+                ),
+            ));
+        }
+        let mut vlan: Option<Vlan> = None;
+        let mut ether_type = [bytes[12], bytes[13]];
+        if BigEndian::read_u16(&ether_type) == EthTypes::Vlan as u16 {
+            vlan = Some(Vlan::from_bytes(&bytes[14..16]));
+            ether_type = [bytes[16], bytes[17]];
+        }
+        Ok(Ethernet {
             dst_mac: [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]],
             src_mac: [bytes[6], bytes[7], bytes[8], bytes[9], bytes[10], bytes[11]],
-            ether_type: [bytes[12], bytes[13]],
-        }
+            vlan,
+            ether_type,
+        })
+    }
+
+    /// Length of the header in bytes, including the 802.1Q tag when present.
+    pub fn header_len(&self) -> usize {
+        if self.vlan.is_some() { 18 } else { 14 }
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes: Vec<u8> = vec![];
         bytes.append(&mut self.dst_mac.to_vec());
         bytes.append(&mut self.src_mac.to_vec());
+        if let Some(vlan) = &self.vlan {
+            bytes.append(&mut vlan.to_bytes());
+        }
         bytes.append(&mut self.ether_type.to_vec());
         bytes
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_untagged() {
+        let bytes: &[u8] = &[
+            0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x88, 0xba,
+        ];
+        let ethernet = Ethernet::from_bytes(bytes);
+        assert_eq!(ethernet.vlan, None);
+        assert_eq!(ethernet.ether_type, [0x88, 0xba]);
+        assert_eq!(ethernet.header_len(), 14);
+        assert_eq!(ethernet.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn decode_vlan_tagged() {
+        let bytes: &[u8] = &[
+            0x01, 0x0c, 0xcd, 0x04, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x81, 0x00,
+            0x80, 0x05, 0x88, 0xba,
+        ];
+        let ethernet = Ethernet::from_bytes(bytes);
+        assert_eq!(
+            ethernet.vlan,
+            Some(Vlan {
+                priority: 4,
+                dei: false,
+                id: 5
+            })
+        );
+        assert_eq!(ethernet.ether_type, [0x88, 0xba]);
+        assert_eq!(ethernet.header_len(), 18);
+        assert_eq!(ethernet.to_bytes(), bytes);
+        assert!(Ethernet::try_from_bytes(&bytes[..16]).is_err());
+    }
+}
@@ -1,66 +1,88 @@
 use crate::{
-    protocols::sampled_values::model::{ASDUTags, Asdu, Phases, SampleSync},
+    protocols::sampled_values::{
+        model::{ASDUTags, Asdu, Phases, SampleSync},
+        waveform::{Channel, SineGenerator, WaveformSource},
+    },
     standards::asn1::Triplet,
 };
 use byteorder::{BigEndian, ByteOrder};
+use std::io;
 
 impl Asdu {
     pub fn from_bytes(bytes: &[u8]) -> Asdu {
-        let mut triplet = Triplet::from_bytes(bytes);
-        let mut start: usize = triplet.length();
-        let sv_id = String::from_utf8(triplet.value.to_vec()).unwrap();
-
-        triplet = Triplet::from_bytes(&bytes[start..]);
-        start += triplet.length();
-        let mut dataset: Option<String> = None;
-        if triplet.tag == 0x81 {
-            dataset = Some(String::from_utf8(triplet.value.to_vec()).unwrap());
+        Self::try_from_bytes(bytes).unwrap_or_else(|err| panic!("{}", err))
+    }
 
-            triplet = Triplet::from_bytes(&bytes[start..]);
-            start += triplet.length();
-        }
-        let smp_count: u16 = BigEndian::read_u16(&triplet.value);
+    /// Decodes an ASDU, failing on missing, misplaced or malformed fields.
+    pub fn try_from_bytes(bytes: &[u8]) -> io::Result<Asdu> {
+        let mut start: usize = 0;
+        let next = |start: &mut usize| -> io::Result<Triplet> {
+            let triplet = Triplet::try_from_bytes(&bytes[*start..])?;
+            *start += triplet.length();
+            Ok(triplet)
+        };
 
-        triplet = Triplet::from_bytes(&bytes[start..]);
-        start += triplet.length();
-        let conf_rev: u32 = BigEndian::read_u32(&triplet.value);
+        let mut triplet = next(&mut start)?;
+        let sv_id = string(expect(&triplet, ASDUTags::SvId, "svID")?)?;
 
-        triplet = Triplet::from_bytes(&bytes[start..]);
-        start += triplet.length();
+        triplet = next(&mut start)?;
+        let mut dataset: Option<String> = None;
+        if triplet.tag == ASDUTags::Dataset as u8 {
+            dataset = Some(string(&triplet.value)?);
+            triplet = next(&mut start)?;
+        }
+        let smp_count: u16 = BigEndian::read_u16(sized(
+            expect(&triplet, ASDUTags::SmpCount, "smpCnt")?,
+            2,
+            "smpCnt",
+        )?);
+
+        triplet = next(&mut start)?;
+        let conf_rev: u32 = BigEndian::read_u32(sized(
+            expect(&triplet, ASDUTags::ConfRev, "confRev")?,
+            4,
+            "confRev",
+        )?);
+
+        triplet = next(&mut start)?;
         let mut refr_tm: Option<u64> = None;
-        if triplet.tag == 0x84 {
-            refr_tm = Some(BigEndian::read_u64(&triplet.value));
-
-            triplet = Triplet::from_bytes(&bytes[start..]);
-            start += triplet.length();
+        if triplet.tag == ASDUTags::RefrTm as u8 {
+            refr_tm = Some(BigEndian::read_u64(sized(&triplet.value, 8, "refrTm")?));
+            triplet = next(&mut start)?;
         }
-        let smp_sync: SampleSync = match triplet.value[0] {
+        let smp_sync: SampleSync = match sized(
+            expect(&triplet, ASDUTags::SmpSync, "smpSynch")?,
+            1,
+            "smpSynch",
+        )?[0]
+        {
             0 => SampleSync::Internal,
             1 => SampleSync::Local,
             2 => SampleSync::Global,
-            _ => panic!("Invalid SampleSync value: {}", &triplet.value[0]),
+            value => return Err(invalid(format!("Invalid SampleSync value: {}", value))),
         };
 
-        triplet = Triplet::from_bytes(&bytes[start..]);
-        start += triplet.length();
+        triplet = next(&mut start)?;
         let mut smp_rate: Option<u16> = None;
-        if triplet.tag == 0x86 {
-            smp_rate = Some(BigEndian::read_u16(&triplet.value));
-
-            triplet = Triplet::from_bytes(&bytes[start..]);
-            start += triplet.length();
+        if triplet.tag == ASDUTags::SmpRate as u8 {
+            smp_rate = Some(BigEndian::read_u16(sized(&triplet.value, 2, "smpRate")?));
+            triplet = next(&mut start)?;
         }
-        let measures = Phases::from_bytes(&triplet.value);
+        let measures = Phases::from_bytes(sized(
+            expect(&triplet, ASDUTags::Measures, "seqData")?,
+            64,
+            "seqData",
+        )?);
 
         let mut smp_mode: Option<u16> = None;
         if start < bytes.len() {
-            triplet = Triplet::from_bytes(&bytes[start..]);
-            if triplet.tag == 0x88 {
-                smp_mode = Some(BigEndian::read_u16(&triplet.value));
+            triplet = next(&mut start)?;
+            if triplet.tag == ASDUTags::SmpMode as u8 {
+                smp_mode = Some(BigEndian::read_u16(sized(&triplet.value, 2, "smpMod")?));
             }
         }
 
-        Asdu {
+        Ok(Asdu {
             sv_id,
             dataset,
             smp_count,
@@ -70,7 +92,7 @@ impl Asdu {
             smp_rate,
             measures,
             smp_mode,
-        }
+        })
     }
 
     fn sv_id_to_bytes(sv_id: &str) -> Vec<u8> {
@@ -124,7 +146,7 @@ impl Asdu {
     }
 
     fn smp_rate_to_bytes(smp_rate: u16) -> Vec<u8> {
-        let mut bytes: Vec<u8> = vec![ASDUTags::SmpRate as u8, 2];
+        let mut bytes: Vec<u8> = vec![ASDUTags::SmpRate as u8, 2, 0, 0];
         BigEndian::write_u16(&mut bytes[2..], smp_rate);
         bytes
     }
@@ -178,34 +200,59 @@ impl Asdu {
     }
 
     pub fn next(&mut self) {
-        let vol: f64 = 30.0;
-        let amp: f64 = 0.0;
-        let phase_angle: f64 = 0.0;
-
-        self.smp_count = (self.smp_count + 1) % 4800;
-
-        let sample_point: f64 = (self.smp_count % 80) as f64;
-
-        let angle_a: f64 = sample_point * (2_f64 * std::f64::consts::PI / 80_f64);
-        let angle_b: f64 = sample_point * (2_f64 * std::f64::consts::PI / 80_f64);
-        let angle_c: f64 = sample_point * (2_f64 * std::f64::consts::PI / 80_f64);
-
-        self.measures.current.a.value = (amp * (angle_a - phase_angle).sin() * 1000_f64) as i32;
-        self.measures.current.b.value = (amp * (angle_b - phase_angle).sin() * 1000_f64) as i32;
-        self.measures.current.c.value = (amp * (angle_c - phase_angle).sin() * 1000_f64) as i32;
-        self.measures.current.n.value = self.measures.current.a.value
-            + self.measures.current.b.value
-            + self.measures.current.c.value;
-
-        self.measures.voltage.a.value = (vol * angle_a.sin() * 100_f64) as i32;
-        self.measures.voltage.b.value = (vol * angle_b.sin() * 100_f64) as i32;
-        self.measures.voltage.c.value = (vol * angle_c.sin() * 100_f64) as i32;
-        self.measures.voltage.n.value = self.measures.voltage.a.value
-            + self.measures.voltage.b.value
-            + self.measures.voltage.c.value;
+        self.advance(1, 4800, 80);
+    }
+
+    /// Moves `step` samples ahead, wrapping smpCnt at `sample_rate`, and
+    /// recomputes the measurements with the default balanced
+    /// [`SineGenerator`] at `samples_per_cycle`.
+    pub fn advance(&mut self, step: u32, sample_rate: u32, samples_per_cycle: u32) {
+        self.smp_count = ((self.smp_count as u32 + step) % sample_rate) as u16;
+
+        let frequency = sample_rate as f64 / samples_per_cycle as f64;
+        let mut generator = SineGenerator::default();
+        for channel in Channel::ALL {
+            if let Some(sinusoid) = generator.get_mut(channel) {
+                sinusoid.frequency = frequency;
+            }
+        }
+        generator.sample(self.smp_count as u64, sample_rate, &mut self.measures);
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Value of `triplet`, provided it carries the `tag` of `field`.
+fn expect<'a>(triplet: &'a Triplet, tag: ASDUTags, field: &str) -> io::Result<&'a [u8]> {
+    if triplet.tag == tag as u8 {
+        Ok(&triplet.value)
+    } else {
+        Err(invalid(format!(
+            "Expected {} in ASDU, found tag {:#04x}",
+            field, triplet.tag
+        )))
+    }
+}
+
+fn sized<'a>(value: &'a [u8], len: usize, field: &str) -> io::Result<&'a [u8]> {
+    if value.len() == len {
+        Ok(value)
+    } else {
+        Err(invalid(format!(
+            "{} must be {} bytes long, not {}",
+            field,
+            len,
+            value.len()
+        )))
     }
 }
 
+fn string(value: &[u8]) -> io::Result<String> {
+    String::from_utf8(value.to_vec()).map_err(|err| invalid(err.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use crate::protocols::sampled_values::model::{PhaseMeasurement, PhaseMeasures, Phases};
@@ -330,6 +377,32 @@ mod tests {
         assert_eq!(object.to_bytes(), bytes);
     }
 
+    #[test]
+    fn add_smp_rate() {
+        assert_eq!(Asdu::smp_rate_to_bytes(4800), vec![0x86, 0x02, 0x12, 0xc0]);
+    }
+
+    #[test]
+    fn advance_wraps_at_sample_rate() {
+        let bytes: &[u8] = &[
+            0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04, 0x00, 0x00,
+            0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff, 0xff, 0xff,
+            0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff,
+            0xff, 0xff, 0xfc, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00, 0x00, 0x20,
+            0x00,
+        ];
+        let mut asdu = Asdu::from_bytes(bytes);
+        asdu.smp_count = 12_798;
+        asdu.advance(2, 12_800, 256);
+        assert_eq!(asdu.smp_count, 0);
+        assert_eq!(asdu.measures.voltage.a.value, 8980);
+        asdu.advance(64, 12_800, 256);
+        assert_eq!(asdu.smp_count, 64);
+        assert_eq!(asdu.measures.voltage.a.value, 0);
+    }
+
     #[test]
     fn next_asdu() {
         let mut current_asdu = Asdu {
@@ -392,15 +465,15 @@ mod tests {
             measures: Phases {
                 current: PhaseMeasures {
                     a: PhaseMeasurement {
-                        value: 0,
+                        value: 1410,
                         quality: 0,
                     },
                     b: PhaseMeasurement {
-                        value: 0,
+                        value: -609,
                         quality: 0,
                     },
                     c: PhaseMeasurement {
-                        value: 0,
+                        value: -801,
                         quality: 0,
                     },
                     n: PhaseMeasurement {
@@ -410,19 +483,19 @@ mod tests {
                 },
                 voltage: PhaseMeasures {
                     a: PhaseMeasurement {
-                        value: 235,
+                        value: 8953,
                         quality: 0,
                     },
                     b: PhaseMeasurement {
-                        value: 235,
+                        value: -3866,
                         quality: 0,
                     },
                     c: PhaseMeasurement {
-                        value: 235,
+                        value: -5086,
                         quality: 0,
                     },
                     n: PhaseMeasurement {
-                        value: 705,
+                        value: 0,
                         quality: 0,
                     },
                 },
@@ -0,0 +1,321 @@
+/// How the frequency moves on from its value at `start` seconds. Each
+/// change starts from the frequency reached so far, so the frequency never
+/// jumps unless asked to with a [`FrequencyChange::Step`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrequencyChange {
+    /// Jumps to `frequency` Hz.
+    Step { start: f64, frequency: f64 },
+    /// Moves at `rate` Hz/s (the ROCOF, its sign is ignored) until it
+    /// reaches `frequency`, then holds it.
+    Ramp {
+        start: f64,
+        rate: f64,
+        frequency: f64,
+    },
+    /// Approaches `frequency` exponentially with `time_constant` seconds.
+    Exponential {
+        start: f64,
+        frequency: f64,
+        time_constant: f64,
+    },
+}
+
+impl FrequencyChange {
+    pub fn start(&self) -> f64 {
+        match *self {
+            FrequencyChange::Step { start, .. }
+            | FrequencyChange::Ramp { start, .. }
+            | FrequencyChange::Exponential { start, .. } => start.max(0.0),
+        }
+    }
+
+    /// Frequency and cycles elapsed `elapsed` seconds after the start, from
+    /// `initial` Hz at the start.
+    fn integrate(&self, initial: f64, elapsed: f64) -> (f64, f64) {
+        match *self {
+            FrequencyChange::Step { frequency, .. } => (frequency, frequency * elapsed),
+            FrequencyChange::Ramp {
+                rate, frequency, ..
+            } => {
+                let rate = rate.abs() * (frequency - initial).signum();
+                let ramp_time = if rate == 0.0 {
+                    0.0
+                } else {
+                    (frequency - initial) / rate
+                };
+                if elapsed < ramp_time {
+                    let current = initial + rate * elapsed;
+                    (current, (initial + current) / 2.0 * elapsed)
+                } else {
+                    let ramp = (initial + frequency) / 2.0 * ramp_time;
+                    (frequency, ramp + frequency * (elapsed - ramp_time))
+                }
+            }
+            FrequencyChange::Exponential {
+                frequency,
+                time_constant,
+                ..
+            } => {
+                if time_constant <= 0.0 {
+                    return (frequency, frequency * elapsed);
+                }
+                let decay = (-elapsed / time_constant).exp();
+                let offset = initial - frequency;
+                (
+                    frequency + offset * decay,
+                    frequency * elapsed + offset * time_constant * (1.0 - decay),
+                )
+            }
+        }
+    }
+}
+
+/// Sudden change of `angle` degrees of every phase at `time` seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhaseJump {
+    pub time: f64,
+    pub angle: f64,
+}
+
+/// Frequency over time, with the phase integrated exactly so that frequency
+/// changes never cause a discontinuity. Like [`super::waveform::SineGenerator`]
+/// it is stateless: the angle at any time is computed in closed form.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrequencyProfile {
+    /// Frequency of the reference that angles are measured against.
+    pub nominal: f64,
+    /// Frequency at time 0.
+    pub initial: f64,
+    changes: Vec<FrequencyChange>,
+    jumps: Vec<PhaseJump>,
+    /// Where integration starts once earlier changes were folded.
+    origin: Option<Origin>,
+}
+
+/// Time, frequency and cycles that the folded changes led to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Origin {
+    time: f64,
+    frequency: f64,
+    cycles: f64,
+}
+
+impl FrequencyProfile {
+    pub fn new(nominal: f64, initial: f64) -> Self {
+        FrequencyProfile {
+            nominal,
+            initial,
+            changes: vec![],
+            jumps: vec![],
+            origin: None,
+        }
+    }
+
+    pub fn constant(frequency: f64) -> Self {
+        FrequencyProfile::new(frequency, frequency)
+    }
+
+    /// Adds a change, which lasts until the next one starts.
+    pub fn add_change(&mut self, change: FrequencyChange) {
+        let position = self
+            .changes
+            .partition_point(|other| other.start() <= change.start());
+        self.changes.insert(position, change);
+    }
+
+    pub fn with_change(mut self, change: FrequencyChange) -> Self {
+        self.add_change(change);
+        self
+    }
+
+    pub fn add_phase_jump(&mut self, jump: PhaseJump) {
+        self.jumps.push(jump);
+    }
+
+    pub fn with_phase_jump(mut self, jump: PhaseJump) -> Self {
+        self.add_phase_jump(jump);
+        self
+    }
+
+    /// Changes not folded yet, see [`FrequencyProfile::fold_until`].
+    pub fn changes(&self) -> &[FrequencyChange] {
+        &self.changes
+    }
+
+    /// Replaces the changes that ended by `time` with the frequency and
+    /// cycles they led to, so a profile changed again and again stays cheap
+    /// to integrate. The changes that started last by `time` are kept, as
+    /// they may still be in progress. Later times are unaffected; earlier
+    /// ones are no longer described.
+    pub fn fold_until(&mut self, time: f64) {
+        let started = self
+            .changes
+            .partition_point(|change| change.start() <= time);
+        let Some(latest) = started.checked_sub(1) else {
+            return;
+        };
+        let start = self.changes[latest].start();
+        let ended = self
+            .changes
+            .partition_point(|change| change.start() < start);
+        let (frequency, cycles) = self.integrate(start);
+        self.origin = Some(Origin {
+            time: start,
+            frequency,
+            cycles,
+        });
+        self.changes.drain(..ended);
+    }
+
+    pub fn phase_jumps(&self) -> &[PhaseJump] {
+        &self.jumps
+    }
+
+    /// Instantaneous frequency and cycles since time 0, whole cycles dropped.
+    fn integrate(&self, time: f64) -> (f64, f64) {
+        let time = time.max(0.0);
+        let origin = self.origin.unwrap_or(Origin {
+            time: 0.0,
+            frequency: self.initial,
+            cycles: 0.0,
+        });
+        let mut segment = FrequencyChange::Step {
+            start: origin.time,
+            frequency: origin.frequency,
+        };
+        let mut frequency = origin.frequency;
+        let mut cycles = origin.cycles;
+        for change in self
+            .changes
+            .iter()
+            .take_while(|change| change.start() < time)
+        {
+            let (end, elapsed) = segment.integrate(frequency, change.start() - segment.start());
+            frequency = end;
+            cycles = (cycles + elapsed).fract();
+            segment = *change;
+        }
+        let (frequency, elapsed) = segment.integrate(frequency, time - segment.start());
+        (frequency, (cycles + elapsed).fract())
+    }
+
+    /// Instantaneous frequency at `time` seconds.
+    pub fn frequency(&self, time: f64) -> f64 {
+        self.integrate(time).0
+    }
+
+    /// Fundamental angle at `time` seconds in cycles, within `(-1, 1)`,
+    /// phase jumps included.
+    pub fn cycles(&self, time: f64) -> f64 {
+        let jumps: f64 = self
+            .jumps
+            .iter()
+            .filter(|jump| jump.time <= time)
+            .map(|jump| jump.angle / 360.0)
+            .sum();
+        (self.integrate(time).1 + jumps).fract()
+    }
+
+    /// Angle at `time` seconds against a reference rotating at the nominal
+    /// frequency from time 0, in degrees within `(-180, 180]`.
+    pub fn angle(&self, time: f64) -> f64 {
+        let reference = (self.nominal * time.max(0.0)).fract();
+        wrap_degrees((self.cycles(time) - reference) * 360.0)
+    }
+}
+
+/// Wraps `angle` degrees into `(-180, 180]`.
+pub fn wrap_degrees(angle: f64) -> f64 {
+    let angle = angle.rem_euclid(360.0);
+    if angle > 180.0 { angle - 360.0 } else { angle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9
+    }
+
+    #[test]
+    fn constant_off_nominal_frequency_drifts() {
+        let profile = FrequencyProfile::new(50.0, 50.5);
+        assert_eq!(profile.frequency(10.0), 50.5);
+        // 0.5 Hz slip is 180° per second.
+        assert!(close(profile.angle(1.0), 180.0));
+        assert!(close(profile.angle(0.5), 90.0));
+    }
+
+    #[test]
+    fn linear_ramp_integrates_phase() {
+        let profile = FrequencyProfile::constant(50.0).with_change(FrequencyChange::Ramp {
+            start: 1.0,
+            rate: 2.0,
+            frequency: 49.0,
+        });
+        assert_eq!(profile.frequency(1.0), 50.0);
+        assert!(close(profile.frequency(1.25), 49.5));
+        assert_eq!(profile.frequency(3.0), 49.0);
+        // Ramp from 50 to 49 Hz over 0.5 s loses 0.25 cycles, then 1 Hz slip.
+        assert!(close(profile.angle(1.5), -90.0));
+        assert!(close(profile.angle(1.625), -135.0));
+        assert!(close(profile.angle(2.0), 90.0));
+    }
+
+    #[test]
+    fn exponential_change_and_phase_jump() {
+        let profile = FrequencyProfile::constant(60.0)
+            .with_change(FrequencyChange::Exponential {
+                start: 0.0,
+                frequency: 59.0,
+                time_constant: 0.5,
+            })
+            .with_phase_jump(PhaseJump {
+                time: 2.0,
+                angle: 30.0,
+            });
+        assert!(close(profile.frequency(0.5), 59.0 + (-1.0f64).exp()));
+        let before = profile.angle(1.999_999);
+        let after = profile.angle(2.0);
+        assert!((wrap_degrees(after - before) - 30.0).abs() < 0.001);
+        // 1 Hz slip for 20 s, less the 0.5 cycle integral of the decay, plus
+        // the jump.
+        assert!((profile.angle(20.0) + 150.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn step_keeps_phase_continuous() {
+        let profile = FrequencyProfile::constant(50.0).with_change(FrequencyChange::Step {
+            start: 0.01,
+            frequency: 52.0,
+        });
+        let before = profile.cycles(0.01 - 1e-9);
+        let after = profile.cycles(0.01);
+        assert!((after - before).abs() < 1e-6);
+        assert_eq!(profile.frequency(0.02), 52.0);
+    }
+
+    #[test]
+    fn folding_keeps_the_profile_from_then_on() {
+        let mut profile = FrequencyProfile::constant(50.0);
+        for (index, frequency) in [51.0, 49.5, 50.25].into_iter().enumerate() {
+            profile.add_change(FrequencyChange::Step {
+                start: 0.1 * (index + 1) as f64,
+                frequency,
+            });
+        }
+        profile.add_change(FrequencyChange::Ramp {
+            start: 0.35,
+            rate: 1.0,
+            frequency: 50.0,
+        });
+        let unfolded = profile.clone();
+        profile.fold_until(0.4);
+        assert_eq!(profile.changes().len(), 1);
+        for time in [0.35, 0.4, 0.5, 1.0] {
+            assert!(close(profile.frequency(time), unfolded.frequency(time)));
+            assert!(close(profile.cycles(time), unfolded.cycles(time)));
+        }
+    }
+}
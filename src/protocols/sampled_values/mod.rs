@@ -1,4 +1,12 @@
 pub mod asdu;
+pub mod frequency;
 pub mod model;
 pub mod phases;
+pub mod phasor;
+pub mod quality;
+pub mod rate;
 pub mod sampled_value;
+pub mod scaling;
+#[cfg(feature = "serde")]
+pub mod scenario;
+pub mod waveform;
@@ -31,13 +31,13 @@ pub enum SampleSync {
     Global = 2,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
 pub struct PhaseMeasurement {
     pub value: i32,
     pub quality: i32,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
 pub struct PhaseMeasures {
     pub a: PhaseMeasurement,
     pub b: PhaseMeasurement,
@@ -45,13 +45,13 @@ pub struct PhaseMeasures {
     pub n: PhaseMeasurement,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
 pub struct Phases {
     pub current: PhaseMeasures,
     pub voltage: PhaseMeasures,
 }
 
-#[derive(PartialEq, Debug)]
+#[derive(PartialEq, Debug, Clone)]
 pub struct Asdu {
     pub sv_id: String,
     pub dataset: Option<String>,
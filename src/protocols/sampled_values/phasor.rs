@@ -0,0 +1,465 @@
+use std::collections::VecDeque;
+use std::f64::consts::{PI, SQRT_2};
+use std::fmt;
+use std::io;
+
+use crate::protocols::sampled_values::frequency::wrap_degrees;
+use crate::protocols::sampled_values::model::{Asdu, Phases};
+use crate::protocols::sampled_values::rate;
+use crate::protocols::sampled_values::scaling::DatasetScale;
+use crate::protocols::sampled_values::waveform::Channel;
+
+/// DFT variant of a [`PhasorEstimator`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhasorFilter {
+    /// One cycle of samples, rejects DC and every harmonic.
+    #[default]
+    FullCycle,
+    /// Half a cycle of samples: settles twice as fast, but lets even
+    /// harmonics and DC offsets through.
+    HalfCycle,
+    /// Real part of the full-cycle DFT now and a quarter cycle ago, which
+    /// also rejects most of a decaying DC offset at the cost of a quarter
+    /// cycle of extra delay.
+    Cosine,
+}
+
+impl PhasorFilter {
+    /// Samples needed before the first estimate, at `samples_per_cycle`.
+    pub fn window(self, samples_per_cycle: u32) -> u32 {
+        match self {
+            PhasorFilter::FullCycle => samples_per_cycle,
+            PhasorFilter::HalfCycle => samples_per_cycle / 2,
+            PhasorFilter::Cosine => samples_per_cycle + samples_per_cycle / 4,
+        }
+    }
+}
+
+/// When a [`PhasorEstimator`] hands out estimates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PhasorReporting {
+    /// On the last sample of each cycle, counting cycles from smpCnt 0.
+    #[default]
+    EveryCycle,
+    EverySample,
+}
+
+/// Fundamental phasor, `magnitude` RMS in A or V and `angle` in degrees
+/// within `(-180, 180]`, following the [`super::waveform::Sinusoid`]
+/// convention `magnitude * √2 * cos(2π f t + angle)`. Scenario states use
+/// it too, where a missing angle reads as 0°.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Phasor {
+    pub magnitude: f64,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub angle: f64,
+}
+
+impl Phasor {
+    pub fn from_rectangular(real: f64, imaginary: f64) -> Self {
+        Phasor {
+            magnitude: real.hypot(imaginary),
+            angle: wrap_degrees(imaginary.atan2(real).to_degrees()),
+        }
+    }
+
+    pub fn real(&self) -> f64 {
+        self.magnitude * self.angle.to_radians().cos()
+    }
+
+    pub fn imaginary(&self) -> f64 {
+        self.magnitude * self.angle.to_radians().sin()
+    }
+}
+
+impl fmt::Display for Phasor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.4} ∠ {:.2}°", self.magnitude, self.angle)
+    }
+}
+
+/// Phasors of the eight dataset channels for the window ending on sample
+/// `smp_count`, in dataset order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PhasorSet {
+    pub smp_count: u16,
+    pub phasors: [Phasor; 8],
+}
+
+impl PhasorSet {
+    pub fn get(&self, channel: Channel) -> &Phasor {
+        &self.phasors[channel.index()]
+    }
+}
+
+/// Running DFT of the decoded [`Phases`] of one stream.
+///
+/// The sums are recursive: each sample adds its term and removes the one
+/// of the sample leaving the window, and they are recomputed from the
+/// window once per cycle so that rounding never accumulates. Terms rotate
+/// with smpCnt modulo the samples per cycle rather than with the position
+/// in the window, so with a time-synchronised publisher the angles are
+/// referenced to a cosine peaking at the top of the second and a phasor at
+/// nominal frequency stands still. Off nominal it turns at the slip
+/// frequency.
+///
+/// A smpCnt that does not follow the previous one (lost samples, a restart)
+/// empties the window, so estimates resume once it has filled again.
+#[derive(Clone, Debug)]
+pub struct PhasorEstimator {
+    filter: PhasorFilter,
+    reporting: PhasorReporting,
+    sample_rate: u32,
+    samples_per_cycle: usize,
+    scale: DatasetScale,
+    /// `e^(-j2πk/N)` for each position `k` in the cycle.
+    twiddles: Vec<(f64, f64)>,
+    samples: VecDeque<[f64; 8]>,
+    sums: [(f64, f64); 8],
+    /// Cosine filter outputs of the last quarter cycle.
+    cosines: VecDeque<[f64; 8]>,
+    last: Option<u16>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl PhasorEstimator {
+    /// Estimator over `samples_per_cycle` samples per cycle at
+    /// `sample_rate`, which must hold a whole number of cycles so that
+    /// smpCnt 0 always starts one.
+    pub fn new(sample_rate: u32, samples_per_cycle: u32, filter: PhasorFilter) -> io::Result<Self> {
+        if samples_per_cycle < 4 || !sample_rate.is_multiple_of(samples_per_cycle) {
+            return Err(invalid(format!(
+                "{} samples per cycle do not divide {} Hz into whole cycles",
+                samples_per_cycle, sample_rate
+            )));
+        }
+        if filter == PhasorFilter::Cosine && !samples_per_cycle.is_multiple_of(4) {
+            return Err(invalid(format!(
+                "Cosine filter needs a multiple of 4 samples per cycle, not {}",
+                samples_per_cycle
+            )));
+        }
+        if filter == PhasorFilter::HalfCycle && !samples_per_cycle.is_multiple_of(2) {
+            return Err(invalid(format!(
+                "Half-cycle filter needs an even number of samples per cycle, not {}",
+                samples_per_cycle
+            )));
+        }
+        let samples_per_cycle = samples_per_cycle as usize;
+        let twiddles = (0..samples_per_cycle)
+            .map(|k| {
+                let angle = 2.0 * PI * k as f64 / samples_per_cycle as f64;
+                (angle.cos(), -angle.sin())
+            })
+            .collect();
+        Ok(PhasorEstimator {
+            filter,
+            reporting: PhasorReporting::default(),
+            sample_rate,
+            samples_per_cycle,
+            scale: DatasetScale::default(),
+            twiddles,
+            samples: VecDeque::with_capacity(samples_per_cycle + 1),
+            sums: [(0.0, 0.0); 8],
+            cosines: VecDeque::with_capacity(samples_per_cycle / 4 + 1),
+            last: None,
+        })
+    }
+
+    /// Estimator for a stream of `frequency` Hz at `sample_rate`.
+    pub fn for_frequency(
+        sample_rate: u32,
+        frequency: u32,
+        filter: PhasorFilter,
+    ) -> io::Result<Self> {
+        let samples_per_cycle =
+            rate::samples_per_cycle(sample_rate, frequency).ok_or_else(|| {
+                invalid(format!(
+                    "{} Hz is not a whole number of samples per cycle of {} Hz",
+                    sample_rate, frequency
+                ))
+            })?;
+        PhasorEstimator::new(sample_rate, samples_per_cycle, filter)
+    }
+
+    /// Scaling from raw counts to A and V, 9-2LE by default.
+    pub fn scale(mut self, scale: DatasetScale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn reporting(mut self, reporting: PhasorReporting) -> Self {
+        self.reporting = reporting;
+        self
+    }
+
+    pub fn filter(&self) -> PhasorFilter {
+        self.filter
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn samples_per_cycle(&self) -> u32 {
+        self.samples_per_cycle as u32
+    }
+
+    /// Samples needed after a reset before the first estimate.
+    pub fn window(&self) -> u32 {
+        self.filter.window(self.samples_per_cycle())
+    }
+
+    /// Empties the window.
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.cosines.clear();
+        self.sums = [(0.0, 0.0); 8];
+        self.last = None;
+    }
+
+    pub fn push_asdu(&mut self, asdu: &Asdu) -> Option<PhasorSet> {
+        self.push(asdu.smp_count, &asdu.measures)
+    }
+
+    /// Adds sample `smp_count`, returning the phasors of the window ending
+    /// on it when the window is full and one is due.
+    pub fn push(&mut self, smp_count: u16, phases: &Phases) -> Option<PhasorSet> {
+        if let Some(last) = self.last
+            && u32::from(smp_count) != (u32::from(last) + 1) % self.sample_rate
+        {
+            self.reset();
+        }
+        self.last = Some(smp_count);
+
+        let n = self.samples_per_cycle;
+        let position = usize::from(smp_count) % n;
+        let values = self.scale.values(phases);
+        let (re, im) = self.twiddles[position];
+        for (sum, value) in self.sums.iter_mut().zip(values) {
+            sum.0 += value * re;
+            sum.1 += value * im;
+        }
+        self.samples.push_back(values);
+        let length = self.dft_length();
+        if self.samples.len() > length
+            && let Some(old) = self.samples.pop_front()
+        {
+            let (re, im) = self.twiddles[(position + n - length) % n];
+            for (sum, value) in self.sums.iter_mut().zip(old) {
+                sum.0 -= value * re;
+                sum.1 -= value * im;
+            }
+        }
+        if position == 0 {
+            self.recompute(position);
+        }
+        if self.samples.len() < length {
+            return None;
+        }
+
+        let phasors = match self.filter {
+            PhasorFilter::FullCycle | PhasorFilter::HalfCycle => {
+                let gain = SQRT_2 / length as f64;
+                self.sums.map(|(real, imaginary)| {
+                    Phasor::from_rectangular(real * gain, imaginary * gain)
+                })
+            }
+            PhasorFilter::Cosine => {
+                // Real part of the rotating phasor, a cosine filter output.
+                let gain = 2.0 / n as f64;
+                let cosine = self
+                    .sums
+                    .map(|(real, imaginary)| (real * re + imaginary * im) * gain);
+                self.cosines.push_back(cosine);
+                if self.cosines.len() <= n / 4 {
+                    return None;
+                }
+                let quarter = self.cosines.pop_front()?;
+                // The output a quarter cycle ago is the imaginary part, and
+                // the product with e^(-j2πk/N) stops the rotation.
+                let mut phasors = [Phasor::default(); 8];
+                for (index, phasor) in phasors.iter_mut().enumerate() {
+                    let (real, imaginary) = (cosine[index], quarter[index]);
+                    *phasor = Phasor::from_rectangular(
+                        (real * re - imaginary * im) / SQRT_2,
+                        (real * im + imaginary * re) / SQRT_2,
+                    );
+                }
+                phasors
+            }
+        };
+        let due = match self.reporting {
+            PhasorReporting::EverySample => true,
+            PhasorReporting::EveryCycle => position == n - 1,
+        };
+        due.then_some(PhasorSet { smp_count, phasors })
+    }
+
+    fn dft_length(&self) -> usize {
+        match self.filter {
+            PhasorFilter::HalfCycle => self.samples_per_cycle / 2,
+            PhasorFilter::FullCycle | PhasorFilter::Cosine => self.samples_per_cycle,
+        }
+    }
+
+    /// Sums the window again, the newest sample being at `position`.
+    fn recompute(&mut self, position: usize) {
+        let n = self.samples_per_cycle;
+        let first = position + n - (self.samples.len() - 1);
+        self.sums = [(0.0, 0.0); 8];
+        for (offset, values) in self.samples.iter().enumerate() {
+            let (re, im) = self.twiddles[(first + offset) % n];
+            for (sum, value) in self.sums.iter_mut().zip(values) {
+                sum.0 += value * re;
+                sum.1 += value * im;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::sampled_values::waveform::{Harmonic, SineGenerator, Sinusoid};
+
+    const RATE: u32 = 4000;
+
+    fn generator() -> SineGenerator {
+        let mut generator = SineGenerator::balanced(50.0, 100.0, 6350.0);
+        generator.get_mut(Channel::Ia).unwrap().phase = -30.0;
+        generator
+    }
+
+    fn phases_at(generator: &SineGenerator, index: u64) -> Phases {
+        let mut phases = Phases::default();
+        let values = Channel::ALL.map(|channel| generator.value_at(channel, index, RATE));
+        DatasetScale::default().set_values(&mut phases, &values);
+        phases
+    }
+
+    fn run(
+        estimator: &mut PhasorEstimator,
+        generator: &SineGenerator,
+        indexes: std::ops::Range<u64>,
+    ) -> Vec<PhasorSet> {
+        indexes
+            .filter_map(|index| {
+                let smp_count = (index % u64::from(RATE)) as u16;
+                estimator.push(smp_count, &phases_at(generator, index))
+            })
+            .collect()
+    }
+
+    fn assert_phasor(phasor: &Phasor, magnitude: f64, angle: f64) {
+        assert!(
+            (phasor.magnitude - magnitude).abs() < magnitude * 1e-3 + 2e-3,
+            "{} is not {} ∠ {}",
+            phasor,
+            magnitude,
+            angle
+        );
+        if magnitude > 0.0 {
+            assert!(
+                wrap_degrees(phasor.angle - angle).abs() < 0.05,
+                "{} is not {} ∠ {}",
+                phasor,
+                magnitude,
+                angle
+            );
+        }
+    }
+
+    #[test]
+    fn every_filter_estimates_the_fundamental() {
+        let generator = generator();
+        for filter in [
+            PhasorFilter::FullCycle,
+            PhasorFilter::HalfCycle,
+            PhasorFilter::Cosine,
+        ] {
+            let mut estimator = PhasorEstimator::for_frequency(RATE, 50, filter).unwrap();
+            assert_eq!(estimator.samples_per_cycle(), 80);
+            // Starts just before the top of the second, across the smpCnt wrap.
+            let estimates = run(&mut estimator, &generator, 3_930..4_410);
+            let first = u64::from(estimator.window()) + 3_930;
+            let cycles = (first..4_410).filter(|index| index % 80 == 79).count();
+            assert_eq!(estimates.len(), cycles, "{:?}", filter);
+            for estimate in &estimates {
+                assert_eq!(estimate.smp_count % 80, 79);
+                assert_phasor(estimate.get(Channel::Ia), 100.0, -30.0);
+                assert_phasor(estimate.get(Channel::Ib), 100.0, -120.0);
+                assert_phasor(estimate.get(Channel::Va), 6350.0, 0.0);
+                assert_phasor(estimate.get(Channel::Vc), 6350.0, 120.0);
+                assert_phasor(estimate.get(Channel::Vn), 0.0, 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn half_cycle_lets_even_harmonics_through() {
+        let mut generator = SineGenerator::balanced(50.0, 100.0, 6350.0).with(
+            Channel::Ia,
+            Some(Sinusoid {
+                rms: 100.0,
+                frequency: 50.0,
+                phase: 45.0,
+                scale: Channel::Ia.default_scale(),
+            }),
+        );
+        generator.add_harmonic(
+            Channel::Ia,
+            Harmonic {
+                order: 2,
+                magnitude: 0.2,
+                angle: 0.0,
+            },
+        );
+        let mut full = PhasorEstimator::new(RATE, 80, PhasorFilter::FullCycle)
+            .unwrap()
+            .reporting(PhasorReporting::EverySample);
+        let estimates = run(&mut full, &generator, 0..400);
+        assert_eq!(estimates.len(), 400 - 79);
+        assert!(estimates.iter().all(|estimate| {
+            let phasor = estimate.get(Channel::Ia);
+            (phasor.magnitude - 100.0).abs() < 0.1 && wrap_degrees(phasor.angle - 45.0).abs() < 0.05
+        }));
+
+        let mut cosine = PhasorEstimator::new(RATE, 80, PhasorFilter::Cosine)
+            .unwrap()
+            .reporting(PhasorReporting::EverySample);
+        let estimates = run(&mut cosine, &generator, 0..400);
+        assert_eq!(estimates.len(), 400 - 99);
+        assert_phasor(estimates[0].get(Channel::Ia), 100.0, 45.0);
+
+        let mut half = PhasorEstimator::new(RATE, 80, PhasorFilter::HalfCycle)
+            .unwrap()
+            .reporting(PhasorReporting::EverySample);
+        let worst = run(&mut half, &generator, 0..400)
+            .iter()
+            .map(|estimate| (estimate.get(Channel::Ia).magnitude - 100.0).abs())
+            .fold(0.0, f64::max);
+        assert!(worst > 5.0);
+    }
+
+    #[test]
+    fn gap_in_smp_count_refills_the_window() {
+        let generator = generator();
+        let mut estimator = PhasorEstimator::new(RATE, 80, PhasorFilter::FullCycle)
+            .unwrap()
+            .reporting(PhasorReporting::EverySample);
+        assert_eq!(run(&mut estimator, &generator, 0..100).len(), 21);
+        // Sample 100 is lost.
+        let estimates = run(&mut estimator, &generator, 101..300);
+        assert_eq!(estimates.first().unwrap().smp_count, 180);
+        assert_phasor(estimates[0].get(Channel::Ia), 100.0, -30.0);
+
+        assert!(PhasorEstimator::new(RATE, 96, PhasorFilter::FullCycle).is_err());
+        assert!(PhasorEstimator::new(4800, 6, PhasorFilter::Cosine).is_err());
+        assert!(PhasorEstimator::for_frequency(4000, 60, PhasorFilter::FullCycle).is_err());
+    }
+}
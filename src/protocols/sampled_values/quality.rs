@@ -0,0 +1,99 @@
+//! Bits of the 9-2LE quality word (IEC 61850-8-1 Quality, LSB first).
+
+/// Validity, bits 0-1.
+pub const VALIDITY_MASK: i32 = 0b11;
+pub const VALIDITY_GOOD: i32 = 0b00;
+pub const VALIDITY_INVALID: i32 = 0b01;
+pub const VALIDITY_QUESTIONABLE: i32 = 0b11;
+
+// Detail quality.
+pub const OVERFLOW: i32 = 1 << 2;
+pub const OUT_OF_RANGE: i32 = 1 << 3;
+pub const BAD_REFERENCE: i32 = 1 << 4;
+pub const OSCILLATORY: i32 = 1 << 5;
+pub const FAILURE: i32 = 1 << 6;
+pub const OLD_DATA: i32 = 1 << 7;
+pub const INCONSISTENT: i32 = 1 << 8;
+pub const INACCURATE: i32 = 1 << 9;
+
+/// Source: substituted instead of process.
+pub const SUBSTITUTED: i32 = 1 << 10;
+pub const TEST: i32 = 1 << 11;
+pub const OPERATOR_BLOCKED: i32 = 1 << 12;
+/// 9-2LE: value computed from other channels, such as a residual neutral.
+pub const DERIVED: i32 = 1 << 13;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
+pub enum Validity {
+    Good,
+    Invalid,
+    Questionable,
+}
+
+impl Validity {
+    pub fn bits(self) -> i32 {
+        match self {
+            Validity::Good => VALIDITY_GOOD,
+            Validity::Invalid => VALIDITY_INVALID,
+            Validity::Questionable => VALIDITY_QUESTIONABLE,
+        }
+    }
+}
+
+/// Forces parts of a channel quality word. Fields left `None` keep the
+/// value produced by the waveform source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(default, deny_unknown_fields))]
+pub struct QualityOverride {
+    pub validity: Option<Validity>,
+    pub test: Option<bool>,
+}
+
+impl QualityOverride {
+    pub const NONE: QualityOverride = QualityOverride {
+        validity: None,
+        test: None,
+    };
+
+    pub fn is_none(&self) -> bool {
+        *self == QualityOverride::NONE
+    }
+
+    pub fn apply(&self, quality: i32) -> i32 {
+        let mut quality = quality;
+        if let Some(validity) = self.validity {
+            quality = (quality & !VALIDITY_MASK) | validity.bits();
+        }
+        match self.test {
+            Some(true) => quality | TEST,
+            Some(false) => quality & !TEST,
+            None => quality,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn override_keeps_other_bits() {
+        let questionable_test = QualityOverride {
+            validity: Some(Validity::Questionable),
+            test: Some(true),
+        };
+        assert_eq!(
+            questionable_test.apply(OVERFLOW | VALIDITY_INVALID),
+            OVERFLOW | VALIDITY_QUESTIONABLE | TEST
+        );
+        let good = QualityOverride {
+            validity: Some(Validity::Good),
+            test: Some(false),
+        };
+        assert_eq!(good.apply(VALIDITY_INVALID | TEST | DERIVED), DERIVED);
+        assert_eq!(QualityOverride::NONE.apply(TEST), TEST);
+    }
+}
@@ -0,0 +1,29 @@
+/// Sample rates of IEC 61869-9 and 9-2LE, in samples per second: 80, 96 and
+/// 256 samples per cycle at 50 or 60 Hz, plus the 14400 Hz power quality
+/// rate shared by both frequencies.
+pub const STANDARD_RATES: [u32; 6] = [4000, 4800, 5760, 12800, 14400, 15360];
+
+pub fn is_standard_rate(sample_rate: u32) -> bool {
+    STANDARD_RATES.contains(&sample_rate)
+}
+
+/// Samples per cycle of `frequency` at `sample_rate`, if it is a whole number.
+pub fn samples_per_cycle(sample_rate: u32, frequency: u32) -> Option<u32> {
+    (frequency > 0 && sample_rate.is_multiple_of(frequency)).then(|| sample_rate / frequency)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn standard_rates_per_cycle() {
+        assert_eq!(samples_per_cycle(4000, 50), Some(80));
+        assert_eq!(samples_per_cycle(4800, 60), Some(80));
+        assert_eq!(samples_per_cycle(4800, 50), Some(96));
+        assert_eq!(samples_per_cycle(15360, 60), Some(256));
+        assert_eq!(samples_per_cycle(4000, 60), None);
+        assert!(is_standard_rate(14400));
+        assert!(!is_standard_rate(1000));
+    }
+}
@@ -1,39 +1,71 @@
 use crate::protocols::sampled_values::model::{Asdu, PDUTags, SampledValue};
 use crate::standards::asn1::Triplet;
 use byteorder::{BigEndian, ByteOrder};
+use std::io;
 
 impl SampledValue {
     pub fn from_bytes(bytes: &[u8]) -> SampledValue {
-        let sav_pdu_triplet = Triplet::from_bytes(&bytes[8..]);
-        let no_asdu_triplet = Triplet::from_bytes(&sav_pdu_triplet.value);
+        Self::try_from_bytes(bytes).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Decodes the APDU following the Ethernet header, failing on truncated
+    /// or malformed frames instead of panicking.
+    pub fn try_from_bytes(bytes: &[u8]) -> io::Result<SampledValue> {
+        if bytes.len() < 8 {
+            return Err(invalid(format!(
+                "Sampled Values header truncated to {} bytes",
+                bytes.len()
+            )));
+        }
+        let sav_pdu_triplet = Triplet::try_from_bytes(&bytes[8..])?;
+        if sav_pdu_triplet.tag != PDUTags::PDU as u8 {
+            return Err(invalid(format!(
+                "savPdu tag is {:#04x}, not 0x60",
+                sav_pdu_triplet.tag
+            )));
+        }
+        let no_asdu_triplet = Triplet::try_from_bytes(&sav_pdu_triplet.value)?;
+        if no_asdu_triplet.tag != PDUTags::NoAsdu as u8
+            || !(1..=4).contains(&no_asdu_triplet.length)
+        {
+            return Err(invalid("Malformed noASDU in savPdu".to_string()));
+        }
         let seq_asdu_triplet =
-            Triplet::from_bytes(&sav_pdu_triplet.value[no_asdu_triplet.length()..]);
+            Triplet::try_from_bytes(&sav_pdu_triplet.value[no_asdu_triplet.length()..])?;
+        if seq_asdu_triplet.tag != PDUTags::SeqAsdu as u8 {
+            return Err(invalid(format!(
+                "seqASDU tag is {:#04x}, not 0xa2",
+                seq_asdu_triplet.tag
+            )));
+        }
 
-        let number_of_asdu = BigEndian::read_int(
-            &no_asdu_triplet.value,
-            1 + no_asdu_triplet.extended_length as usize,
-        ) as u32;
+        let number_of_asdu =
+            BigEndian::read_uint(&no_asdu_triplet.value, no_asdu_triplet.length) as u32;
         let mut asdus: Vec<Asdu> = vec![];
         let mut asdu_start = 0;
         for _ in 0..number_of_asdu {
-            let asdu_triplet = Triplet::from_bytes(&seq_asdu_triplet.value[asdu_start..]);
+            let asdu_triplet = Triplet::try_from_bytes(&seq_asdu_triplet.value[asdu_start..])?;
 
             if asdu_triplet.tag != PDUTags::ASDU as u8 {
-                panic!("ASDU tag is not 0x30 at offset {} of seq_asdu", asdu_start);
+                return Err(invalid(format!(
+                    "ASDU tag is not 0x30 at offset {} of seq_asdu",
+                    asdu_start
+                )));
             }
-            let asdu = Asdu::from_bytes(&asdu_triplet.value);
+            let asdu = Asdu::try_from_bytes(&asdu_triplet.value)?;
             asdus.push(asdu);
             asdu_start += asdu_triplet.length();
         }
-        SampledValue {
+        Ok(SampledValue {
             app_id: BigEndian::read_u16(&bytes[0..2]),
             length: BigEndian::read_u16(&bytes[2..4]),
-            simulation: bytes[4] >> 3 == 1,
-            reserved1: [bytes[4], bytes[5]],
+            // Simulation is the most significant bit of reserved1.
+            simulation: bytes[4] & 0x80 != 0,
+            reserved1: [bytes[4] & 0x7f, bytes[5]],
             reserved2: [bytes[6], bytes[7]],
             number_of_asdu,
             asdu: asdus,
-        }
+        })
     }
 
     fn pdu_to_bytes(&self) -> Vec<u8> {
@@ -59,7 +91,7 @@ impl SampledValue {
         let mut bytes: Vec<u8> = vec![0; 4];
         BigEndian::write_u16(&mut bytes, self.app_id);
         BigEndian::write_u16(&mut bytes[2..], self.length);
-        bytes.push(self.reserved1[0] | (self.simulation as u8) << 3);
+        bytes.push(self.reserved1[0] | (self.simulation as u8) << 7);
         bytes.push(self.reserved1[1]);
         bytes.push(self.reserved2[0]);
         bytes.push(self.reserved2[1]);
@@ -71,12 +103,23 @@ impl SampledValue {
     }
 
     pub fn next(&mut self) {
+        self.advance(4800, 80);
+    }
+
+    /// Moves every ASDU ahead by the number of ASDUs in the frame, so that
+    /// consecutive ASDUs keep carrying consecutive samples.
+    pub fn advance(&mut self, sample_rate: u32, samples_per_cycle: u32) {
+        let step = self.asdu.len() as u32;
         for asdu in &mut self.asdu {
-            asdu.next();
+            asdu.advance(step, sample_rate, samples_per_cycle);
         }
     }
 }
 
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,6 +186,8 @@ mod tests {
         ];
 
         let sampled_value = SampledValue::from_bytes(bytes);
+        assert!(sampled_value.simulation);
+        assert_eq!(sampled_value.reserved1, [0, 0]);
         assert_eq!(bytes, sampled_value.to_bytes());
     }
 
@@ -171,4 +216,36 @@ mod tests {
         let sampled_value = SampledValue::from_bytes(bytes);
         assert_eq!(bytes, sampled_value.to_bytes());
     }
+
+    #[test]
+    fn advance_keeps_consecutive_samples() {
+        let bytes: &[u8] = &[
+            0x40, 0x02, 0x00, 0x66, 0x00, 0x00, 0x00, 0x00, // Header
+            0x60, 0x5c, // PDU
+            0x80, 0x01, 0x01, // number of ASDU
+            0xa2, 0x57, // sequence of ASDU
+            0x30, 0x55, 0x80, 0x04, 0x34, 0x30, 0x30, 0x30, 0x82, 0x02, 0x00, 0x00, 0x83, 0x04,
+            0x00, 0x00, 0x00, 0x01, 0x85, 0x01, 0x01, 0x87, 0x40, 0xff, 0xff, 0xff, 0xfd, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x20, 0x00, 0xff,
+            0xff, 0xff, 0xfd, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xfd, 0x00, 0x00, 0x00,
+            0x00, 0xff, 0xff, 0xff, 0xfc, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xf6, 0x00,
+            0x00, 0x20, 0x00, // ASDU
+        ];
+        let mut sampled_value = SampledValue::from_bytes(bytes);
+        let mut second = sampled_value.asdu[0].clone();
+        second.smp_count = 1;
+        sampled_value.asdu.push(second);
+        sampled_value.number_of_asdu = 2;
+
+        sampled_value.advance(4000, 80);
+        let counts: Vec<u16> = sampled_value.asdu.iter().map(|a| a.smp_count).collect();
+        assert_eq!(counts, vec![2, 3]);
+
+        sampled_value.asdu[0].smp_count = 3998;
+        sampled_value.asdu[1].smp_count = 3999;
+        sampled_value.advance(4000, 80);
+        let counts: Vec<u16> = sampled_value.asdu.iter().map(|a| a.smp_count).collect();
+        assert_eq!(counts, vec![0, 1]);
+    }
 }
@@ -0,0 +1,180 @@
+use crate::protocols::sampled_values::model::{PhaseMeasurement, Phases};
+use crate::protocols::sampled_values::quality::OVERFLOW;
+use crate::protocols::sampled_values::waveform::Channel;
+
+/// Conversion between raw INT32 counts of one channel and amperes or volts.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChannelScale {
+    /// Primary A or V per count.
+    pub lsb: f64,
+    /// Instrument transformer ratio, primary over secondary.
+    pub ratio: f64,
+}
+
+impl ChannelScale {
+    pub const fn new(lsb: f64) -> Self {
+        ChannelScale { lsb, ratio: 1.0 }
+    }
+
+    pub const fn with_ratio(mut self, ratio: f64) -> Self {
+        self.ratio = ratio;
+        self
+    }
+
+    /// 9-2LE scale of `channel`: 1 mA or 10 mV per count.
+    pub fn default_for(channel: Channel) -> Self {
+        ChannelScale::new(channel.default_scale())
+    }
+
+    /// Primary value of `raw` counts.
+    pub fn to_value(&self, raw: i32) -> f64 {
+        raw as f64 * self.lsb
+    }
+
+    /// Counts for the primary `value`, rounded and clamped to the i32 range.
+    /// The flag tells whether the value saturated.
+    pub fn to_raw(&self, value: f64) -> (i32, bool) {
+        let counts = (value / self.lsb).round();
+        if counts.is_nan() {
+            (0, false)
+        } else if counts > i32::MAX as f64 {
+            (i32::MAX, true)
+        } else if counts < i32::MIN as f64 {
+            (i32::MIN, true)
+        } else {
+            (counts as i32, false)
+        }
+    }
+
+    /// Secondary value of `raw` counts, behind the CT or VT.
+    pub fn to_secondary(&self, raw: i32) -> f64 {
+        self.to_value(raw) / self.ratio
+    }
+
+    pub fn from_secondary(&self, value: f64) -> (i32, bool) {
+        self.to_raw(value * self.ratio)
+    }
+
+    pub fn value(&self, measurement: &PhaseMeasurement) -> f64 {
+        self.to_value(measurement.value)
+    }
+
+    /// Stores the primary `value` in `measurement`, setting the overflow
+    /// quality bit when it saturates and clearing it otherwise.
+    pub fn set_value(&self, measurement: &mut PhaseMeasurement, value: f64) {
+        let (raw, overflow) = self.to_raw(value);
+        measurement.value = raw;
+        if overflow {
+            measurement.quality |= OVERFLOW;
+        } else {
+            measurement.quality &= !OVERFLOW;
+        }
+    }
+}
+
+/// Scales of the eight dataset channels, in dataset order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DatasetScale(pub [ChannelScale; 8]);
+
+impl Default for DatasetScale {
+    fn default() -> Self {
+        DatasetScale(Channel::ALL.map(ChannelScale::default_for))
+    }
+}
+
+impl DatasetScale {
+    /// 9-2LE scales with the same CT ratio on the currents and VT ratio on
+    /// the voltages.
+    pub fn with_ratios(ct_ratio: f64, vt_ratio: f64) -> Self {
+        DatasetScale(Channel::ALL.map(|channel| {
+            let ratio = if channel.is_current() {
+                ct_ratio
+            } else {
+                vt_ratio
+            };
+            ChannelScale::default_for(channel).with_ratio(ratio)
+        }))
+    }
+
+    pub fn get(&self, channel: Channel) -> &ChannelScale {
+        &self.0[channel.index()]
+    }
+
+    pub fn set(&mut self, channel: Channel, scale: ChannelScale) {
+        self.0[channel.index()] = scale;
+    }
+
+    pub fn value(&self, channel: Channel, phases: &Phases) -> f64 {
+        self.get(channel).value(channel.measurement(phases))
+    }
+
+    pub fn set_value(&self, channel: Channel, phases: &mut Phases, value: f64) {
+        self.get(channel)
+            .set_value(channel.measurement_mut(phases), value)
+    }
+
+    /// Primary values of every channel, in dataset order.
+    pub fn values(&self, phases: &Phases) -> [f64; 8] {
+        Channel::ALL.map(|channel| self.value(channel, phases))
+    }
+
+    pub fn secondary_values(&self, phases: &Phases) -> [f64; 8] {
+        Channel::ALL.map(|channel| {
+            self.get(channel)
+                .to_secondary(channel.measurement(phases).value)
+        })
+    }
+
+    pub fn set_values(&self, phases: &mut Phases, values: &[f64; 8]) {
+        for channel in Channel::ALL {
+            self.set_value(channel, phases, values[channel.index()]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_9_2le_scaling() {
+        let scale = DatasetScale::default();
+        let mut phases = Phases::default();
+        scale.set_value(Channel::Ia, &mut phases, 1.2345);
+        scale.set_value(Channel::Va, &mut phases, -230.0);
+        assert_eq!(phases.current.a.value, 1_235);
+        assert_eq!(phases.voltage.a.value, -23_000);
+        assert_eq!(scale.value(Channel::Va, &phases), -230.0);
+        assert_eq!(phases.current.a.quality, 0);
+    }
+
+    #[test]
+    fn ratios_give_secondary_values() {
+        let scale = DatasetScale::with_ratios(400.0, 1_000.0);
+        let mut phases = Phases::default();
+        let (raw, overflow) = scale.get(Channel::Ib).from_secondary(5.0);
+        assert_eq!((raw, overflow), (2_000_000, false));
+        phases.current.b.value = raw;
+        phases.voltage.c.value = 11_000_000;
+        let secondary = scale.secondary_values(&phases);
+        assert_eq!(secondary[Channel::Ib.index()], 5.0);
+        assert_eq!(secondary[Channel::Vc.index()], 110.0);
+    }
+
+    #[test]
+    fn saturation_sets_overflow() {
+        let scale = DatasetScale::default();
+        let mut phases = Phases::default();
+        phases.current.a.quality = 0b1;
+        scale.set_value(Channel::Ia, &mut phases, 3e6);
+        assert_eq!(phases.current.a.value, i32::MAX);
+        assert_eq!(phases.current.a.quality, 0b1 | OVERFLOW);
+
+        scale.set_value(Channel::Ia, &mut phases, -3e6);
+        assert_eq!(phases.current.a.value, i32::MIN);
+
+        scale.set_value(Channel::Ia, &mut phases, 1.0);
+        assert_eq!(phases.current.a.value, 1_000);
+        assert_eq!(phases.current.a.quality, 0b1);
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::BTreeMap;
+use std::f64::consts::{PI, SQRT_2};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocols::sampled_values::{
+    model::Phases,
+    phasor::Phasor,
+    quality::QualityOverride,
+    scaling::DatasetScale,
+    waveform::{Channel, WaveformChange, WaveformSource, check_frequency},
+};
+
+fn default_frequency() -> f64 {
+    50.0
+}
+
+/// One state of a sequence, like a state of a secondary injection test set.
+/// Channels without a phasor are zero, neutrals without one are the residual
+/// sum of their phases.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    pub name: String,
+    #[serde(default = "default_frequency")]
+    pub frequency: f64,
+    /// Seconds before moving to the next state, `None` to wait for a trigger
+    /// or to hold forever.
+    #[serde(default)]
+    pub duration: Option<f64>,
+    /// Leave the state when [`ScenarioControl::trigger`] is called.
+    #[serde(default)]
+    pub external_trigger: bool,
+    /// Name of the next state, the following one when `None`.
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub phasors: BTreeMap<Channel, Phasor>,
+    /// Publish with the simulation bit set while in this state.
+    #[serde(default)]
+    pub simulation: bool,
+    /// Quality of the channels while in this state, good otherwise.
+    #[serde(default)]
+    pub quality: BTreeMap<Channel, QualityOverride>,
+}
+
+/// Ordered list of states, loadable from TOML or JSON:
+///
+/// ```toml
+/// [[states]]
+/// name = "prefault"
+/// duration = 1.0
+/// phasors.Ia = { magnitude = 1.0, angle = 0.0 }
+///
+/// [[states]]
+/// name = "fault"
+/// duration = 0.1
+/// phasors.Ia = { magnitude = 20.0, angle = -80.0 }
+/// simulation = true
+/// quality.Ib = { validity = "questionable", test = true }
+/// ```
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Scenario {
+    pub states: Vec<State>,
+}
+
+#[derive(Debug)]
+pub enum ScenarioError {
+    Io(io::Error),
+    Parse(String),
+    Invalid(String),
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScenarioError::Io(err) => write!(f, "Cannot read scenario: {}", err),
+            ScenarioError::Parse(err) => write!(f, "Cannot parse scenario: {}", err),
+            ScenarioError::Invalid(err) => write!(f, "Invalid scenario: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ScenarioError {}
+
+impl From<io::Error> for ScenarioError {
+    fn from(err: io::Error) -> Self {
+        ScenarioError::Io(err)
+    }
+}
+
+impl Scenario {
+    pub fn from_toml(text: &str) -> Result<Self, ScenarioError> {
+        let scenario: Scenario =
+            toml::from_str(text).map_err(|err| ScenarioError::Parse(err.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    pub fn from_json(text: &str) -> Result<Self, ScenarioError> {
+        let scenario: Scenario =
+            serde_json::from_str(text).map_err(|err| ScenarioError::Parse(err.to_string()))?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+
+    /// Reads a `.toml` or `.json` scenario file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            Some("json") => Self::from_json(&text),
+            _ => Err(ScenarioError::Parse(format!(
+                "{} is neither .toml nor .json",
+                path.display()
+            ))),
+        }
+    }
+
+    pub fn state_index(&self, name: &str) -> Option<usize> {
+        self.states.iter().position(|state| state.name == name)
+    }
+
+    pub fn validate(&self) -> Result<(), ScenarioError> {
+        if self.states.is_empty() {
+            return Err(ScenarioError::Invalid("no states".to_string()));
+        }
+        for (index, state) in self.states.iter().enumerate() {
+            if self.state_index(&state.name) != Some(index) {
+                return Err(ScenarioError::Invalid(format!(
+                    "duplicate state {:?}",
+                    state.name
+                )));
+            }
+            if state.frequency.is_nan() || state.frequency <= 0.0 {
+                return Err(ScenarioError::Invalid(format!(
+                    "state {:?} frequency must be positive",
+                    state.name
+                )));
+            }
+            if state
+                .duration
+                .is_some_and(|duration| duration.is_nan() || duration < 0.0)
+            {
+                return Err(ScenarioError::Invalid(format!(
+                    "state {:?} duration must not be negative",
+                    state.name
+                )));
+            }
+            if let Some(next) = &state.next
+                && self.state_index(next).is_none()
+            {
+                return Err(ScenarioError::Invalid(format!(
+                    "state {:?} goes to unknown state {:?}",
+                    state.name, next
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// State after `index`, `None` at the end of the sequence.
+    fn next_state(&self, index: usize) -> Option<usize> {
+        match &self.states[index].next {
+            Some(next) => self.state_index(next),
+            None => (index + 1 < self.states.len()).then_some(index + 1),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Shared {
+    trigger: AtomicBool,
+    state: AtomicUsize,
+}
+
+/// Cloneable handle to a running [`ScenarioPlayer`], usable from any thread.
+#[derive(Clone, Default)]
+pub struct ScenarioControl {
+    shared: Arc<Shared>,
+}
+
+impl ScenarioControl {
+    /// Leaves the current state on the next sample if it waits for an
+    /// external trigger. A trigger is forgotten when a state is entered.
+    pub fn trigger(&self) {
+        self.shared.trigger.store(true, Ordering::Release);
+    }
+
+    /// Index of the state being published.
+    pub fn current_state(&self) -> usize {
+        self.shared.state.load(Ordering::Acquire)
+    }
+
+    fn take_trigger(&self) -> bool {
+        self.shared.trigger.swap(false, Ordering::AcqRel)
+    }
+}
+
+/// Plays a [`Scenario`] as a waveform source. The reference angle runs
+/// continuously across states, so a state change only alters what the new
+/// state defines: magnitudes, angles relative to the reference and
+/// frequency. Transitions happen on the first sample at or after the state
+/// duration.
+pub struct ScenarioPlayer {
+    scenario: Scenario,
+    state: usize,
+    state_start: Option<u64>,
+    last_index: u64,
+    /// Reference angle in cycles, within `[0, 1)`.
+    phase: f64,
+    control: ScenarioControl,
+    scale: DatasetScale,
+}
+
+impl ScenarioPlayer {
+    pub fn new(scenario: Scenario) -> Result<Self, ScenarioError> {
+        scenario.validate()?;
+        Ok(ScenarioPlayer {
+            scenario,
+            state: 0,
+            state_start: None,
+            last_index: 0,
+            phase: 0.0,
+            control: ScenarioControl::default(),
+            scale: DatasetScale::default(),
+        })
+    }
+
+    pub fn control(&self) -> ScenarioControl {
+        self.control.clone()
+    }
+
+    pub fn scenario(&self) -> &Scenario {
+        &self.scenario
+    }
+
+    pub fn current_state(&self) -> &State {
+        &self.scenario.states[self.state]
+    }
+
+    /// Scales used to encode the phasor values, 9-2LE by default.
+    pub fn set_scale(&mut self, scale: DatasetScale) {
+        self.scale = scale;
+    }
+
+    fn enter(&mut self, state: usize, index: u64) {
+        self.state = state;
+        self.state_start = Some(index);
+        self.control.shared.trigger.store(false, Ordering::Release);
+        self.control.shared.state.store(state, Ordering::Release);
+    }
+
+    /// Moves the reference angle and the state machine to sample `index`.
+    fn seek(&mut self, index: u64, sample_rate: u32) {
+        let Some(state_start) = self.state_start else {
+            self.last_index = index;
+            self.enter(self.state, index);
+            return;
+        };
+        let elapsed = index.saturating_sub(self.last_index) as f64 / sample_rate as f64;
+        self.phase = (self.phase + self.current_state().frequency * elapsed).fract();
+        self.last_index = index;
+
+        let mut start = state_start;
+        // Zero length states are crossed in one sample, bounded so a cycle of
+        // them cannot loop forever.
+        for _ in 0..self.scenario.states.len() {
+            let state = self.current_state();
+            let timed_out = state.duration.is_some_and(|duration| {
+                index - start >= (duration * sample_rate as f64).round() as u64
+            });
+            let triggered = state.external_trigger && self.control.take_trigger();
+            if !(timed_out || triggered) {
+                break;
+            }
+            match self.scenario.next_state(self.state) {
+                Some(next) => {
+                    self.enter(next, index);
+                    start = index;
+                }
+                None => break,
+            }
+        }
+    }
+
+    fn value(&self, channel: Channel) -> f64 {
+        match self.current_state().phasors.get(&channel) {
+            Some(phasor) => {
+                phasor.magnitude
+                    * SQRT_2
+                    * (2.0 * PI * self.phase + phasor.angle.to_radians()).cos()
+            }
+            None => Channel::phases_of(channel)
+                .iter()
+                .map(|phase| self.value(*phase))
+                .sum(),
+        }
+    }
+}
+
+impl WaveformSource for ScenarioPlayer {
+    fn sample(&mut self, index: u64, sample_rate: u32, measures: &mut Phases) {
+        self.seek(index, sample_rate);
+        let state = self.current_state();
+        for channel in Channel::ALL {
+            let quality = state
+                .quality
+                .get(&channel)
+                .map_or(0, |quality| quality.apply(0));
+            channel.measurement_mut(measures).quality = quality;
+            self.scale.set_value(channel, measures, self.value(channel));
+        }
+    }
+
+    fn simulation(&self) -> Option<bool> {
+        Some(self.current_state().simulation)
+    }
+
+    /// Phasor and frequency changes edit the current state, so they also
+    /// hold when the sequence comes back to it. A state change enters the
+    /// named state as if its predecessor had ended.
+    fn adjust(&mut self, change: &WaveformChange, index: u64, sample_rate: u32) -> io::Result<()> {
+        self.seek(index, sample_rate);
+        match change {
+            WaveformChange::Phasor {
+                channel,
+                magnitude,
+                angle,
+            } => {
+                let phasor = self.scenario.states[self.state]
+                    .phasors
+                    .entry(*channel)
+                    .or_default();
+                phasor.magnitude = magnitude.unwrap_or(phasor.magnitude);
+                phasor.angle = angle.unwrap_or(phasor.angle);
+            }
+            WaveformChange::Frequency(frequency) => {
+                check_frequency(*frequency)?;
+                self.scenario.states[self.state].frequency = *frequency;
+            }
+            WaveformChange::State(name) => {
+                let state = self.scenario.state_index(name).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("No scenario state named {:?}", name),
+                    )
+                })?;
+                self.enter(state, index);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENARIO: &str = r#"
+        [[states]]
+        name = "prefault"
+        duration = 0.01
+        phasors.Va = { magnitude = 100.0 }
+
+        [[states]]
+        name = "fault"
+        external_trigger = true
+        phasors.Va = { magnitude = 50.0 }
+        phasors.Ia = { magnitude = 10.0, angle = -90.0 }
+        simulation = true
+        quality.Ia = { validity = "invalid", test = true }
+
+        [[states]]
+        name = "postfault"
+        frequency = 60.0
+        phasors.Va = { magnitude = 100.0 }
+    "#;
+
+    fn va(player: &mut ScenarioPlayer, index: u64) -> i32 {
+        let mut measures = Phases::default();
+        player.sample(index, 4000, &mut measures);
+        measures.voltage.a.value
+    }
+
+    #[test]
+    fn load_toml_and_json() {
+        let scenario = Scenario::from_toml(SCENARIO).unwrap();
+        assert_eq!(scenario.states.len(), 3);
+        assert_eq!(scenario.states[0].frequency, 50.0);
+        assert_eq!(scenario.states[1].phasors[&Channel::Ia].angle, -90.0);
+
+        let json = serde_json::to_string(&scenario).unwrap();
+        assert_eq!(Scenario::from_json(&json).unwrap(), scenario);
+
+        let invalid = r#"{"states": [{"name": "a", "next": "b"}]}"#;
+        assert!(matches!(
+            Scenario::from_json(invalid),
+            Err(ScenarioError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn sample_exact_transition_with_continuous_phase() {
+        let mut player = ScenarioPlayer::new(Scenario::from_toml(SCENARIO).unwrap()).unwrap();
+        let control = player.control();
+        let peak = 100.0 * SQRT_2 / 0.01;
+
+        assert_eq!(va(&mut player, 0), peak.round() as i32);
+        for index in 1..40 {
+            va(&mut player, index);
+            assert_eq!(control.current_state(), 0);
+        }
+        // 0.01 s at 4000 Hz is 40 samples, half a 50 Hz cycle: the fault
+        // starts at the reference angle reached, not at zero.
+        assert_eq!(va(&mut player, 40), -(peak / 2.0).round() as i32);
+        assert_eq!(control.current_state(), 1);
+
+        // The fault waits for the external trigger.
+        for index in 41..400 {
+            va(&mut player, index);
+        }
+        assert_eq!(control.current_state(), 1);
+        control.trigger();
+        va(&mut player, 400);
+        assert_eq!(control.current_state(), 2);
+        assert_eq!(player.current_state().name, "postfault");
+    }
+
+    #[test]
+    fn frequency_change_keeps_phase() {
+        let mut player = ScenarioPlayer::new(Scenario::from_toml(SCENARIO).unwrap()).unwrap();
+        let control = player.control();
+        for index in 0..41 {
+            va(&mut player, index);
+        }
+        control.trigger();
+        va(&mut player, 41);
+        // Sample 41 is 41 samples of 50 Hz after the start, then the 60 Hz
+        // state advances 60 / 4000 cycles per sample.
+        let phase = 41.0 * 50.0 / 4000.0 + 60.0 / 4000.0;
+        let expected = 100.0 * SQRT_2 * (2.0 * PI * phase).cos() / 0.01;
+        assert_eq!(va(&mut player, 42), expected.round() as i32);
+    }
+
+    #[test]
+    fn states_schedule_simulation_and_quality() {
+        use crate::protocols::sampled_values::quality::{TEST, VALIDITY_INVALID};
+
+        let mut player = ScenarioPlayer::new(Scenario::from_toml(SCENARIO).unwrap()).unwrap();
+        let mut measures = Phases::default();
+        player.sample(0, 4000, &mut measures);
+        assert_eq!(player.simulation(), Some(false));
+        assert_eq!(measures.current.a.quality, 0);
+
+        player.sample(40, 4000, &mut measures);
+        assert_eq!(player.simulation(), Some(true));
+        assert_eq!(measures.current.a.quality, VALIDITY_INVALID | TEST);
+        assert_eq!(measures.voltage.a.quality, 0);
+
+        player.control().trigger();
+        player.sample(41, 4000, &mut measures);
+        assert_eq!(player.simulation(), Some(false));
+        assert_eq!(measures.current.a.quality, 0);
+    }
+
+    #[test]
+    fn live_state_and_phasor_changes() {
+        let mut player = ScenarioPlayer::new(Scenario::from_toml(SCENARIO).unwrap()).unwrap();
+        let control = player.control();
+        va(&mut player, 0);
+        let fault = WaveformChange::State("fault".to_string());
+        player.adjust(&fault, 10, 4000).unwrap();
+        assert_eq!(control.current_state(), 1);
+        // The reference angle keeps turning: 10 samples are 1/8 cycle.
+        let expected = 50.0 * SQRT_2 * (PI / 4.0).cos() / 0.01;
+        assert_eq!(va(&mut player, 10), expected.round() as i32);
+
+        let change = WaveformChange::Phasor {
+            channel: Channel::Va,
+            magnitude: None,
+            angle: Some(90.0),
+        };
+        player.adjust(&change, 20, 4000).unwrap();
+        let expected = 50.0 * SQRT_2 * (PI / 2.0 + PI / 2.0).cos() / 0.01;
+        assert_eq!(va(&mut player, 20), expected.round() as i32);
+
+        let unknown = WaveformChange::State("reclose".to_string());
+        assert!(player.adjust(&unknown, 21, 4000).is_err());
+        assert!(
+            player
+                .adjust(&WaveformChange::Frequency(-1.0), 21, 4000)
+                .is_err()
+        );
+    }
+}
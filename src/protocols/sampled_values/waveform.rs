@@ -0,0 +1,739 @@
+use std::f64::consts::{PI, SQRT_2};
+use std::io;
+
+use crate::protocols::sampled_values::frequency::{
+    FrequencyChange, FrequencyProfile, wrap_degrees,
+};
+use crate::protocols::sampled_values::model::{PhaseMeasurement, Phases};
+use crate::protocols::sampled_values::scaling::ChannelScale;
+use crate::random::SplitMix64;
+
+/// Produces the measurements carried by each ASDU. `index` counts samples
+/// since the stream started, so `index / sample_rate` is elapsed seconds.
+pub trait WaveformSource: Send {
+    fn sample(&mut self, index: u64, sample_rate: u32, measures: &mut Phases);
+
+    /// Simulation flag the source asks for after its last sample, `None`
+    /// to leave it to the publisher.
+    fn simulation(&self) -> Option<bool> {
+        None
+    }
+
+    /// Applies `change` from sample `index` on, before that sample is
+    /// produced. Sources that cannot make the change fail with
+    /// `io::ErrorKind::Unsupported`.
+    fn adjust(&mut self, change: &WaveformChange, index: u64, sample_rate: u32) -> io::Result<()> {
+        let _ = (index, sample_rate);
+        Err(unsupported(change))
+    }
+}
+
+/// Live change to a running [`WaveformSource`].
+#[derive(Clone, Debug, PartialEq)]
+pub enum WaveformChange {
+    /// Fundamental RMS magnitude in A or V and angle in degrees of `channel`,
+    /// each kept when `None`.
+    Phasor {
+        channel: Channel,
+        magnitude: Option<f64>,
+        angle: Option<f64>,
+    },
+    /// Fundamental frequency of every channel in Hz, with a continuous phase.
+    Frequency(f64),
+    /// Moves a scenario to the state with this name.
+    State(String),
+}
+
+pub(crate) fn unsupported(change: &WaveformChange) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!("Waveform source does not support {:?}", change),
+    )
+}
+
+pub(crate) fn check_frequency(frequency: f64) -> io::Result<()> {
+    if frequency.is_finite() && frequency > 0.0 {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid frequency {}", frequency),
+        ))
+    }
+}
+
+impl<F> WaveformSource for F
+where
+    F: FnMut(u64, u32, &mut Phases) + Send,
+{
+    fn sample(&mut self, index: u64, sample_rate: u32, measures: &mut Phases) {
+        self(index, sample_rate, measures)
+    }
+}
+
+/// Publishes the same measurements on every sample.
+#[derive(PartialEq, Debug, Default, Clone, Copy)]
+pub struct Constant(pub Phases);
+
+impl WaveformSource for Constant {
+    fn sample(&mut self, _index: u64, _sample_rate: u32, measures: &mut Phases) {
+        *measures = self.0;
+    }
+}
+
+/// The eight channels of the 9-2LE dataset, in dataset order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Channel {
+    Ia,
+    Ib,
+    Ic,
+    In,
+    Va,
+    Vb,
+    Vc,
+    Vn,
+}
+
+impl Channel {
+    pub const ALL: [Channel; 8] = [
+        Channel::Ia,
+        Channel::Ib,
+        Channel::Ic,
+        Channel::In,
+        Channel::Va,
+        Channel::Vb,
+        Channel::Vc,
+        Channel::Vn,
+    ];
+
+    pub fn is_current(self) -> bool {
+        matches!(self, Channel::Ia | Channel::Ib | Channel::Ic | Channel::In)
+    }
+
+    pub fn is_neutral(self) -> bool {
+        matches!(self, Channel::In | Channel::Vn)
+    }
+
+    /// Phases A, B and C summed into a neutral channel.
+    pub fn phases_of(neutral: Channel) -> &'static [Channel] {
+        match neutral {
+            Channel::In => &Channel::ALL[0..3],
+            Channel::Vn => &Channel::ALL[4..7],
+            _ => &[],
+        }
+    }
+
+    /// Position in the dataset, 0 for Ia to 7 for Vn.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Name of the channel in the 9-2LE PhsMeas1 dataset.
+    pub fn dataset_member(self) -> &'static str {
+        match self {
+            Channel::Ia => "IATCTR1.Amp",
+            Channel::Ib => "IBTCTR2.Amp",
+            Channel::Ic => "ICTCTR3.Amp",
+            Channel::In => "INTCTR4.Amp",
+            Channel::Va => "UATVTR1.Vol",
+            Channel::Vb => "UBTVTR2.Vol",
+            Channel::Vc => "UCTVTR3.Vol",
+            Channel::Vn => "UNTVTR4.Vol",
+        }
+    }
+
+    /// 9-2LE scale in engineering units per LSB: 1 mA and 10 mV. See
+    /// [`ChannelScale`] to convert values.
+    pub fn default_scale(self) -> f64 {
+        if self.is_current() { 0.001 } else { 0.01 }
+    }
+
+    pub fn measurement(self, phases: &Phases) -> &PhaseMeasurement {
+        match self {
+            Channel::Ia => &phases.current.a,
+            Channel::Ib => &phases.current.b,
+            Channel::Ic => &phases.current.c,
+            Channel::In => &phases.current.n,
+            Channel::Va => &phases.voltage.a,
+            Channel::Vb => &phases.voltage.b,
+            Channel::Vc => &phases.voltage.c,
+            Channel::Vn => &phases.voltage.n,
+        }
+    }
+
+    pub fn measurement_mut(self, phases: &mut Phases) -> &mut PhaseMeasurement {
+        match self {
+            Channel::Ia => &mut phases.current.a,
+            Channel::Ib => &mut phases.current.b,
+            Channel::Ic => &mut phases.current.c,
+            Channel::In => &mut phases.current.n,
+            Channel::Va => &mut phases.voltage.a,
+            Channel::Vb => &mut phases.voltage.b,
+            Channel::Vc => &mut phases.voltage.c,
+            Channel::Vn => &mut phases.voltage.n,
+        }
+    }
+}
+
+/// One sinusoidal channel: `rms * √2 * cos(2π * frequency * t + phase)`,
+/// with `rms` in A or V, `phase` in degrees and `scale` in A or V per LSB.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Sinusoid {
+    pub rms: f64,
+    pub frequency: f64,
+    pub phase: f64,
+    pub scale: f64,
+}
+
+impl Sinusoid {
+    /// Instantaneous value in engineering units at `time` seconds.
+    pub fn value(&self, time: f64) -> f64 {
+        // Whole cycles are dropped before converting to radians, so the
+        // angle stays precise however long the stream has been running.
+        self.value_at_cycles((self.frequency * time).fract())
+    }
+
+    /// Instantaneous value once the reference has turned `cycles` cycles,
+    /// for frequencies that change over time.
+    pub fn value_at_cycles(&self, cycles: f64) -> f64 {
+        self.rms * SQRT_2 * (2.0 * PI * cycles.fract() + self.phase.to_radians()).cos()
+    }
+}
+
+/// Harmonic of the channel fundamental. `magnitude` is relative to the
+/// fundamental RMS and `angle` in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Harmonic {
+    pub order: u32,
+    pub magnitude: f64,
+    pub angle: f64,
+}
+
+/// Component at any `frequency` in Hz, typically between two harmonics.
+/// `magnitude` is relative to the fundamental RMS and `angle` in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Interharmonic {
+    pub frequency: f64,
+    pub magnitude: f64,
+    pub angle: f64,
+}
+
+/// Exponentially decaying DC offset, as after a fault inception: `initial`
+/// A or V at `start` seconds, decaying with `time_constant` seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DecayingDc {
+    pub initial: f64,
+    pub time_constant: f64,
+    pub start: f64,
+}
+
+impl DecayingDc {
+    pub fn value(&self, time: f64) -> f64 {
+        if time < self.start {
+            0.0
+        } else if self.time_constant <= 0.0 {
+            self.initial
+        } else {
+            self.initial * (-(time - self.start) / self.time_constant).exp()
+        }
+    }
+}
+
+/// Everything added on top of a channel fundamental.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Distortion {
+    pub harmonics: Vec<Harmonic>,
+    pub interharmonics: Vec<Interharmonic>,
+    pub dc_offset: Option<DecayingDc>,
+    /// Standard deviation of the Gaussian noise, in A or V.
+    pub noise: f64,
+}
+
+/// Stateless sinusoidal generator: every sample is computed from its index
+/// alone, so long runs never drift. Neutral channels are the residual sum of
+/// their three phases unless given a sinusoid of their own.
+///
+/// With a [`FrequencyProfile`], every fundamental and harmonic follows its
+/// frequency ramps and phase jumps instead of the fixed channel frequency.
+///
+/// Each channel can carry harmonics, interharmonics, a decaying DC offset
+/// and Gaussian noise. The noise of a sample only depends on the seed, the
+/// channel and the sample index, so a seed always reproduces the same
+/// stream.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SineGenerator {
+    channels: [Option<Sinusoid>; 8],
+    distortion: [Distortion; 8],
+    frequency: Option<FrequencyProfile>,
+    seed: u64,
+}
+
+impl Default for SineGenerator {
+    /// Balanced 50 Hz set of 1 A and 63.5 V.
+    fn default() -> Self {
+        Self::balanced(50.0, 1.0, 63.5)
+    }
+}
+
+impl SineGenerator {
+    /// Phases A, B and C at 0°, -120° and +120°, currents in phase with
+    /// the voltages and residual neutrals.
+    pub fn balanced(frequency: f64, current_rms: f64, voltage_rms: f64) -> Self {
+        let mut generator = SineGenerator {
+            channels: [None; 8],
+            distortion: Default::default(),
+            frequency: None,
+            seed: 0,
+        };
+        let phases = [(0, 0.0), (1, -120.0), (2, 120.0)];
+        for (offset, phase) in phases {
+            for (channel, rms) in [
+                (Channel::ALL[offset], current_rms),
+                (Channel::ALL[offset + 4], voltage_rms),
+            ] {
+                generator.set(
+                    channel,
+                    Some(Sinusoid {
+                        rms,
+                        frequency,
+                        phase,
+                        scale: channel.default_scale(),
+                    }),
+                );
+            }
+        }
+        generator
+    }
+
+    /// Sets `channel`, `None` makes a neutral residual and a phase zero.
+    pub fn set(&mut self, channel: Channel, sinusoid: Option<Sinusoid>) {
+        self.channels[channel.index()] = sinusoid;
+    }
+
+    pub fn with(mut self, channel: Channel, sinusoid: Option<Sinusoid>) -> Self {
+        self.set(channel, sinusoid);
+        self
+    }
+
+    pub fn get(&self, channel: Channel) -> Option<&Sinusoid> {
+        self.channels[channel.index()].as_ref()
+    }
+
+    pub fn get_mut(&mut self, channel: Channel) -> Option<&mut Sinusoid> {
+        self.channels[channel.index()].as_mut()
+    }
+
+    pub fn distortion(&self, channel: Channel) -> &Distortion {
+        &self.distortion[channel.index()]
+    }
+
+    pub fn distortion_mut(&mut self, channel: Channel) -> &mut Distortion {
+        &mut self.distortion[channel.index()]
+    }
+
+    pub fn add_harmonic(&mut self, channel: Channel, harmonic: Harmonic) {
+        self.distortion_mut(channel).harmonics.push(harmonic);
+    }
+
+    pub fn add_interharmonic(&mut self, channel: Channel, interharmonic: Interharmonic) {
+        self.distortion_mut(channel)
+            .interharmonics
+            .push(interharmonic);
+    }
+
+    pub fn set_dc_offset(&mut self, channel: Channel, dc_offset: Option<DecayingDc>) {
+        self.distortion_mut(channel).dc_offset = dc_offset;
+    }
+
+    pub fn set_noise(&mut self, channel: Channel, noise: f64) {
+        self.distortion_mut(channel).noise = noise;
+    }
+
+    /// Drives every fundamental from `profile`, or from the channel
+    /// frequencies for `None`.
+    pub fn set_frequency_profile(&mut self, profile: Option<FrequencyProfile>) {
+        self.frequency = profile;
+    }
+
+    pub fn frequency_profile(&self) -> Option<&FrequencyProfile> {
+        self.frequency.as_ref()
+    }
+
+    /// True instantaneous frequency of `channel` at `time` seconds.
+    pub fn frequency(&self, channel: Channel, time: f64) -> Option<f64> {
+        let sinusoid = self.get(channel)?;
+        Some(match &self.frequency {
+            Some(profile) => profile.frequency(time),
+            None => sinusoid.frequency,
+        })
+    }
+
+    /// True angle of `channel` at `time` seconds in degrees, against a
+    /// reference at the nominal frequency of the profile. Without a profile
+    /// the reference runs at the channel frequency, so this is its phase.
+    pub fn angle(&self, channel: Channel, time: f64) -> Option<f64> {
+        let sinusoid = self.get(channel)?;
+        Some(match &self.frequency {
+            Some(profile) => wrap_degrees(profile.angle(time) + sinusoid.phase),
+            None => wrap_degrees(sinusoid.phase),
+        })
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Engineering value of `channel` at `time` seconds, without noise.
+    pub fn value(&self, channel: Channel, time: f64) -> f64 {
+        let distortion = self.distortion(channel);
+        let dc = distortion.dc_offset.map_or(0.0, |dc| dc.value(time));
+        match self.get(channel) {
+            Some(sinusoid) => {
+                let cycles = match &self.frequency {
+                    Some(profile) => profile.cycles(time),
+                    None => (sinusoid.frequency * time).fract(),
+                };
+                let harmonics = distortion.harmonics.iter().map(|harmonic| {
+                    Sinusoid {
+                        rms: sinusoid.rms * harmonic.magnitude,
+                        phase: harmonic.angle,
+                        ..*sinusoid
+                    }
+                    .value_at_cycles(cycles * harmonic.order as f64)
+                });
+                let interharmonics = distortion.interharmonics.iter().map(|component| {
+                    Sinusoid {
+                        rms: sinusoid.rms * component.magnitude,
+                        frequency: component.frequency,
+                        phase: component.angle,
+                        scale: sinusoid.scale,
+                    }
+                    .value(time)
+                });
+                sinusoid.value_at_cycles(cycles) + harmonics.chain(interharmonics).sum::<f64>() + dc
+            }
+            None if channel.is_neutral() => {
+                Channel::phases_of(channel)
+                    .iter()
+                    .map(|phase| self.value(*phase, time))
+                    .sum::<f64>()
+                    + dc
+            }
+            None => dc,
+        }
+    }
+
+    /// Engineering value of `channel` for sample `index`, noise included.
+    pub fn value_at(&self, channel: Channel, index: u64, sample_rate: u32) -> f64 {
+        let time = index as f64 / sample_rate as f64;
+        let noise = self.noise_at(channel, index);
+        match self.get(channel) {
+            None if channel.is_neutral() => {
+                let dc = self
+                    .distortion(channel)
+                    .dc_offset
+                    .map_or(0.0, |dc| dc.value(time));
+                Channel::phases_of(channel)
+                    .iter()
+                    .map(|phase| self.value_at(*phase, index, sample_rate))
+                    .sum::<f64>()
+                    + dc
+                    + noise
+            }
+            _ => self.value(channel, time) + noise,
+        }
+    }
+
+    fn noise_at(&self, channel: Channel, index: u64) -> f64 {
+        let noise = self.distortion(channel).noise;
+        if noise == 0.0 {
+            return 0.0;
+        }
+        let key = index.wrapping_mul(Channel::ALL.len() as u64) + channel.index() as u64;
+        SplitMix64::at(self.seed, key).gaussian() * noise
+    }
+
+    /// Engineering units per LSB of `channel`. A residual neutral uses the
+    /// scale of phase A.
+    pub fn scale(&self, channel: Channel) -> f64 {
+        match self.get(channel) {
+            Some(sinusoid) => sinusoid.scale,
+            None if channel.is_neutral() => self
+                .get(Channel::ALL[channel.index() - 3])
+                .map_or(channel.default_scale(), |sinusoid| sinusoid.scale),
+            None => channel.default_scale(),
+        }
+    }
+}
+
+impl WaveformSource for SineGenerator {
+    fn sample(&mut self, index: u64, sample_rate: u32, measures: &mut Phases) {
+        for channel in Channel::ALL {
+            let value = self.value_at(channel, index, sample_rate);
+            ChannelScale::new(self.scale(channel))
+                .set_value(channel.measurement_mut(measures), value);
+        }
+    }
+
+    /// A frequency change becomes a step of the frequency profile, created
+    /// at the frequency of the first channel if there is none yet. Changes
+    /// that already ended are folded, so the profile does not grow.
+    fn adjust(&mut self, change: &WaveformChange, index: u64, sample_rate: u32) -> io::Result<()> {
+        let fundamental = self
+            .channels
+            .iter()
+            .flatten()
+            .next()
+            .map_or(50.0, |sinusoid| sinusoid.frequency);
+        match *change {
+            WaveformChange::Phasor {
+                channel,
+                magnitude,
+                angle,
+            } => {
+                let scale = self.scale(channel);
+                let sinusoid = self.channels[channel.index()].get_or_insert(Sinusoid {
+                    rms: 0.0,
+                    frequency: fundamental,
+                    phase: 0.0,
+                    scale,
+                });
+                sinusoid.rms = magnitude.unwrap_or(sinusoid.rms);
+                sinusoid.phase = angle.unwrap_or(sinusoid.phase);
+                Ok(())
+            }
+            WaveformChange::Frequency(frequency) => {
+                check_frequency(frequency)?;
+                let start = index as f64 / sample_rate as f64;
+                let profile = self
+                    .frequency
+                    .get_or_insert_with(|| FrequencyProfile::constant(fundamental));
+                profile.fold_until(start);
+                profile.add_change(FrequencyChange::Step { start, frequency });
+                Ok(())
+            }
+            WaveformChange::State(_) => Err(unsupported(change)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closure_source() {
+        let mut source = |index: u64, _sample_rate: u32, measures: &mut Phases| {
+            measures.voltage.a.value = index as i32 * 10;
+        };
+        let mut measures = Phases::default();
+        source.sample(3, 4800, &mut measures);
+        assert_eq!(measures.voltage.a.value, 30);
+        assert_eq!(measures.current, Phases::default().current);
+    }
+
+    #[test]
+    fn balanced_set() {
+        let mut generator = SineGenerator::balanced(50.0, 100.0, 230.0);
+        let mut measures = Phases::default();
+        generator.sample(0, 4000, &mut measures);
+
+        let peak = 230.0 * SQRT_2 / 0.01;
+        assert_eq!(measures.voltage.a.value, peak.round() as i32);
+        assert_eq!(measures.voltage.b.value, (peak * -0.5).round() as i32);
+        assert_eq!(measures.voltage.c.value, (peak * -0.5).round() as i32);
+        assert_eq!(measures.voltage.n.value, 0);
+        assert_eq!(measures.current.a.value, 141_421);
+        assert_eq!(measures.current.n.value, 0);
+
+        // A quarter cycle later phase A crosses zero and B and C are opposite.
+        generator.sample(20, 4000, &mut measures);
+        assert_eq!(measures.voltage.a.value, 0);
+        assert_eq!(measures.voltage.b.value, -measures.voltage.c.value);
+    }
+
+    #[test]
+    fn neutral_is_residual_sum() {
+        let mut generator = SineGenerator::default().with(Channel::Ib, None);
+        let mut measures = Phases::default();
+        for index in 0..80 {
+            generator.sample(index, 4000, &mut measures);
+            let residual = measures.current.a.value + measures.current.c.value;
+            assert!((measures.current.n.value - residual).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn harmonics_and_decaying_dc() {
+        let mut generator = SineGenerator::balanced(50.0, 100.0, 0.0);
+        generator.add_harmonic(
+            Channel::Ia,
+            Harmonic {
+                order: 2,
+                magnitude: 0.2,
+                angle: 90.0,
+            },
+        );
+        generator.set_dc_offset(
+            Channel::Ia,
+            Some(DecayingDc {
+                initial: 50.0,
+                time_constant: 0.04,
+                start: 0.01,
+            }),
+        );
+        let peak = 100.0 * SQRT_2;
+        // At t = 0 the second harmonic is at 90° and the DC has not started.
+        assert!((generator.value(Channel::Ia, 0.0) - peak).abs() < 1e-9);
+        // One time constant after the start the offset has decayed to 1/e.
+        let time = 0.05;
+        let expected = peak * (2.0 * PI * 2.5).cos()
+            + 0.2 * peak * (2.0 * PI * 5.0 + PI / 2.0).cos()
+            + 50.0 / std::f64::consts::E;
+        assert!((generator.value(Channel::Ia, time) - expected).abs() < 1e-9);
+        // The residual neutral carries the distortion of phase A.
+        let neutral = generator.value(Channel::In, time);
+        assert!((neutral - (expected - peak * (2.0 * PI * 2.5).cos())).abs() < 1e-9);
+    }
+
+    #[test]
+    fn interharmonic_component() {
+        let mut generator = SineGenerator::balanced(50.0, 0.0, 10.0);
+        generator.add_interharmonic(
+            Channel::Vb,
+            Interharmonic {
+                frequency: 175.0,
+                magnitude: 0.5,
+                angle: 0.0,
+            },
+        );
+        let time = 1.0 / 700.0;
+        let fundamental = 10.0 * SQRT_2 * (2.0 * PI * 50.0 * time - 2.0 * PI / 3.0).cos();
+        let expected = fundamental + 5.0 * SQRT_2 * (PI / 2.0).cos();
+        assert!((generator.value(Channel::Vb, time) - expected).abs() < 1e-9);
+    }
+
+    #[test]
+    fn seeded_noise_is_reproducible() {
+        let mut generator = SineGenerator::default();
+        generator.set_noise(Channel::Va, 0.5);
+        generator.set_seed(1234);
+        let mut other = generator.clone();
+
+        let (mut first, mut second) = (Phases::default(), Phases::default());
+        let mut differs = false;
+        for index in 0..100 {
+            generator.sample(index, 4800, &mut first);
+            other.sample(index, 4800, &mut second);
+            assert_eq!(first, second);
+            let clean = (generator.value(Channel::Va, index as f64 / 4800.0) / 0.01).round();
+            differs |= first.voltage.a.value != clean as i32;
+        }
+        assert!(differs);
+
+        other.set_seed(4321);
+        generator.sample(5, 4800, &mut first);
+        other.sample(5, 4800, &mut second);
+        assert_ne!(first.voltage.a, second.voltage.a);
+        assert_eq!(first.voltage.b, second.voltage.b);
+    }
+
+    #[test]
+    fn stateless_per_index() {
+        let mut generator = SineGenerator::default();
+        let (mut first, mut second) = (Phases::default(), Phases::default());
+        // One hour of 4800 Hz samples is a whole number of 50 Hz cycles.
+        generator.sample(7, 4800, &mut first);
+        generator.sample(7 + 4800 * 3600, 4800, &mut second);
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn frequency_ramp_and_phase_jump() {
+        use crate::protocols::sampled_values::frequency::PhaseJump;
+
+        let mut generator = SineGenerator::balanced(50.0, 1.0, 100.0);
+        generator.add_harmonic(
+            Channel::Va,
+            Harmonic {
+                order: 3,
+                magnitude: 0.1,
+                angle: 0.0,
+            },
+        );
+        generator.set_frequency_profile(Some(
+            FrequencyProfile::constant(50.0)
+                .with_change(FrequencyChange::Ramp {
+                    start: 0.1,
+                    rate: 5.0,
+                    frequency: 51.0,
+                })
+                .with_phase_jump(PhaseJump {
+                    time: 0.5,
+                    angle: -20.0,
+                }),
+        ));
+        assert_eq!(generator.frequency(Channel::Va, 0.2), Some(50.5));
+        assert_eq!(generator.frequency(Channel::In, 0.2), None);
+
+        // Ramping 50 to 51 Hz over 0.2 s gains 0.1 cycles, then 1 Hz slip.
+        let angle = generator.angle(Channel::Vb, 0.4).unwrap();
+        assert!((angle - (-120.0 + 36.0 + 36.0)).abs() < 1e-6);
+        let angle = generator.angle(Channel::Va, 0.5).unwrap();
+        assert!((angle - (36.0 + 72.0 - 20.0)).abs() < 1e-6);
+
+        // No step in the waveform through the ramp: at most the slope of a
+        // 100 V, 50 Hz sine (plus its third harmonic) between samples.
+        let max_step = 100.0 * SQRT_2 * 2.0 * PI * 51.0 * 1.3 / 4800.0;
+        for index in 400..2000 {
+            let step = generator.value_at(Channel::Va, index + 1, 4800)
+                - generator.value_at(Channel::Va, index, 4800);
+            assert!(step.abs() < max_step);
+        }
+    }
+
+    #[test]
+    fn live_changes_keep_phase() {
+        let mut generator = SineGenerator::balanced(50.0, 1.0, 100.0);
+        let before = generator.value_at(Channel::Va, 480, 4800);
+        generator
+            .adjust(&WaveformChange::Frequency(55.0), 480, 4800)
+            .unwrap();
+        assert_eq!(generator.value_at(Channel::Va, 480, 4800), before);
+        assert_eq!(generator.frequency(Channel::Ia, 0.2), Some(55.0));
+
+        let change = WaveformChange::Phasor {
+            channel: Channel::Ia,
+            magnitude: Some(5.0),
+            angle: None,
+        };
+        generator.adjust(&change, 960, 4800).unwrap();
+        assert_eq!(generator.get(Channel::Ia).unwrap().rms, 5.0);
+        assert_eq!(generator.get(Channel::Ia).unwrap().phase, 0.0);
+        assert!(
+            generator
+                .adjust(&WaveformChange::Frequency(0.0), 960, 4800)
+                .is_err()
+        );
+        let state = WaveformChange::State("fault".to_string());
+        assert_eq!(
+            generator.adjust(&state, 960, 4800).unwrap_err().kind(),
+            io::ErrorKind::Unsupported
+        );
+
+        // Elapsed changes are folded, so the profile does not grow.
+        for step in 0..100 {
+            let index = 1000 + step * 48;
+            let before = generator.value_at(Channel::Va, index, 4800);
+            let frequency = WaveformChange::Frequency(49.0 + step as f64 * 0.02);
+            generator.adjust(&frequency, index, 4800).unwrap();
+            assert!((generator.value_at(Channel::Va, index, 4800) - before).abs() < 1e-9);
+        }
+        assert_eq!(generator.frequency_profile().unwrap().changes().len(), 2);
+    }
+}
@@ -1,3 +1,5 @@
+use std::io;
+
 use byteorder::{BigEndian, ByteOrder};
 
 pub struct Triplet {
@@ -91,26 +93,59 @@ impl Triplet {
     }
 
     pub fn from_bytes(bytes: &[u8]) -> Triplet {
+        Self::try_from_bytes(bytes).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Decodes the triplet at the start of `bytes`, failing on unsupported
+    /// lengths and on values running past the end of `bytes`.
+    pub fn try_from_bytes(bytes: &[u8]) -> io::Result<Triplet> {
+        if bytes.len() < 2 {
+            return Err(invalid(format!(
+                "ASN.1 triplet truncated to {} bytes",
+                bytes.len()
+            )));
+        }
         let tag = bytes[0];
+        if bytes[1] > 0x84 {
+            return Err(invalid(format!("ASN.1 length too long {}", bytes[1])));
+        }
 
         let extended_length: u8 = if bytes[1] > 0x80 {
             bytes[1] & 0x0F // 0x8X where X is the length
         } else {
             0u8
         };
-
-        let length = Self::extract_length(bytes[1], &bytes[2..6]);
         let start_value = 2 + extended_length as usize;
+        if bytes.len() < start_value {
+            return Err(invalid(format!(
+                "ASN.1 length of tag {:#04x} truncated",
+                tag
+            )));
+        }
+
+        let length = Self::extract_length(bytes[1], &bytes[2..]);
+        if bytes.len() - start_value < length {
+            return Err(invalid(format!(
+                "ASN.1 value of tag {:#04x} needs {} bytes, {} left",
+                tag,
+                length,
+                bytes.len() - start_value
+            )));
+        }
         let value = bytes[start_value..start_value + length].to_vec();
-        Triplet {
+        Ok(Triplet {
             extended_length,
             tag,
             length,
             value,
-        }
+        })
     }
 }
 
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -151,6 +186,19 @@ mod tests {
         assert_eq!(result.extended_length, 1);
     }
 
+    #[test]
+    fn truncated_triplets_are_errors() {
+        // Short form lengths no longer need four bytes after the tag.
+        assert_eq!(
+            Triplet::try_from_bytes(&[0x88, 0x01, 0x02]).unwrap().value,
+            [2]
+        );
+        assert!(Triplet::try_from_bytes(&[0x80]).is_err());
+        assert!(Triplet::try_from_bytes(&[0x80, 0x04, 0x34, 0x30]).is_err());
+        assert!(Triplet::try_from_bytes(&[0xa2, 0x82, 0x01]).is_err());
+        assert!(Triplet::try_from_bytes(&[0xa2, 0x85, 0, 0, 0, 0, 1]).is_err());
+    }
+
     #[test]
     fn test_build_triplet() {
         let triplet = Triplet::build(0x80, vec![0x34, 0x30, 0x30, 0x30]);
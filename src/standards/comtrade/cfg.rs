@@ -0,0 +1,411 @@
+use std::io;
+use std::str::FromStr;
+
+/// Sample encoding of the .dat file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    Ascii,
+    Binary,
+    Binary32,
+    Float32,
+}
+
+impl DataFormat {
+    pub fn name(self) -> &'static str {
+        match self {
+            DataFormat::Ascii => "ASCII",
+            DataFormat::Binary => "BINARY",
+            DataFormat::Binary32 => "BINARY32",
+            DataFormat::Float32 => "FLOAT32",
+        }
+    }
+
+    /// Bytes per analog value in binary formats.
+    pub fn analog_size(self) -> usize {
+        match self {
+            DataFormat::Binary => 2,
+            _ => 4,
+        }
+    }
+}
+
+impl FromStr for DataFormat {
+    type Err = io::Error;
+
+    fn from_str(name: &str) -> io::Result<Self> {
+        match name.trim().to_ascii_uppercase().as_str() {
+            "ASCII" => Ok(DataFormat::Ascii),
+            "BINARY" => Ok(DataFormat::Binary),
+            "BINARY32" => Ok(DataFormat::Binary32),
+            "FLOAT32" => Ok(DataFormat::Float32),
+            _ => Err(invalid(format!("Unknown data file type {:?}", name))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AnalogChannel {
+    pub id: String,
+    pub phase: String,
+    pub circuit: String,
+    pub unit: String,
+    /// Value = `multiplier * sample + offset`, in the units of `unit`.
+    pub multiplier: f64,
+    pub offset: f64,
+    /// Time skew from the start of the sample period, in microseconds.
+    pub skew: f64,
+    pub min: f64,
+    pub max: f64,
+    pub primary: f64,
+    pub secondary: f64,
+    /// Whether `multiplier` and `offset` give primary values.
+    pub is_primary: bool,
+}
+
+impl AnalogChannel {
+    pub fn value(&self, sample: f64) -> f64 {
+        self.multiplier * sample + self.offset
+    }
+
+    /// Factor from the recorded values to primary values.
+    pub fn to_primary(&self) -> f64 {
+        if self.is_primary || self.secondary == 0.0 {
+            1.0
+        } else {
+            self.primary / self.secondary
+        }
+    }
+
+    /// Factor from the recorded values to secondary values.
+    pub fn to_secondary(&self) -> f64 {
+        if !self.is_primary || self.primary == 0.0 {
+            1.0
+        } else {
+            self.secondary / self.primary
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct DigitalChannel {
+    pub id: String,
+    pub phase: String,
+    pub circuit: String,
+    pub normal_state: bool,
+}
+
+/// Contents of a .cfg file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub station_name: String,
+    pub device_id: String,
+    /// 1991 when the file has no revision year.
+    pub revision: u16,
+    pub analog: Vec<AnalogChannel>,
+    pub digital: Vec<DigitalChannel>,
+    pub line_frequency: f64,
+    /// Sample rates in Hz with the number of the last sample at that rate.
+    /// Empty, or a single 0 Hz rate, when only timestamps give the timing.
+    pub sample_rates: Vec<(f64, u64)>,
+    /// First data point, as written in the file (`dd/mm/yyyy,hh:mm:ss.ssssss`).
+    pub start: String,
+    pub trigger: String,
+    pub format: DataFormat,
+    /// Multiplier of the .dat timestamps, which are in microseconds.
+    pub time_multiplier: f64,
+    /// Offset of the timestamps from UTC (2013), e.g. `+1h30` or `-5`.
+    pub time_code: String,
+    pub local_code: String,
+    /// 2013 time quality code of the recording device, a hex digit from `0`
+    /// (clock locked) to `F` (clock failure). Empty when not given.
+    pub time_quality: String,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn number<T: FromStr>(field: Option<&&str>, what: &str) -> io::Result<T> {
+    let field = field.map(|field| field.trim()).unwrap_or_default();
+    field
+        .parse()
+        .map_err(|_| invalid(format!("Invalid {} {:?} in .cfg", what, field)))
+}
+
+fn text(field: Option<&&str>) -> String {
+    field
+        .map(|field| field.trim().to_string())
+        .unwrap_or_default()
+}
+
+impl Config {
+    pub fn parse(cfg: &str) -> io::Result<Self> {
+        let mut lines = cfg.lines().map(|line| line.trim_end_matches('\r'));
+        let mut next_fields = || -> io::Result<Vec<&str>> {
+            lines
+                .next()
+                .map(|line| line.split(',').collect())
+                .ok_or_else(|| invalid("Truncated .cfg file".to_string()))
+        };
+
+        let header = next_fields()?;
+        let revision = match header.get(2).map(|field| field.trim()) {
+            Some(year) if !year.is_empty() => number(Some(&year), "revision year")?,
+            _ => 1991,
+        };
+
+        let counts = next_fields()?;
+        let count = |field: Option<&&str>, suffix: char, what: &str| {
+            let field = field.map(|field| field.trim().trim_end_matches(suffix));
+            number::<usize>(field.as_ref(), what)
+        };
+        let analog_count = count(counts.get(1), 'A', "analog channel count")?;
+        let digital_count = count(counts.get(2), 'D', "digital channel count")?;
+
+        let mut analog = vec![];
+        for _ in 0..analog_count {
+            let fields = next_fields()?;
+            let ps = text(fields.get(12));
+            analog.push(AnalogChannel {
+                id: text(fields.get(1)),
+                phase: text(fields.get(2)),
+                circuit: text(fields.get(3)),
+                unit: text(fields.get(4)),
+                multiplier: number(fields.get(5), "analog multiplier")?,
+                offset: number(fields.get(6), "analog offset")?,
+                skew: number(fields.get(7), "analog skew").unwrap_or(0.0),
+                min: number(fields.get(8), "analog minimum").unwrap_or(f64::MIN),
+                max: number(fields.get(9), "analog maximum").unwrap_or(f64::MAX),
+                primary: number(fields.get(10), "primary ratio").unwrap_or(1.0),
+                secondary: number(fields.get(11), "secondary ratio").unwrap_or(1.0),
+                is_primary: !ps.eq_ignore_ascii_case("s"),
+            });
+        }
+
+        let mut digital = vec![];
+        for _ in 0..digital_count {
+            let fields = next_fields()?;
+            // 1991 digital channels only have an index, a name and a state.
+            let (phase, circuit, state) = if fields.len() <= 3 {
+                (String::new(), String::new(), fields.get(2))
+            } else {
+                (text(fields.get(2)), text(fields.get(3)), fields.get(4))
+            };
+            digital.push(DigitalChannel {
+                id: text(fields.get(1)),
+                phase,
+                circuit,
+                normal_state: number::<u8>(state, "digital normal state").unwrap_or(0) == 1,
+            });
+        }
+
+        let line_frequency = number(next_fields()?.first(), "line frequency")?;
+        let rate_count: usize = number(next_fields()?.first(), "number of sample rates")?;
+        let mut sample_rates = vec![];
+        for _ in 0..rate_count.max(1) {
+            let fields = next_fields()?;
+            sample_rates.push((
+                number(fields.first(), "sample rate")?,
+                number(fields.get(1), "last sample number")?,
+            ));
+        }
+        if rate_count == 0 {
+            sample_rates.clear();
+        }
+
+        let start = next_fields()?.join(",").trim().to_string();
+        let trigger = next_fields()?.join(",").trim().to_string();
+        let format = next_fields()?
+            .first()
+            .copied()
+            .unwrap_or_default()
+            .parse()?;
+        let time_multiplier = lines
+            .next()
+            .and_then(|line| line.trim().parse().ok())
+            .unwrap_or(1.0);
+        let codes: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+        let quality: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+
+        Ok(Config {
+            station_name: text(header.first()),
+            device_id: text(header.get(1)),
+            revision,
+            analog,
+            digital,
+            line_frequency,
+            sample_rates,
+            start,
+            trigger,
+            format,
+            time_multiplier,
+            time_code: text(codes.first()),
+            local_code: text(codes.get(1)),
+            time_quality: text(quality.first()),
+        })
+    }
+
+    /// Time of sample `index` in seconds from the first sample, from the
+    /// sample rates. `None` when the file only has timestamps.
+    pub fn sample_time(&self, index: u64) -> Option<f64> {
+        let mut time = 0.0;
+        let mut first: u64 = 0;
+        for (rate, last) in &self.sample_rates {
+            if *rate <= 0.0 {
+                return None;
+            }
+            // Sample numbers in the file start at 1.
+            if index < *last {
+                return Some(time + (index - first) as f64 / rate);
+            }
+            time += (*last - first) as f64 / rate;
+            first = *last;
+        }
+        None
+    }
+
+    /// Number of samples announced by the sample rates, if any.
+    pub fn sample_count(&self) -> Option<u64> {
+        self.sample_rates.last().map(|(_, last)| *last)
+    }
+
+    /// Writes the configuration as a 2013 .cfg file with CRLF line endings.
+    /// `samples` is the record count announced when there are no sample
+    /// rates and the timestamps give the timing.
+    pub fn to_cfg(&self, samples: usize) -> String {
+        let mut lines = vec![
+            format!("{},{},2013", self.station_name, self.device_id),
+            format!(
+                "{},{}A,{}D",
+                self.analog.len() + self.digital.len(),
+                self.analog.len(),
+                self.digital.len()
+            ),
+        ];
+        for (index, channel) in self.analog.iter().enumerate() {
+            lines.push(format!(
+                "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                index + 1,
+                channel.id,
+                channel.phase,
+                channel.circuit,
+                channel.unit,
+                channel.multiplier,
+                channel.offset,
+                channel.skew,
+                channel.min,
+                channel.max,
+                channel.primary,
+                channel.secondary,
+                if channel.is_primary { "P" } else { "S" }
+            ));
+        }
+        for (index, channel) in self.digital.iter().enumerate() {
+            lines.push(format!(
+                "{},{},{},{},{}",
+                index + 1,
+                channel.id,
+                channel.phase,
+                channel.circuit,
+                channel.normal_state as u8
+            ));
+        }
+        lines.push(self.line_frequency.to_string());
+        lines.push(self.sample_rates.len().to_string());
+        if self.sample_rates.is_empty() {
+            lines.push(format!("0,{}", samples));
+        }
+        for (rate, last) in &self.sample_rates {
+            lines.push(format!("{},{}", rate, last));
+        }
+        lines.push(self.start.clone());
+        lines.push(self.trigger.clone());
+        lines.push(self.format.name().to_string());
+        lines.push(self.time_multiplier.to_string());
+        lines.push(format!("{},{}", self.time_code, self.local_code));
+        if !self.time_quality.is_empty() {
+            // No leap second in the record.
+            lines.push(format!("{},0", self.time_quality));
+        }
+        let mut cfg = lines.join("\r\n");
+        cfg.push_str("\r\n");
+        cfg
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG_1999: &str = "STATION A,RELAY 1,1999\r
+3,2A,1D\r
+1,IA,A,LINE,A,0.01,0,0,-32767,32767,1200,5,P\r
+2,VA,A,LINE,kV,0.001,0,0,-32767,32767,110,0.1,S\r
+1,TRIP,,,0\r
+50\r
+2\r
+1000,10\r
+2000,30\r
+01/02/2020,10:00:00.000000\r
+01/02/2020,10:00:00.010000\r
+BINARY\r
+1\r
+";
+
+    #[test]
+    fn parse_1999_config() {
+        let config = Config::parse(CFG_1999).unwrap();
+        assert_eq!(config.station_name, "STATION A");
+        assert_eq!(config.revision, 1999);
+        assert_eq!(config.analog.len(), 2);
+        assert_eq!(config.analog[0].id, "IA");
+        assert_eq!(config.analog[0].value(100.0), 1.0);
+        assert_eq!(config.analog[0].to_primary(), 1.0);
+        assert_eq!(config.analog[1].to_primary(), 1100.0);
+        assert_eq!(config.digital[0].id, "TRIP");
+        assert_eq!(config.line_frequency, 50.0);
+        assert_eq!(config.format, DataFormat::Binary);
+        assert_eq!(config.sample_count(), Some(30));
+        assert_eq!(config.sample_time(5), Some(0.005));
+        assert_eq!(config.sample_time(20), Some(0.015));
+    }
+
+    #[test]
+    fn parse_1991_config() {
+        let cfg = "OLD,DEV\n1,1A,0D\n1,IA,A,,A,1,0,0,-100,100\n60\n0\n0,4\n\n\nASCII\n";
+        let config = Config::parse(cfg).unwrap();
+        assert_eq!(config.revision, 1991);
+        assert!(config.analog[0].is_primary);
+        assert!(config.sample_rates.is_empty());
+        assert_eq!(config.sample_time(1), None);
+        assert_eq!(config.format, DataFormat::Ascii);
+        assert_eq!(config.time_multiplier, 1.0);
+    }
+
+    #[test]
+    fn huge_counts_are_truncated_files() {
+        for cfg in [
+            "OLD,DEV\n1,99999999999999A,0D\n",
+            "OLD,DEV\n0,0A,0D\n50\n99999999999999\n",
+        ] {
+            let err = Config::parse(cfg).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        }
+    }
+
+    #[test]
+    fn write_and_parse_config() {
+        let mut config = Config::parse(CFG_1999).unwrap();
+        config.revision = 2013;
+        config.format = DataFormat::Binary32;
+        config.time_code = "+1".to_string();
+        config.local_code = "0".to_string();
+        config.time_quality = "0".to_string();
+        assert_eq!(Config::parse(&config.to_cfg(30)).unwrap(), config);
+
+        config.sample_rates.clear();
+        let cfg = config.to_cfg(7);
+        assert!(cfg.contains("\r\n0\r\n0,7\r\n"));
+        assert_eq!(Config::parse(&cfg).unwrap(), config);
+    }
+}
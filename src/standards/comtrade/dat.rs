@@ -0,0 +1,285 @@
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use super::cfg::{Config, DataFormat};
+
+/// Missing analog value in BINARY files.
+pub const MISSING_BINARY: i16 = i16::MIN;
+/// Missing analog value in BINARY32 files.
+pub const MISSING_BINARY32: i32 = i32::MIN;
+/// Missing timestamp in binary files.
+pub const MISSING_TIMESTAMP: u32 = u32::MAX;
+/// Missing analog value in 1999 ASCII files; 2013 leaves the field empty.
+const MISSING_ASCII_1999: f64 = 99999.0;
+
+/// One sample of every channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub number: u32,
+    /// In units of `Config::time_multiplier` microseconds.
+    pub timestamp: Option<u32>,
+    /// Raw samples, before `AnalogChannel::value`. NaN when missing.
+    pub analog: Vec<f64>,
+    pub digital: Vec<bool>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Number of 16-bit words holding `digital` status channels.
+pub fn digital_words(digital: usize) -> usize {
+    digital.div_ceil(16)
+}
+
+/// Size of one binary record for `config`.
+pub fn record_size(config: &Config) -> usize {
+    8 + config.analog.len() * config.format.analog_size() + 2 * digital_words(config.digital.len())
+}
+
+pub fn parse_ascii(config: &Config, dat: &str) -> io::Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for (line_number, line) in dat.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line == "\x1a" {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+        let expected = 2 + config.analog.len() + config.digital.len();
+        if fields.len() < expected {
+            return Err(invalid(format!(
+                "Line {} of .dat has {} fields, expected {}",
+                line_number + 1,
+                fields.len(),
+                expected
+            )));
+        }
+        let field_error = |field: &str| {
+            invalid(format!(
+                "Invalid value {:?} on line {} of .dat",
+                field,
+                line_number + 1
+            ))
+        };
+
+        let number = fields[0].parse().map_err(|_| field_error(fields[0]))?;
+        let timestamp = match fields[1] {
+            "" => None,
+            field => Some(field.parse().map_err(|_| field_error(field))?),
+        };
+        let mut analog = Vec::with_capacity(config.analog.len());
+        for field in &fields[2..2 + config.analog.len()] {
+            let value = match *field {
+                "" => f64::NAN,
+                field => field.parse().map_err(|_| field_error(field))?,
+            };
+            let missing = config.revision < 2013 && value == MISSING_ASCII_1999;
+            analog.push(if missing { f64::NAN } else { value });
+        }
+        let mut digital = Vec::with_capacity(config.digital.len());
+        for field in &fields[2 + config.analog.len()..expected] {
+            digital.push(match *field {
+                "0" => false,
+                "1" => true,
+                field => return Err(field_error(field)),
+            });
+        }
+        records.push(Record {
+            number,
+            timestamp,
+            analog,
+            digital,
+        });
+    }
+    Ok(records)
+}
+
+pub fn parse_binary(config: &Config, dat: &[u8]) -> io::Result<Vec<Record>> {
+    let size = record_size(config);
+    if !dat.len().is_multiple_of(size) {
+        return Err(invalid(format!(
+            ".dat is {} bytes, not a multiple of the {} byte record",
+            dat.len(),
+            size
+        )));
+    }
+    let analog_size = config.format.analog_size();
+
+    let mut records = Vec::with_capacity(dat.len() / size);
+    for record in dat.chunks_exact(size) {
+        let timestamp = LittleEndian::read_u32(&record[4..8]);
+        let mut analog = Vec::with_capacity(config.analog.len());
+        for value in record[8..]
+            .chunks_exact(analog_size)
+            .take(config.analog.len())
+        {
+            analog.push(match config.format {
+                DataFormat::Binary => match LittleEndian::read_i16(value) {
+                    MISSING_BINARY => f64::NAN,
+                    value => value as f64,
+                },
+                DataFormat::Binary32 => match LittleEndian::read_i32(value) {
+                    MISSING_BINARY32 => f64::NAN,
+                    value => value as f64,
+                },
+                DataFormat::Float32 => LittleEndian::read_f32(value) as f64,
+                DataFormat::Ascii => unreachable!(),
+            });
+        }
+        let words = &record[8 + config.analog.len() * analog_size..];
+        let digital = (0..config.digital.len())
+            .map(|bit| LittleEndian::read_u16(&words[bit / 16 * 2..]) & (1 << (bit % 16)) != 0)
+            .collect();
+        records.push(Record {
+            number: LittleEndian::read_u32(&record[0..4]),
+            timestamp: (timestamp != MISSING_TIMESTAMP).then_some(timestamp),
+            analog,
+            digital,
+        });
+    }
+    Ok(records)
+}
+
+pub fn parse(config: &Config, dat: &[u8]) -> io::Result<Vec<Record>> {
+    match config.format {
+        DataFormat::Ascii => {
+            let dat = std::str::from_utf8(dat)
+                .map_err(|_| invalid("ASCII .dat is not valid text".to_string()))?;
+            parse_ascii(config, dat)
+        }
+        _ => parse_binary(config, dat),
+    }
+}
+
+/// Encodes `records` in the data format of `config`. Missing (NaN) analog
+/// values are written as the missing marker of binary formats and as an
+/// empty field in ASCII.
+pub fn write(config: &Config, records: &[Record]) -> Vec<u8> {
+    if config.format == DataFormat::Ascii {
+        return write_ascii(records).into_bytes();
+    }
+    let mut dat = vec![0; records.len() * record_size(config)];
+    for (record, bytes) in records
+        .iter()
+        .zip(dat.chunks_exact_mut(record_size(config)))
+    {
+        LittleEndian::write_u32(&mut bytes[0..4], record.number);
+        LittleEndian::write_u32(
+            &mut bytes[4..8],
+            record.timestamp.unwrap_or(MISSING_TIMESTAMP),
+        );
+        let analog_size = config.format.analog_size();
+        for (value, bytes) in record
+            .analog
+            .iter()
+            .zip(bytes[8..].chunks_exact_mut(analog_size))
+        {
+            match config.format {
+                DataFormat::Binary if value.is_nan() => {
+                    LittleEndian::write_i16(bytes, MISSING_BINARY)
+                }
+                DataFormat::Binary => LittleEndian::write_i16(bytes, *value as i16),
+                DataFormat::Binary32 if value.is_nan() => {
+                    LittleEndian::write_i32(bytes, MISSING_BINARY32)
+                }
+                DataFormat::Binary32 => LittleEndian::write_i32(bytes, *value as i32),
+                DataFormat::Float32 => LittleEndian::write_f32(bytes, *value as f32),
+                DataFormat::Ascii => unreachable!(),
+            }
+        }
+        let words = &mut bytes[8 + record.analog.len() * analog_size..];
+        for (bit, _) in record
+            .digital
+            .iter()
+            .enumerate()
+            .filter(|(_, state)| **state)
+        {
+            words[bit / 16 * 2 + bit % 16 / 8] |= 1 << (bit % 8);
+        }
+    }
+    dat
+}
+
+fn write_ascii(records: &[Record]) -> String {
+    let mut dat = String::new();
+    for record in records {
+        let mut fields = vec![
+            record.number.to_string(),
+            record
+                .timestamp
+                .map(|time| time.to_string())
+                .unwrap_or_default(),
+        ];
+        fields.extend(record.analog.iter().map(|value| {
+            if value.is_nan() {
+                String::new()
+            } else {
+                value.to_string()
+            }
+        }));
+        fields.extend(
+            record
+                .digital
+                .iter()
+                .map(|state| (*state as u8).to_string()),
+        );
+        dat.push_str(&fields.join(","));
+        dat.push_str("\r\n");
+    }
+    dat
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: &str = "S,D,2013\n3,2A,1D\n1,IA,A,,A,0.5,0,0,-32767,32767,1,1,P\n\
+        2,VA,A,,V,1,0,0,-32767,32767,1,1,P\n1,TRIP,,,0\n50\n1\n1000,2\n\
+        01/01/2024,00:00:00.000000\n01/01/2024,00:00:00.000000\nASCII\n1\n+0,+0\n";
+
+    #[test]
+    fn parse_ascii_records() {
+        let config = Config::parse(CFG).unwrap();
+        let records = parse_ascii(&config, "1,0,10,-5,0\n2,1000,,7,1\n").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].analog, vec![10.0, -5.0]);
+        assert_eq!(records[1].timestamp, Some(1000));
+        assert!(records[1].analog[0].is_nan());
+        assert_eq!(records[1].digital, vec![true]);
+        assert!(parse_ascii(&config, "1,0,10\n").is_err());
+        let dat = write(&config, &records);
+        assert_eq!(dat, b"1,0,10,-5,0\r\n2,1000,,7,1\r\n");
+    }
+
+    #[test]
+    fn parse_binary_records() {
+        let mut config = Config::parse(CFG).unwrap();
+        for (format, values) in [
+            (DataFormat::Binary, vec![0x0a, 0x00, 0x00, 0x80]),
+            (
+                DataFormat::Binary32,
+                vec![0x0a, 0, 0, 0, 0x00, 0x00, 0x00, 0x80],
+            ),
+            (
+                DataFormat::Float32,
+                [10.0f32.to_le_bytes(), f32::NAN.to_le_bytes()].concat(),
+            ),
+        ] {
+            config.format = format;
+            let mut dat = vec![1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff];
+            dat.extend(values);
+            dat.extend([0x01, 0x00]);
+            assert_eq!(dat.len(), record_size(&config));
+
+            let records = parse(&config, &dat).unwrap();
+            assert_eq!(records[0].number, 1);
+            assert_eq!(records[0].timestamp, None);
+            assert_eq!(records[0].analog[0], 10.0);
+            assert!(records[0].analog[1].is_nan());
+            assert_eq!(records[0].digital, vec![true]);
+            assert!(parse(&config, &dat[1..]).is_err());
+            assert_eq!(write(&config, &records), dat);
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! COMTRADE (IEEE C37.111 / IEC 60255-24) transient records.
+//!
+//! Reads the 1991, 1999 and 2013 revisions, from a .cfg/.dat pair or a
+//! single 2013 .cff file, with ASCII, BINARY, BINARY32 or FLOAT32 data.
+//! Writes 2013 records, such as received SV streams (`Comtrade::from_asdus`).
+
+pub mod cfg;
+pub mod dat;
+pub mod recording;
+pub mod source;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub use cfg::{AnalogChannel, Config, DataFormat, DigitalChannel};
+pub use dat::Record;
+pub use recording::RecordingOptions;
+pub use source::{ComtradeSource, Scaling};
+
+/// A parsed record: its configuration and every sample.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Comtrade {
+    pub config: Config,
+    pub records: Vec<Record>,
+    /// Contents of the .hdr and .inf sections of a .cff file, if any.
+    pub header: Option<String>,
+    pub information: Option<String>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn text(bytes: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(bytes).map_err(|_| invalid("COMTRADE text is not UTF-8".to_string()))
+}
+
+/// Section header of a .cff file: `--- file type: DAT BINARY: 1234 ---`.
+fn section_header(line: &[u8]) -> Option<(String, Option<usize>)> {
+    let line = std::str::from_utf8(line).ok()?.trim();
+    let kind = line
+        .strip_prefix("---")?
+        .strip_suffix("---")?
+        .trim()
+        .strip_prefix("file type:")?
+        .trim();
+    match kind.split_once(':') {
+        Some((kind, size)) => Some((
+            kind.trim().to_ascii_uppercase(),
+            Some(size.trim().parse().ok()?),
+        )),
+        None => Some((kind.to_ascii_uppercase(), None)),
+    }
+}
+
+impl Comtrade {
+    /// Opens a .cff file, or the .cfg of a .cfg/.dat pair. Any other
+    /// extension is taken as the common stem of the pair.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+        if extension.as_deref() == Some("cff".as_ref()) {
+            return Comtrade::from_cff(&fs::read(path)?);
+        }
+
+        let cfg = fs::read(path.with_extension("cfg"))?;
+        let dat = match fs::read(path.with_extension("dat")) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                fs::read(path.with_extension("DAT"))?
+            }
+            dat => dat?,
+        };
+        Comtrade::from_parts(text(&cfg)?, &dat)
+    }
+
+    pub fn from_parts(cfg: &str, dat: &[u8]) -> io::Result<Self> {
+        let config = Config::parse(cfg)?;
+        let records = dat::parse(&config, dat)?;
+        Ok(Comtrade {
+            config,
+            records,
+            header: None,
+            information: None,
+        })
+    }
+
+    pub fn from_cff(cff: &[u8]) -> io::Result<Self> {
+        let mut sections: Vec<(String, &[u8])> = Vec::new();
+        let mut open: Option<(String, usize)> = None;
+        let mut position = 0;
+        while position < cff.len() {
+            let end = cff[position..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(cff.len(), |end| position + end);
+            let next = (end + 1).min(cff.len());
+            let Some((kind, size)) = section_header(&cff[position..end]) else {
+                position = next;
+                continue;
+            };
+            if let Some((kind, start)) = open.take() {
+                sections.push((kind, &cff[start..position]));
+            }
+            match size {
+                // Binary data is not line based: take exactly `size` bytes.
+                Some(size) => {
+                    let body = cff
+                        .get(next..next + size)
+                        .ok_or_else(|| invalid(format!("Truncated {} section in .cff", kind)))?;
+                    sections.push((kind, body));
+                    position = next + size;
+                }
+                None => {
+                    open = Some((kind, next));
+                    position = next;
+                }
+            }
+        }
+        if let Some((kind, start)) = open {
+            sections.push((kind, &cff[start..]));
+        }
+
+        let section = |name: &str| {
+            sections
+                .iter()
+                .find(|(kind, _)| kind.starts_with(name))
+                .map(|(_, body)| *body)
+        };
+        let cfg = section("CFG").ok_or_else(|| invalid("No CFG section in .cff".to_string()))?;
+        let dat = section("DAT").ok_or_else(|| invalid("No DAT section in .cff".to_string()))?;
+        let mut comtrade = Comtrade::from_parts(text(cfg)?, dat)?;
+        comtrade.header = section("HDR")
+            .map(|body| Ok::<_, io::Error>(text(body)?.trim_end().to_string()))
+            .transpose()?;
+        comtrade.information = section("INF")
+            .map(|body| Ok::<_, io::Error>(text(body)?.trim_end().to_string()))
+            .transpose()?;
+        Ok(comtrade)
+    }
+
+    /// Writes a .cff file when `path` ends in `.cff`, otherwise a .cfg and a
+    /// .dat file next to each other.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .map(|extension| extension.to_ascii_lowercase());
+        if extension.as_deref() == Some("cff".as_ref()) {
+            return fs::write(path, self.to_cff());
+        }
+        fs::write(
+            path.with_extension("cfg"),
+            self.config.to_cfg(self.records.len()),
+        )?;
+        fs::write(
+            path.with_extension("dat"),
+            dat::write(&self.config, &self.records),
+        )
+    }
+
+    /// Combines the configuration, header, information and data into a
+    /// single 2013 .cff file.
+    pub fn to_cff(&self) -> Vec<u8> {
+        let mut cff = format!(
+            "--- file type: CFG ---\r\n{}",
+            self.config.to_cfg(self.records.len())
+        );
+        for (kind, text) in [("INF", &self.information), ("HDR", &self.header)] {
+            if let Some(text) = text {
+                cff.push_str(&format!("--- file type: {} ---\r\n{}\r\n", kind, text));
+            }
+        }
+        let dat = dat::write(&self.config, &self.records);
+        let mut cff = match self.config.format {
+            DataFormat::Ascii => format!("{}--- file type: DAT ASCII ---\r\n", cff),
+            _ => format!("{}--- file type: DAT BINARY: {} ---\r\n", cff, dat.len()),
+        }
+        .into_bytes();
+        cff.extend(dat);
+        cff
+    }
+
+    /// Time of record `index` in seconds from the first one: from the sample
+    /// rates when the file has them, otherwise from its timestamp.
+    pub fn sample_time(&self, index: usize) -> Option<f64> {
+        self.config.sample_time(index as u64).or_else(|| {
+            let timestamp = self.records.get(index)?.timestamp?;
+            Some(timestamp as f64 * self.config.time_multiplier * 1e-6)
+        })
+    }
+
+    /// Value of analog channel `channel` in record `index`, in the units of
+    /// the channel. NaN when missing.
+    pub fn analog_value(&self, index: usize, channel: usize) -> f64 {
+        self.config.analog[channel].value(self.records[index].analog[channel])
+    }
+
+    /// Index of the analog channel named `id`.
+    pub fn analog_index(&self, id: &str) -> Option<usize> {
+        self.config
+            .analog
+            .iter()
+            .position(|channel| channel.id.eq_ignore_ascii_case(id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: &str = "SUB,MU,2013\r\n2,1A,1D\r\n1,IA,A,,A,0.5,0,0,-32767,32767,1,1,P\r\n\
+        1,TRIP,,,0\r\n50\r\n0\r\n0,2\r\n01/01/2024,00:00:00.000000\r\n\
+        01/01/2024,00:00:00.000000\r\nBINARY\r\n1\r\n+0,+0\r\n";
+
+    #[test]
+    fn read_cff_with_binary_data() {
+        let dat: Vec<u8> = [
+            [1, 0, 0, 0, 0, 0, 0, 0, 4, 0, 0, 0],
+            [2, 0, 0, 0, 250, 0, 0, 0, 0xfe, 0xff, 1, 0],
+        ]
+        .concat();
+        let mut cff = format!(
+            "--- file type: CFG ---\r\n{}--- file type: INF ---\r\n[Public]\r\n\
+             --- file type: HDR ---\r\n--- file type: DAT BINARY: {} ---\r\n",
+            CFG,
+            dat.len()
+        )
+        .into_bytes();
+        cff.extend(&dat);
+        cff.extend(b"\r\n");
+
+        let comtrade = Comtrade::from_cff(&cff).unwrap();
+        assert_eq!(comtrade.config.station_name, "SUB");
+        assert_eq!(comtrade.information.as_deref(), Some("[Public]"));
+        assert_eq!(comtrade.header.as_deref(), Some(""));
+        assert_eq!(comtrade.records.len(), 2);
+        assert_eq!(comtrade.analog_value(0, 0), 2.0);
+        assert_eq!(comtrade.analog_value(1, 0), -1.0);
+        assert_eq!(comtrade.records[1].digital, vec![true]);
+        assert_eq!(comtrade.sample_time(1), Some(250e-6));
+        assert_eq!(comtrade.analog_index("ia"), Some(0));
+        assert_eq!(Comtrade::from_cff(&comtrade.to_cff()).unwrap(), comtrade);
+    }
+
+    #[test]
+    fn open_cfg_and_ascii_dat() {
+        let dir = std::env::temp_dir().join(format!("comtrade-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("fault.cfg");
+        fs::write(&path, CFG.replace("BINARY", "ASCII")).unwrap();
+        fs::write(path.with_extension("dat"), "1,0,4,0\r\n2,250,-2,1\r\n").unwrap();
+
+        let comtrade = Comtrade::open(&path).unwrap();
+        assert_eq!(comtrade.config.format, DataFormat::Ascii);
+        assert_eq!(comtrade.analog_value(1, 0), -1.0);
+
+        let copy = dir.join("copy.cff");
+        comtrade.save(&copy).unwrap();
+        assert_eq!(Comtrade::open(&copy).unwrap().records, comtrade.records);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
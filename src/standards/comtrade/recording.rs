@@ -0,0 +1,334 @@
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::cfg::{AnalogChannel, Config, DataFormat};
+use super::{Comtrade, Record};
+use crate::protocols::sampled_values::model::{Asdu, SampleSync};
+use crate::protocols::sampled_values::waveform::Channel;
+
+/// How to turn a window of received ASDUs into a COMTRADE record.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingOptions {
+    pub station_name: String,
+    pub device_id: String,
+    pub line_frequency: f64,
+    /// SV sample rate. Defaults to the smpRate of the first ASDU.
+    pub sample_rate: Option<u32>,
+    /// Approximate reception time of the first ASDU, used to place its
+    /// smpCnt in the right second when it carries no refrTm.
+    pub received: Option<SystemTime>,
+    /// Index of the ASDU that triggered the record. Defaults to the first.
+    /// It must be one of the ASDUs kept in the record.
+    pub trigger: Option<usize>,
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self {
+        RecordingOptions {
+            station_name: String::new(),
+            device_id: String::new(),
+            line_frequency: 50.0,
+            sample_rate: None,
+            received: None,
+            trigger: None,
+        }
+    }
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decodes an IEC 61850 UtcTime: seconds, a 24-bit fraction and a quality
+/// byte.
+pub fn utc_time(refr_tm: u64) -> SystemTime {
+    let seconds = refr_tm >> 32;
+    let fraction = (refr_tm >> 8) & 0xff_ffff;
+    UNIX_EPOCH
+        + Duration::from_secs(seconds)
+        + Duration::from_nanos((fraction * 1_000_000_000) >> 24)
+}
+
+/// Formats `time` as a COMTRADE timestamp, `dd/mm/yyyy,hh:mm:ss.ssssss` in UTC.
+pub fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, seconds) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    // Civil date from days since 1970-01-01 (proleptic Gregorian calendar).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + (month <= 2) as i64;
+
+    format!(
+        "{:02}/{:02}/{:04},{:02}:{:02}:{:02}.{:06}",
+        day,
+        month,
+        year,
+        seconds / 3_600,
+        seconds / 60 % 60,
+        seconds % 60,
+        since_epoch.subsec_micros()
+    )
+}
+
+/// Time of the first ASDU: its refrTm when present, otherwise its smpCnt
+/// from the top of the second it was received in.
+fn first_sample_time(asdu: &Asdu, sample_rate: u32, received: Option<SystemTime>) -> SystemTime {
+    if let Some(refr_tm) = asdu.refr_tm {
+        return utc_time(refr_tm);
+    }
+    let offset = Duration::from_nanos(asdu.smp_count as u64 * 1_000_000_000 / sample_rate as u64);
+    let Some(received) = received else {
+        return UNIX_EPOCH + offset;
+    };
+    let since_epoch = received.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let time = UNIX_EPOCH + Duration::from_secs(since_epoch.as_secs()) + offset;
+    // A sample sent late in a second may be received in the next one.
+    if time > received + Duration::from_millis(500) {
+        time - Duration::from_secs(1)
+    } else {
+        time
+    }
+}
+
+impl Comtrade {
+    /// Records `asdus`, a window of one stream in reception order, as a 2013
+    /// BINARY32 record with one analog channel per dataset value.
+    ///
+    /// Values keep the raw 9-2LE integers, with channel multipliers of 1 mA
+    /// and 10 mV. Timing follows smpCnt: a gap in the counts switches the
+    /// record to timestamps. Repeated counts are dropped, and so are counts
+    /// more than half a second ahead, which are late ASDUs received out of
+    /// order rather than gaps.
+    pub fn from_asdus(asdus: &[Asdu], options: &RecordingOptions) -> io::Result<Self> {
+        let first = asdus
+            .first()
+            .ok_or_else(|| invalid("No ASDU to record".to_string()))?;
+        if let Some(trigger) = options.trigger
+            && trigger >= asdus.len()
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Trigger ASDU {} of {} is out of range",
+                    trigger,
+                    asdus.len()
+                ),
+            ));
+        }
+        let sample_rate = options
+            .sample_rate
+            .or(first.smp_rate.map(u32::from))
+            .filter(|rate| *rate > 0)
+            .ok_or_else(|| invalid("Unknown SV sample rate".to_string()))?;
+        let circuit = first.dataset.clone().unwrap_or_else(|| first.sv_id.clone());
+
+        let analog = Channel::ALL
+            .iter()
+            .map(|channel| AnalogChannel {
+                id: channel.dataset_member().to_string(),
+                phase: ["A", "B", "C", "N"][channel.index() % 4].to_string(),
+                circuit: circuit.clone(),
+                unit: if channel.is_current() { "A" } else { "V" }.to_string(),
+                multiplier: channel.default_scale(),
+                offset: 0.0,
+                skew: 0.0,
+                min: -(i32::MAX as f64),
+                max: i32::MAX as f64,
+                primary: 1.0,
+                secondary: 1.0,
+                is_primary: true,
+            })
+            .collect();
+
+        let mut records = Vec::with_capacity(asdus.len());
+        let mut elapsed: u64 = 0;
+        let mut trigger_sample = options.trigger.map_or(Some(0), |_| None);
+        let mut contiguous = true;
+        let mut previous = first.smp_count;
+        for (index, asdu) in asdus.iter().enumerate() {
+            if index > 0 {
+                let step =
+                    (asdu.smp_count as i64 - previous as i64).rem_euclid(sample_rate as i64) as u64;
+                if step == 0 || step > sample_rate as u64 / 2 {
+                    continue;
+                }
+                contiguous &= step == 1;
+                elapsed += step;
+                previous = asdu.smp_count;
+            }
+            if options.trigger == Some(index) {
+                trigger_sample = Some(elapsed);
+            }
+            records.push(Record {
+                number: records.len() as u32 + 1,
+                timestamp: Some((elapsed * 1_000_000 / sample_rate as u64) as u32),
+                analog: Channel::ALL
+                    .iter()
+                    .map(|channel| channel.measurement(&asdu.measures).value as f64)
+                    .collect(),
+                digital: vec![],
+            });
+        }
+
+        let trigger_sample = trigger_sample.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Trigger ASDU {} is repeated or out of order, it is not recorded",
+                    options.trigger.unwrap_or_default()
+                ),
+            )
+        })?;
+        let start = first_sample_time(first, sample_rate, options.received);
+        let trigger =
+            start + Duration::from_nanos(trigger_sample * 1_000_000_000 / sample_rate as u64);
+        let config = Config {
+            station_name: options.station_name.clone(),
+            device_id: options.device_id.clone(),
+            revision: 2013,
+            analog,
+            digital: vec![],
+            line_frequency: options.line_frequency,
+            sample_rates: if contiguous {
+                vec![(sample_rate as f64, records.len() as u64)]
+            } else {
+                vec![]
+            },
+            start: format_time(start),
+            trigger: format_time(trigger),
+            format: DataFormat::Binary32,
+            time_multiplier: 1.0,
+            time_code: "0".to_string(),
+            local_code: "0".to_string(),
+            time_quality: match first.smp_sync {
+                SampleSync::Global => "0",
+                _ => "F",
+            }
+            .to_string(),
+        };
+        Ok(Comtrade {
+            config,
+            records,
+            header: None,
+            information: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocols::sampled_values::model::Phases;
+
+    fn asdu(smp_count: u16, value: i32) -> Asdu {
+        let mut measures = Phases::default();
+        measures.current.a.value = value;
+        measures.voltage.c.value = -value;
+        Asdu {
+            sv_id: "MU01".to_string(),
+            dataset: None,
+            smp_count,
+            conf_rev: 1,
+            refr_tm: None,
+            smp_sync: SampleSync::Global,
+            smp_rate: Some(4000),
+            measures,
+            smp_mode: None,
+        }
+    }
+
+    #[test]
+    fn format_comtrade_time() {
+        let time = UNIX_EPOCH + Duration::from_micros(1_709_210_096_123_456);
+        assert_eq!(format_time(time), "29/02/2024,12:34:56.123456");
+        assert_eq!(format_time(UNIX_EPOCH), "01/01/1970,00:00:00.000000");
+    }
+
+    #[test]
+    fn utc_time_fraction() {
+        let refr_tm = (1_000u64 << 32) | (0x80_0000 << 8) | 0x0a;
+        assert_eq!(
+            utc_time(refr_tm),
+            UNIX_EPOCH + Duration::from_millis(1_000_500)
+        );
+    }
+
+    #[test]
+    fn record_contiguous_window() {
+        let asdus = vec![asdu(3998, 1), asdu(3999, 2), asdu(0, 3), asdu(1, 4)];
+        let received = UNIX_EPOCH + Duration::from_millis(10_000_200);
+        let options = RecordingOptions {
+            station_name: "SUB".to_string(),
+            received: Some(received),
+            trigger: Some(2),
+            ..RecordingOptions::default()
+        };
+        let comtrade = Comtrade::from_asdus(&asdus, &options).unwrap();
+        let config = &comtrade.config;
+        assert_eq!(config.analog[0].id, "IATCTR1.Amp");
+        assert_eq!(config.analog[0].circuit, "MU01");
+        assert_eq!(config.sample_rates, vec![(4000.0, 4)]);
+        assert_eq!(config.start, "01/01/1970,02:46:39.999500");
+        assert_eq!(config.trigger, "01/01/1970,02:46:40.000000");
+        assert_eq!(config.time_quality, "0");
+        assert_eq!(comtrade.records[2].timestamp, Some(500));
+        assert_eq!(comtrade.analog_value(3, 0), 0.004);
+        assert_eq!(comtrade.analog_value(3, 6), -0.04);
+
+        let copy = Comtrade::from_cff(&comtrade.to_cff()).unwrap();
+        assert_eq!(copy, comtrade);
+    }
+
+    #[test]
+    fn record_with_gap_and_refr_tm() {
+        let mut asdus = vec![asdu(10, 1), asdu(10, 1), asdu(11, 2), asdu(14, 3)];
+        asdus[0].refr_tm = Some(1_000 << 32);
+        let comtrade = Comtrade::from_asdus(&asdus, &RecordingOptions::default()).unwrap();
+        assert_eq!(comtrade.records.len(), 3);
+        assert!(comtrade.config.sample_rates.is_empty());
+        assert_eq!(comtrade.config.start, "01/01/1970,00:16:40.000000");
+        assert_eq!(comtrade.sample_time(2), Some(0.001));
+        assert!(Comtrade::from_asdus(&[], &RecordingOptions::default()).is_err());
+    }
+
+    #[test]
+    fn late_asdu_is_dropped_not_a_gap() {
+        let asdus = vec![
+            asdu(10, 1),
+            asdu(11, 2),
+            asdu(13, 4),
+            asdu(12, 3),
+            asdu(14, 5),
+        ];
+        let options = RecordingOptions {
+            trigger: Some(4),
+            ..RecordingOptions::default()
+        };
+        let comtrade = Comtrade::from_asdus(&asdus, &options).unwrap();
+        assert_eq!(comtrade.records.len(), 4);
+        assert_eq!(comtrade.records[3].timestamp, Some(1_000));
+        assert_eq!(comtrade.config.trigger, "01/01/1970,00:00:00.003500");
+
+        for trigger in [3, 5] {
+            let options = RecordingOptions {
+                trigger: Some(trigger),
+                ..RecordingOptions::default()
+            };
+            let err = Comtrade::from_asdus(&asdus, &options).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        }
+    }
+}
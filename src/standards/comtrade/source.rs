@@ -0,0 +1,263 @@
+use std::io;
+
+use super::Comtrade;
+use crate::protocols::sampled_values::model::Phases;
+use crate::protocols::sampled_values::scaling::DatasetScale;
+use crate::protocols::sampled_values::waveform::{Channel, WaveformSource};
+
+/// Side of the instrument transformers the published values are on.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scaling {
+    /// Primary values, as a merging unit publishes them.
+    #[default]
+    Primary,
+    /// Secondary values, as seen by a relay behind the CTs and VTs.
+    Secondary,
+}
+
+/// Plays analog channels of a COMTRADE record into the SV dataset,
+/// linearly interpolated to the publisher sample rate.
+///
+/// Unmapped phases publish 0 and an unmapped neutral the sum of its phases.
+/// Past the end of the record, samples are 0 unless `repeat` is set.
+#[derive(Clone, Debug)]
+pub struct ComtradeSource {
+    comtrade: Comtrade,
+    times: Vec<f64>,
+    mapping: [Option<usize>; 8],
+    gains: [f64; 8],
+    pub scaling: Scaling,
+    pub repeat: bool,
+    /// Encoding of the published values, 9-2LE by default.
+    pub scale: DatasetScale,
+    cursor: usize,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Factor from `unit` to A or V, from its SI prefix.
+fn unit_factor(unit: &str) -> f64 {
+    let mut chars = unit.chars();
+    match (chars.next(), chars.as_str()) {
+        (Some('k'), "A" | "V") => 1e3,
+        (Some('M'), "A" | "V") => 1e6,
+        (Some('m'), "A" | "V") => 1e-3,
+        _ => 1.0,
+    }
+}
+
+impl ComtradeSource {
+    pub fn new(comtrade: Comtrade) -> io::Result<Self> {
+        if comtrade.records.is_empty() {
+            return Err(invalid("COMTRADE record has no samples".to_string()));
+        }
+        let times = (0..comtrade.records.len())
+            .map(|index| comtrade.sample_time(index))
+            .collect::<Option<Vec<f64>>>()
+            .ok_or_else(|| invalid("COMTRADE record has no sample timing".to_string()))?;
+        if times.windows(2).any(|pair| pair[1] <= pair[0]) {
+            return Err(invalid("COMTRADE sample times do not increase".to_string()));
+        }
+        Ok(ComtradeSource {
+            comtrade,
+            times,
+            mapping: [None; 8],
+            gains: [1.0; 8],
+            scaling: Scaling::default(),
+            repeat: false,
+            scale: DatasetScale::default(),
+            cursor: 0,
+        })
+    }
+
+    pub fn comtrade(&self) -> &Comtrade {
+        &self.comtrade
+    }
+
+    /// Publishes analog channel `analog` on `channel`, or nothing for `None`.
+    pub fn map(&mut self, channel: Channel, analog: Option<usize>) -> io::Result<()> {
+        if let Some(analog) = analog
+            && analog >= self.comtrade.config.analog.len()
+        {
+            return Err(invalid(format!("No analog channel {} to map", analog)));
+        }
+        self.mapping[channel.index()] = analog;
+        Ok(())
+    }
+
+    /// Publishes the analog channel named `id` on `channel`.
+    pub fn map_id(&mut self, channel: Channel, id: &str) -> io::Result<()> {
+        let analog = self
+            .comtrade
+            .analog_index(id)
+            .ok_or_else(|| invalid(format!("No analog channel named {:?}", id)))?;
+        self.map(channel, Some(analog))
+    }
+
+    /// Maps the first current and voltage channel of each phase, from the
+    /// phase identifier (`A`, `B`, `C`, `N`) and the unit of the channels.
+    /// Returns how many dataset channels were mapped.
+    pub fn auto_map(&mut self) -> usize {
+        let mut mapped = 0;
+        for channel in Channel::ALL {
+            let phase = ["A", "B", "C", "N"][channel.index() % 4];
+            let unit = if channel.is_current() { 'A' } else { 'V' };
+            let analog = self.comtrade.config.analog.iter().position(|analog| {
+                analog.phase.eq_ignore_ascii_case(phase) && analog.unit.ends_with(unit)
+            });
+            if analog.is_some() {
+                mapped += 1;
+            }
+            self.mapping[channel.index()] = analog;
+        }
+        mapped
+    }
+
+    /// Extra factor applied to `channel` after unit and ratio conversion.
+    pub fn set_gain(&mut self, channel: Channel, gain: f64) {
+        self.gains[channel.index()] = gain;
+    }
+
+    /// Duration of the record in seconds, one sample past the last one.
+    pub fn duration(&self) -> f64 {
+        let last = self.times[self.times.len() - 1];
+        match self.times.len() {
+            1 => last,
+            len => last + (last - self.times[len - 2]),
+        }
+    }
+
+    /// Value of analog channel `analog` in record `index`, in A or V on the
+    /// side given by `scaling`.
+    fn value(&self, analog: usize, index: usize) -> f64 {
+        let channel = &self.comtrade.config.analog[analog];
+        let ratio = match self.scaling {
+            Scaling::Primary => channel.to_primary(),
+            Scaling::Secondary => channel.to_secondary(),
+        };
+        let value = self.comtrade.analog_value(index, analog) * unit_factor(&channel.unit) * ratio;
+        if value.is_nan() { 0.0 } else { value }
+    }
+
+    /// Record before `time` and the interpolation weight of the next one.
+    fn locate(&mut self, time: f64) -> Option<(usize, f64)> {
+        if time < self.times[0] {
+            return None;
+        }
+        if time < self.times[self.cursor] {
+            self.cursor = 0;
+        }
+        // Playback moves forward, so scan on from the last position.
+        while self.cursor + 1 < self.times.len() && self.times[self.cursor + 1] <= time {
+            self.cursor += 1;
+        }
+        let index = self.cursor;
+        match self.times.get(index + 1) {
+            Some(next) => Some((
+                index,
+                (time - self.times[index]) / (next - self.times[index]),
+            )),
+            None if time < self.duration() => Some((index, 0.0)),
+            None => None,
+        }
+    }
+
+    /// Value of `channel` at `time` seconds into the record, in A or V.
+    pub fn value_at(&mut self, channel: Channel, time: f64) -> f64 {
+        let time = if self.repeat {
+            time % self.duration()
+        } else {
+            time
+        };
+        let Some((index, weight)) = self.locate(time) else {
+            return 0.0;
+        };
+        let interpolate = |source: &Self, analog: usize| {
+            let value = source.value(analog, index);
+            if weight == 0.0 {
+                value
+            } else {
+                value + (source.value(analog, index + 1) - value) * weight
+            }
+        };
+        let gain = self.gains[channel.index()];
+        match self.mapping[channel.index()] {
+            Some(analog) => interpolate(self, analog) * gain,
+            None if channel.is_neutral() => {
+                Channel::phases_of(channel)
+                    .iter()
+                    .filter_map(|phase| self.mapping[phase.index()].map(|analog| (phase, analog)))
+                    .map(|(phase, analog)| interpolate(self, analog) * self.gains[phase.index()])
+                    .sum::<f64>()
+                    * gain
+            }
+            None => 0.0,
+        }
+    }
+}
+
+impl WaveformSource for ComtradeSource {
+    fn sample(&mut self, index: u64, sample_rate: u32, measures: &mut Phases) {
+        let time = index as f64 / sample_rate as f64;
+        for channel in Channel::ALL {
+            let value = self.value_at(channel, time);
+            self.scale.set_value(channel, measures, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: &str = "SUB,MU,2013\n3,3A,0D\n1,IA,A,,A,1,0,0,-32767,32767,400,1,S\n\
+        2,IB,B,,A,1,0,0,-32767,32767,400,1,S\n3,VA,A,,kV,1,0,0,-32767,32767,100,0.1,P\n\
+        50\n1\n1000,4\n01/01/2024,00:00:00.000000\n01/01/2024,00:00:00.000000\nASCII\n1\n";
+
+    fn source() -> ComtradeSource {
+        let dat = "1,0,0,1,10\n2,1000,2,1,20\n3,2000,4,1,30\n4,3000,6,1,40\n";
+        ComtradeSource::new(Comtrade::from_parts(CFG, dat.as_bytes()).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn resample_and_scale() {
+        let mut source = source();
+        assert_eq!(source.auto_map(), 3);
+        let mut measures = Phases::default();
+
+        // 0.5 ms is halfway between the first two 1 kHz samples.
+        source.sample(2, 4000, &mut measures);
+        assert_eq!(measures.current.a.value, 400_000);
+        assert_eq!(measures.current.b.value, 400_000);
+        assert_eq!(measures.current.n.value, 800_000);
+        assert_eq!(measures.voltage.a.value, 1_500_000);
+        assert_eq!(measures.voltage.b.value, 0);
+
+        source.scaling = Scaling::Secondary;
+        source.set_gain(Channel::Va, 2.0);
+        source.sample(4, 4000, &mut measures);
+        assert_eq!(measures.current.a.value, 2_000);
+        assert_eq!(measures.voltage.a.value, 4_000);
+
+        // The record lasts 4 ms.
+        source.sample(16, 4000, &mut measures);
+        assert_eq!(measures.current.a.value, 0);
+        source.repeat = true;
+        source.sample(16, 4000, &mut measures);
+        assert_eq!(measures.current.a.value, 0);
+        source.sample(18, 4000, &mut measures);
+        assert_eq!(measures.current.a.value, 1_000);
+    }
+
+    #[test]
+    fn map_by_name() {
+        let mut source = source();
+        source.map_id(Channel::Ic, "ib").unwrap();
+        assert!(source.map_id(Channel::Ia, "IX").is_err());
+        assert!(source.map(Channel::Ia, Some(3)).is_err());
+        assert_eq!(source.value_at(Channel::Ic, 0.0), 400.0);
+        assert_eq!(source.value_at(Channel::In, 0.0), 400.0);
+    }
+}
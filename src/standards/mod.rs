@@ -1 +1,2 @@
 pub mod asn1;
+pub mod comtrade;